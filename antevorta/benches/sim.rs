@@ -0,0 +1,118 @@
+use alator::clock::{Clock, ClockBuilder};
+use alator::exchange::DefaultExchangeBuilder;
+use alator::sim::SimulatedBrokerBuilder;
+use alator::types::{Frequency, PortfolioAllocation};
+use antevorta::config::uk::UKSimConfig;
+use antevorta::input::{build_hashmapsource_random, SimDataSource, VecSourceSim};
+use antevorta::schedule::Schedule;
+use antevorta::strat::StaticInvestmentStrategy;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::rc::Rc;
+
+const DAYS: i64 = 365 * 20;
+
+const CONFIG: &str = r#"{
+    "starting_cash": 10000.0,
+    "nic": "A",
+    "contribution_pct": 0.05,
+    "emergency_cash_min": 5000.0,
+    "lifetime_pension_contributions": 0.0,
+    "flows": [
+        {
+            "flow_type": "EmploymentPAYE",
+            "value": 4000.0,
+            "schedule": {
+                "schedule_type": "StartOfMonth"
+            }
+        },
+        {
+            "flow_type": "InflationLinkedExpense",
+            "value": 2000.0,
+            "schedule": {
+                "schedule_type": "EndOfMonth"
+            }
+        }
+    ],
+    "stacks": [
+        {
+            "stack_type": "Gia",
+            "value": 20000.0
+        },
+        {
+            "stack_type": "Isa",
+            "value": 20000.0
+        },
+        {
+            "stack_type": "Sipp",
+            "value": 20000.0
+        }
+    ]
+}"#;
+
+fn strat<D: SimDataSource>(clock: Clock, src: D) -> StaticInvestmentStrategy<D> {
+    let mut weights = PortfolioAllocation::new();
+    weights.insert("ABC", 0.5);
+    weights.insert("BCD", 0.5);
+
+    let exchange = DefaultExchangeBuilder::new()
+        .with_clock(Rc::clone(&clock))
+        .with_data_source(src.clone())
+        .build();
+    let brkr = SimulatedBrokerBuilder::new()
+        .with_exchange(exchange)
+        .with_data(src)
+        .build();
+    StaticInvestmentStrategy::new(brkr, Schedule::EveryFriday, weights, clock)
+}
+
+fn clock() -> Clock {
+    ClockBuilder::with_length_in_days(0, DAYS)
+        .with_frequency(&Frequency::Daily)
+        .build()
+}
+
+//Whole simulation including the broker and flows, sources are built outside of the timed run
+fn bench_sim(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sim");
+    group.sample_size(10);
+    group.bench_function("hashmap", |b| {
+        b.iter_batched(
+            || {
+                let clock = clock();
+                let src = build_hashmapsource_random(Rc::clone(&clock));
+                let strat = strat(Rc::clone(&clock), src.clone());
+                let config = UKSimConfig::parse(CONFIG).unwrap();
+                (Rc::clone(&clock), config.create(clock, strat, src))
+            },
+            |(clock, mut sim)| {
+                while clock.borrow().has_next() {
+                    clock.borrow_mut().tick();
+                    sim.update();
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("vec", |b| {
+        b.iter_batched(
+            || {
+                let clock = clock();
+                let src = VecSourceSim::from(&build_hashmapsource_random(Rc::clone(&clock)));
+                let strat = strat(Rc::clone(&clock), src.clone());
+                let config = UKSimConfig::parse(CONFIG).unwrap();
+                (Rc::clone(&clock), config.create(clock, strat, src))
+            },
+            |(clock, mut sim)| {
+                while clock.borrow().has_next() {
+                    clock.borrow_mut().tick();
+                    sim.update();
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_sim);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+use alator::clock::{Clock, ClockBuilder};
+use alator::input::DataSource;
+use alator::types::Frequency;
+use antevorta::input::{build_hashmapsource_random, HashMapSourceSim, SimDataSource, VecSourceSim};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use std::rc::Rc;
+
+const DAYS: i64 = 365 * 40;
+
+fn setup() -> (Clock, HashMapSourceSim, VecSourceSim) {
+    let clock = ClockBuilder::with_length_in_days(0, DAYS)
+        .with_frequency(&Frequency::Daily)
+        .build();
+    let hashmap = build_hashmapsource_random(Rc::clone(&clock));
+    let vec = VecSourceSim::from(&hashmap);
+    (clock, hashmap, vec)
+}
+
+//Queries made by the simulation on each day, trailing inflation is only used monthly
+fn run<S: SimDataSource>(clock: Clock, source: S) -> f64 {
+    let mut res = 0.0;
+    let mut day = 0;
+    while clock.borrow().has_next() {
+        res += *source.get_quote("ABC").unwrap().bid;
+        res += *source.get_quote("BCD").unwrap().bid;
+        res += source.get_current_inflation().unwrap();
+        res += source.get_current_interest_rate().unwrap();
+        if day % 30 == 0 {
+            res += source.get_trailing_month_inflation();
+        }
+        day += 1;
+        clock.borrow_mut().tick();
+    }
+    res
+}
+
+//The HashMap source iterates over the whole clock for trailing inflation so single queries are
+//made halfway through
+fn bench_queries(c: &mut Criterion) {
+    let (clock, hashmap, vec) = setup();
+    for _i in 0..DAYS / 2 {
+        clock.borrow_mut().tick();
+    }
+
+    c.bench_function("hashmap_get_quote", |b| b.iter(|| hashmap.get_quote(black_box("BCD"))));
+    c.bench_function("vec_get_quote", |b| b.iter(|| vec.get_quote(black_box("BCD"))));
+    c.bench_function("hashmap_trailing_year_inflation", |b| {
+        b.iter(|| hashmap.get_trailing_year_inflation())
+    });
+    c.bench_function("vec_trailing_year_inflation", |b| {
+        b.iter(|| vec.get_trailing_year_inflation())
+    });
+}
+
+fn bench_full_run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full_run");
+    group.sample_size(10);
+    group.bench_function("hashmap", |b| {
+        b.iter_batched(
+            || {
+                let (clock, hashmap, _vec) = setup();
+                (clock, hashmap)
+            },
+            |(clock, source)| run(clock, source),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("vec", |b| {
+        b.iter_batched(
+            || {
+                let (clock, _hashmap, vec) = setup();
+                (clock, vec)
+            },
+            |(clock, source)| run(clock, source),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_queries, bench_full_run);
+criterion_main!(benches);
@@ -1,35 +1,53 @@
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_json::Error;
 use std::rc::Rc;
 use alator::clock::Clock;
-use alator::types::CashValue;
+use alator::types::{CashValue, DateTime};
 
-use crate::flow::{Employment, EmploymentPAYE, Expense, PctOfIncomeExpense, Rental, Flow};
-use crate::stack::{CanTransfer, Isa, Sipp, Stack, BankAcc, Mortgage, Gia};
+use crate::flow::{
+    AnnuityAmount, AnnuityIndexation, AnnuityPurchase, Child, ChildBenefit, DefinedBenefit, Employment, EmploymentPAYE, EmployerContribution, Expense,
+    PctOfIncomeExpense, PensionScheme, Rental, Flow,
+};
+use crate::stack::{
+    CanTransfer, Isa, Jisa, Lisa, ReliefMethod, Sipp, Stack, StudentLoan, StudentLoanPlan,
+    BankAcc, Mortgage, Gia, Property,
+};
 use crate::sim::uk::{UKSimulationState, SimState};
-use crate::tax::uk::{NIC, UKTaxConfig};
+use crate::tax::uk::{
+    EstateConfig, FiscalDrag, InheritanceTaxConfig, Residency, NIC, UKTaxTables,
+};
 use crate::strat::InvestmentStrategy;
-use crate::input::{HashMapSourceSim, SimDataSource};
-use crate::schedule::Schedule;
+use crate::input::SimDataSource;
+use crate::mortality::{LifeTable, LifeTableBasis, Sex};
+use crate::schedule::{tax_year, Schedule, Step};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UKSimConfig {
     starting_cash: f64,
     emergency_cash_min: f64,
     nic: NIC,
+    residency: Option<Residency>, //Defaults to RestOfUK
+    fiscal_drag: Option<FiscalDragUKSimConfig>, //Defaults to thresholds frozen until 2028
+    estate: Option<EstateUKSimConfig>, //Defaults to no residence or transferred bands
+    mortality: Option<MortalityUKSimConfig>, //Samples date of death if estate has no date
+    annuity: Option<AnnuityUKSimConfig>, //Requires mortality for pricing
     lifetime_pension_contributions: f64,
     contribution_pct: f64,
+    bed_and_isa: Option<bool>,
+    bed_and_sipp: Option<f64>, //Maximum net amount moved from GIA into SIPP each year
     flows: Option<Vec<FlowUKSimConfig>>,
     stacks: Option<Vec<StackUKSimConfig>>,
+    step: Option<Step>, //Defaults to Daily, the clock and source must have the same step
 }
 
 impl UKSimConfig {
-    pub fn create<S: InvestmentStrategy>(
+    pub fn create<S: InvestmentStrategy, D: SimDataSource>(
         &self,
         clock: Clock,
         strat: S,
-        src: HashMapSourceSim,
-    ) -> UKSimulationState<S> {
+        src: D,
+    ) -> UKSimulationState<S, D> {
         //TODO: the bank account should be given as part of the config and initialised rather than
         //have the input as a special value
         let mut bank = BankAcc::new();
@@ -38,17 +56,32 @@ impl UKSimConfig {
         let mut gia: Option<Gia<S>> = None;
         let mut sipp: Option<Sipp<S>> = None;
         let mut isa: Option<Isa<S>> = None;
+        let mut lisa: Option<Lisa<S>> = None;
+        let mut jisa: Option<Jisa<S>> = None;
+        let mut jisa_contribution = 0.0;
+        let mut student_loans: Vec<StudentLoan<D>> = Vec::new();
+        let mut properties: Vec<Property<D>> = Vec::new();
+        let mut mortgages: Vec<Mortgage<D>> = Vec::new();
+        let step = self.step();
 
         //This loop is just used to initialise the accounts
         if let Some(stacks) = &self.stacks {
             for stack in stacks {
                 let into_cash = self.lifetime_pension_contributions.into();
                 let into_internal =
-                    stack.build(Rc::clone(&clock), src.clone(), strat.clone(), into_cash);
+                    stack.build(Rc::clone(&clock), src.clone(), strat.clone(), into_cash, &step);
                 match into_internal {
                     Stack::Isa(val) => isa = Some(val),
+                    Stack::Lisa(val) => lisa = Some(val),
+                    Stack::Jisa(val) => {
+                        jisa = Some(val);
+                        jisa_contribution = stack.contribution.unwrap_or(0.0);
+                    }
                     Stack::Sipp(val) => sipp = Some(val),
                     Stack::Gia(val) => gia = Some(val),
+                    Stack::StudentLoan(val) => student_loans.push(val),
+                    Stack::Property(val) => properties.push(val),
+                    Stack::Mortgage(val) => mortgages.push(val),
                     _ => (),
                 }
             }
@@ -61,21 +94,21 @@ impl UKSimConfig {
         }
 
         //Have to make sure that expenses are ordered after income
-        let mut non_expense_flows: Vec<Flow> = self
+        let mut non_expense_flows: Vec<Flow<D>> = self
             .flows
             .as_ref()
             .unwrap()
             .iter()
-            .map(|f| f.build(&src))
+            .map(|f| f.build(&src, &step))
             .filter(|f| f.is_expense())
             .collect();
 
-        let mut expense_flows: Vec<Flow> = self
+        let mut expense_flows: Vec<Flow<D>> = self
             .flows
             .as_ref()
             .unwrap()
             .iter()
-            .map(|f| f.build(&src))
+            .map(|f| f.build(&src, &step))
             .filter(|f| !f.is_expense())
             .collect();
 
@@ -83,10 +116,38 @@ impl UKSimConfig {
         flows.append(&mut expense_flows);
         flows.append(&mut non_expense_flows);
 
+        //Tax config is picked from the table for the tax year that the simulation starts in
+        let fiscal_drag = match &self.fiscal_drag {
+            Some(f) => f.into(),
+            None => FiscalDrag::default(),
+        };
+        let tax_tables =
+            UKTaxTables::new(self.residency.unwrap_or(Residency::RestOfUK), fiscal_drag);
+        let start_date = clock.borrow().now();
+        let tax_config = tax_tables.config(&tax_year(&start_date));
+
+        let annuity_purchase = self.annuity.as_ref().map(|a| {
+            let mortality = self
+                .mortality
+                .as_ref()
+                .expect("Annuity purchase requires mortality to be set");
+            a.build(mortality)
+        });
+
+        let (date_of_death, estate_config) = match &self.estate {
+            Some(e) => (e.date_of_death.map(DateTime::from), e.into()),
+            None => (None, EstateConfig::default()),
+        };
+
         UKSimulationState {
             nic_group: self.nic,
-            annual_tax_schedule: Schedule::EveryYear(1, 4),
-            perf_schedule: Schedule::StartOfMonth,
+            annual_tax_schedule: Schedule::EveryYear(1, 4).for_step(&step),
+            perf_schedule: Schedule::StartOfMonth.for_step(&step),
+            tax_year_start_schedule: Schedule::EveryYear(2, 4).for_step(&step),
+            last_tax_date: start_date.clone(),
+            step,
+            bed_and_isa: self.bed_and_isa.unwrap_or(false),
+            bed_and_sipp: self.bed_and_sipp,
             clock: Rc::clone(&clock),
             contribution_pct: self.contribution_pct,
             emergency_fund_minimum: self.emergency_cash_min,
@@ -96,20 +157,42 @@ impl UKSimConfig {
             gia: gia.unwrap(),
             sipp: sipp.unwrap(),
             isa: isa.unwrap(),
-            tax_config: UKTaxConfig::default(),
+            lisa,
+            jisa,
+            child_isa: None,
+            jisa_contribution,
+            annuity_purchase,
+            student_loans,
+            properties,
+            mortgages,
+            tax_config,
+            tax_tables,
             sim_state: SimState::Ready,
+            date_of_death,
+            estate_config,
+            iht_config: InheritanceTaxConfig::default(),
+            estate: None,
+            ruin_date: None,
             income_paid_in_curr_loop: 0.0.into(),
             expense_annual: 0.0.into(),
             gross_income_annual: 0.0.into(),
             net_income_annual: 0.0.into(),
             tax_paid_annual: 0.0.into(),
             tax_paid_paye_annual: 0.0.into(),
+            ni_paid_paye_annual: 0.0.into(),
+            student_loan_paid_paye_annual: 0.0.into(),
+            child_benefit_annual: 0.0.into(),
             sipp_contributions_annual: 0.0.into(),
+            relief_at_source_contributions_annual: 0.0.into(),
+            employer_contributions_annual: 0.0.into(),
+            defined_benefit_input_annual: 0.0.into(),
             non_paye_income_annual: 0.0.into(),
             paye_income_annual: 0.0.into(),
+            pension_income_annual: 0.0.into(),
             rental_income_annual: 0.0.into(),
             savings_income_annual: 0.0.into(),
             self_employment_income_annual: 0.0.into(),
+            residential_capital_gains_annual: 0.0.into(),
             paid_into_gia_since_start: 0.0.into(),
             paid_into_isa_since_start: 0.0.into(),
             paid_into_sipp_since_start: 0.0.into(),
@@ -117,14 +200,38 @@ impl UKSimConfig {
             sipp_snapshot: Vec::new(),
             gia_snapshot: Vec::new(),
             cash: Vec::new(),
+            jisa_value: Vec::new(),
+            property_value: Vec::new(),
             gross_income: Vec::new(),
             net_income: Vec::new(),
             expense: Vec::new(),
             tax_paid: Vec::new(),
             sipp_contributions: Vec::new(),
+            employer_contributions: Vec::new(),
+            student_loan_repaid: Vec::new(),
+            student_loan_balance: Vec::new(),
+            tax_breakdown: Vec::new(),
         }
     }
 
+    //Fixed date of death takes precedence over the mortality model, returns None if neither is set
+    pub fn sample_date_of_death(&self, start_date: &i64, rng: &mut impl Rng) -> Option<i64> {
+        if let Some(date_of_death) = self.estate.as_ref().and_then(|e| e.date_of_death) {
+            return Some(date_of_death);
+        }
+        self.mortality.as_ref().map(|m| {
+            LifeTable::new(m.sex, m.basis.unwrap_or(LifeTableBasis::Cohort)).sample_date_of_death(
+                &m.date_of_birth,
+                start_date,
+                rng,
+            )
+        })
+    }
+
+    pub fn step(&self) -> Step {
+        self.step.unwrap_or(Step::Daily)
+    }
+
     pub fn parse(json_str: &str) -> Result<UKSimConfig, Error> {
         serde_json::from_str(json_str)
     }
@@ -174,6 +281,129 @@ enum SupportedFlowTypes {
     Expense,
     PctOfIncomeExpense,
     InflationLinkedExpense,
+    ChildBenefit,
+    DefinedBenefit,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct ChildUKSimConfig {
+    date_of_birth: i64,
+    end_age: Option<i32>, //Defaults to 16, up to 20 if in approved education
+}
+
+impl From<&ChildUKSimConfig> for Child {
+    fn from(c: &ChildUKSimConfig) -> Self {
+        Child::new(c.date_of_birth.into(), c.end_age.unwrap_or(16))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+enum SupportedEmployerContributionTypes {
+    Flat,
+    Matched,
+    Tiered,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+enum SupportedFiscalDragTypes {
+    FreezeUntil,
+    Index,
+    Custom,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct FiscalDragUKSimConfig {
+    policy_type: SupportedFiscalDragTypes,
+    until: Option<i32>,       //Only for FreezeUntil, tax year that indexing starts
+    rates: Option<Vec<f64>>,  //Only for Custom, annual uprating after the last tax table
+}
+
+impl From<&FiscalDragUKSimConfig> for FiscalDrag {
+    fn from(c: &FiscalDragUKSimConfig) -> Self {
+        match c.policy_type {
+            SupportedFiscalDragTypes::FreezeUntil => FiscalDrag::FreezeUntil(c.until.unwrap()),
+            SupportedFiscalDragTypes::Index => FiscalDrag::Index,
+            SupportedFiscalDragTypes::Custom => FiscalDrag::Custom(c.rates.clone().unwrap()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct MortalityUKSimConfig {
+    date_of_birth: i64,
+    sex: Sex,
+    basis: Option<LifeTableBasis>, //Defaults to Cohort
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct AnnuityUKSimConfig {
+    age: i32,
+    amount: Option<f64>, //Either amount or pct of SIPP must be given
+    pct: Option<f64>,
+    indexation: Option<AnnuityIndexation>, //Defaults to Level
+}
+
+impl AnnuityUKSimConfig {
+    fn build(&self, mortality: &MortalityUKSimConfig) -> AnnuityPurchase {
+        let seconds_in_year = 365.25 * 86_400.0;
+        let date = mortality.date_of_birth + (self.age as f64 * seconds_in_year) as i64;
+        let amount = match self.amount {
+            Some(amount) => AnnuityAmount::Fixed(amount),
+            None => AnnuityAmount::PctOfSipp(self.pct.unwrap()),
+        };
+        AnnuityPurchase::new(
+            date.into(),
+            mortality.date_of_birth,
+            amount,
+            self.indexation.unwrap_or(AnnuityIndexation::Level),
+            LifeTable::new(mortality.sex, mortality.basis.unwrap_or(LifeTableBasis::Cohort)),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct EstateUKSimConfig {
+    date_of_death: Option<i64>,                      //Defaults to end of simulation
+    residence: Option<f64>,                          //Main residence passed to direct descendants
+    include_pensions: Option<bool>,                  //Defaults to included for deaths from April 2027
+    transferred_nil_rate_band: Option<f64>,          //Proportion unused by spouse
+    transferred_residence_nil_rate_band: Option<f64>, //Proportion unused by spouse
+}
+
+impl From<&EstateUKSimConfig> for EstateConfig {
+    fn from(c: &EstateUKSimConfig) -> Self {
+        EstateConfig {
+            residence: c.residence.unwrap_or(0.0),
+            include_pensions: c.include_pensions,
+            transferred_nil_rate_band: c.transferred_nil_rate_band.unwrap_or(0.0),
+            transferred_residence_nil_rate_band: c
+                .transferred_residence_nil_rate_band
+                .unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct EmployerContributionUKSimConfig {
+    contribution_type: SupportedEmployerContributionTypes,
+    pct: Option<f64>,                 //Only for Flat
+    match_rate: Option<f64>,          //Only for Matched
+    cap: Option<f64>,                 //Only for Matched
+    tiers: Option<Vec<(f64, f64)>>,   //Only for Tiered, (employee pct, employer pct)
+}
+
+impl From<&EmployerContributionUKSimConfig> for EmployerContribution {
+    fn from(c: &EmployerContributionUKSimConfig) -> Self {
+        match c.contribution_type {
+            SupportedEmployerContributionTypes::Flat => EmployerContribution::Flat(c.pct.unwrap()),
+            SupportedEmployerContributionTypes::Matched => {
+                EmployerContribution::Matched(c.match_rate.unwrap(), c.cap.unwrap())
+            }
+            SupportedEmployerContributionTypes::Tiered => {
+                EmployerContribution::Tiered(c.tiers.clone().unwrap())
+            }
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -182,24 +412,67 @@ struct FlowUKSimConfig {
     person: Option<u8>,
     value: Option<f64>,         //PctOfIncomeExpense has no value
     schedule: ScheduleUKSimConfig,   //All flows need schedule
+    end_date: Option<i64>,      //Flow stops after this date, retirement for employment types
     static_growth: Option<f64>, //Only for growth types
     pct: Option<f64>,           //Only for pct types
+    employer_contribution: Option<EmployerContributionUKSimConfig>, //Only for employment types
+    salary_sacrifice: Option<bool>,   //Only for employment types
+    pass_on_ni_saving: Option<bool>,  //Only for employment types with salary sacrifice
+    children: Option<Vec<ChildUKSimConfig>>, //Only for ChildBenefit
+    property: Option<usize>,          //Only for Rental, position among the Property stacks
+    accrued: Option<f64>,             //Only for DefinedBenefit, annual pension at start
+    accrual_rate: Option<f64>,        //Only for DefinedBenefit
+    normal_pension_date: Option<i64>, //Only for DefinedBenefit
+    retirement_date: Option<i64>,     //Only for DefinedBenefit, defaults to normal pension date
+    early_retirement_factor: Option<f64>, //Only for DefinedBenefit, reduction per year early
+    late_retirement_factor: Option<f64>,  //Only for DefinedBenefit, increase per year late
+    commutation_pct: Option<f64>,     //Only for DefinedBenefit, pension given up for lump sum
+    commutation_factor: Option<f64>,  //Only for DefinedBenefit, defaults to 12
+    revaluation_cap: Option<f64>,     //Only for DefinedBenefit, defaults to 0.05
 }
 
 impl FlowUKSimConfig {
-    fn build(&self, src: &HashMapSourceSim) -> Flow {
+    fn pension(&self) -> PensionScheme {
+        let employer = match &self.employer_contribution {
+            Some(c) => c.into(),
+            None => EmployerContribution::None,
+        };
+        PensionScheme::new(
+            employer,
+            self.salary_sacrifice.unwrap_or(false),
+            self.pass_on_ni_saving.unwrap_or(false),
+        )
+    }
+
+    fn schedule(&self, step: &Step) -> Schedule {
+        match self.end_date {
+            Some(end_date) => Schedule::Until(Box::new(self.schedule.into()), end_date.into()),
+            None => Schedule::from(self.schedule),
+        }
+        .for_step(step)
+    }
+
+    fn build<D: SimDataSource>(&self, src: &D, step: &Step) -> Flow<D> {
         //This used to have more branching, this made no sense because the unwrap should panic out to the client
         //so we just try and unwrap immediately rather than trying to catch anything
-        let schedule: Schedule = self.schedule.into();
+        let schedule = self.schedule(step);
         match &self.flow_type {
-            SupportedFlowTypes::Employment => {
-                Employment::flow(self.value.unwrap().into(), schedule, src.clone())
-            }
-            SupportedFlowTypes::EmploymentPAYE => {
-                EmploymentPAYE::flow(self.value.unwrap().into(), schedule, src.clone())
-            }
+            SupportedFlowTypes::Employment => Employment::flow(
+                self.value.unwrap().into(),
+                schedule,
+                src.clone(),
+                self.pension(),
+            ),
+            SupportedFlowTypes::EmploymentPAYE => EmploymentPAYE::flow(
+                self.value.unwrap().into(),
+                schedule,
+                src.clone(),
+                self.pension(),
+            ),
             SupportedFlowTypes::Expense => Expense::flow(self.value.unwrap().into(), schedule),
-            SupportedFlowTypes::Rental => Rental::flow(self.value.unwrap().into(), schedule),
+            SupportedFlowTypes::Rental => {
+                Rental::flow(self.value.unwrap().into(), schedule, self.property)
+            }
             SupportedFlowTypes::InflationLinkedExpense => {
                 Expense::inflation_linked(self.value.unwrap().into(), schedule, src.clone())
             }
@@ -207,15 +480,41 @@ impl FlowUKSimConfig {
                 self.value.unwrap().into(),
                 schedule,
                 self.static_growth.unwrap(),
+                self.pension(),
+                step,
             ),
             SupportedFlowTypes::EmploymentPAYEStaticGrowth => EmploymentPAYE::static_growth(
                 self.value.unwrap().into(),
                 schedule,
                 self.static_growth.unwrap(),
+                self.pension(),
+                step,
             ),
             SupportedFlowTypes::PctOfIncomeExpense => {
                 PctOfIncomeExpense::flow(self.pct.unwrap(), schedule)
             }
+            SupportedFlowTypes::ChildBenefit => ChildBenefit::flow(
+                self.children.as_ref().unwrap().iter().map(Child::from).collect(),
+                schedule,
+            ),
+            SupportedFlowTypes::DefinedBenefit => {
+                //Value is pensionable salary, zero if no longer accruing
+                let normal_pension_date = self.normal_pension_date.unwrap();
+                Flow::DefinedBenefit(DefinedBenefit::new(
+                    self.accrued.unwrap_or(0.0),
+                    self.value.unwrap_or(0.0),
+                    self.accrual_rate.unwrap_or(0.0),
+                    normal_pension_date.into(),
+                    self.retirement_date.unwrap_or(normal_pension_date).into(),
+                    self.early_retirement_factor.unwrap_or(0.0),
+                    self.late_retirement_factor.unwrap_or(0.0),
+                    self.commutation_pct.unwrap_or(0.0),
+                    self.commutation_factor.unwrap_or(12.0),
+                    self.revaluation_cap.unwrap_or(0.05),
+                    schedule,
+                    src.clone(),
+                ))
+            }
         }
     }
 }
@@ -223,9 +522,13 @@ impl FlowUKSimConfig {
 #[derive(Debug, Deserialize, Serialize)]
 enum SupportedStackTypes {
     Isa,
+    Lisa,
+    Jisa,
     Sipp,
     Gia,
     Mortgage,
+    StudentLoan,
+    Property,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -236,6 +539,17 @@ struct StackUKSimConfig {
     rate: Option<f64>,      //Only for Mortgage
     term: Option<u8>,       //Only for Mortgage
     fix_length: Option<u8>, //Only for Mortgage
+    date_of_birth: Option<i64>, //Only for Lisa and Jisa, owner for Lisa and child for Jisa
+    first_home_purchase: Option<i64>, //Only for Lisa
+    contribution: Option<f64>, //Only for Jisa, paid annually by parent
+    carry_forward: Option<Vec<f64>>, //Only for Sipp, unused allowance oldest first
+    drawdown_start: Option<i64>, //Only for Sipp, MPAA applies from this date
+    relief_method: Option<ReliefMethod>, //Only for Sipp, defaults to NetPay
+    plan: Option<StudentLoanPlan>, //Only for StudentLoan
+    first_repayment: Option<i64>, //Only for StudentLoan, defaults to sim start
+    base_cost: Option<f64>, //Only for Property, defaults to value
+    main_residence: Option<bool>, //Only for Property, defaults to false
+    sale_date: Option<i64>, //Only for Property
 }
 
 impl StackUKSimConfig {
@@ -245,15 +559,33 @@ impl StackUKSimConfig {
         src: D,
         strat: S,
         lifetime_pension_contributions: CashValue,
+        step: &Step,
     ) -> Stack<S, D> {
         let value: CashValue = self.value.into();
         match &self.stack_type {
             SupportedStackTypes::Isa => Stack::Isa(Isa::<S>::new_with_cash(strat, &value)),
+            SupportedStackTypes::Lisa => Stack::Lisa(Lisa::<S>::new_with_cash(
+                strat,
+                &value,
+                self.date_of_birth.unwrap().into(),
+                self.first_home_purchase.map(DateTime::from),
+                clock,
+            )),
+            SupportedStackTypes::Jisa => Stack::Jisa(Jisa::<S>::new_with_cash(
+                strat,
+                &value,
+                self.date_of_birth.unwrap().into(),
+                clock,
+            )),
             SupportedStackTypes::Gia => Stack::Gia(Gia::<S>::new_with_cash(strat, &value)),
             SupportedStackTypes::Sipp => Stack::Sipp(Sipp::<S>::new_with_cash(
                 strat,
                 &lifetime_pension_contributions,
                 &value,
+                self.carry_forward.clone().unwrap_or_default(),
+                self.drawdown_start.map(DateTime::from),
+                self.relief_method.unwrap_or(ReliefMethod::NetPay),
+                clock,
             )),
             SupportedStackTypes::Mortgage => {
                 //We try to unwrap immediately, if there is something wrong we just panic out and the error
@@ -264,9 +596,27 @@ impl StackUKSimConfig {
                 let curr_date = clock.borrow().now();
 
                 let rate = self.rate.unwrap();
-                let m = Mortgage::start(&value, rate, &curr_date, fix_length, clock, src);
-                Stack::Mortgage(m)
+                let term = self.term.unwrap();
+                let m = Mortgage::start(&value, rate, &curr_date, term, fix_length, clock, src);
+                Stack::Mortgage(m.with_step(step))
+            }
+            SupportedStackTypes::StudentLoan => {
+                let first_repayment = self
+                    .first_repayment
+                    .unwrap_or_else(|| *clock.borrow().now());
+                Stack::StudentLoan(
+                    StudentLoan::new(self.plan.unwrap(), &value, &first_repayment, clock, src)
+                        .with_step(step),
+                )
             }
+            SupportedStackTypes::Property => Stack::Property(Property::new(
+                &value,
+                &self.base_cost.unwrap_or(self.value),
+                self.main_residence.unwrap_or(false),
+                self.sale_date.map(DateTime::from),
+                clock,
+                src,
+            )),
         }
     }
 }
@@ -274,6 +624,8 @@ impl StackUKSimConfig {
 #[cfg(test)]
 mod tests {
     use super::UKSimConfig;
+    use crate::schedule::Step;
+    use crate::tax::uk::Residency;
 
     #[test]
     fn test_that_basic_config_loads() {
@@ -310,6 +662,88 @@ mod tests {
         UKSimConfig::parse(data).unwrap();
     }
 
+    #[test]
+    fn test_that_flow_end_date_stops_schedule() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "emergency_cash_min": 4000.0,
+              "flows": [
+                 {
+                     "flow_type": "Employment",
+                     "value": 4000.0,
+                     "schedule": {
+                         "schedule_type": "EveryDay"
+                     },
+                     "end_date": 1593216000
+                 },
+                 {
+                     "flow_type": "Expense",
+                     "value": 100.0,
+                     "schedule": {
+                         "schedule_type": "EveryDay"
+                     }
+                 }
+              ]
+          }"#;
+        let config = UKSimConfig::parse(data).unwrap();
+        let flows = config.flows.unwrap();
+
+        //Ends 2020-06-27, flows without an end date carry on
+        let until = flows[0].schedule(&Step::Daily);
+        assert!(until.check(&1_593_216_000));
+        assert!(!until.check(&1_593_302_400));
+        assert!(flows[1].schedule(&Step::Daily).check(&1_593_302_400));
+
+        //Monthly tick from 2020-06-01 pays each day up to and including the end date
+        let monthly = flows[0].schedule(&Step::Monthly);
+        assert!(monthly.count(&1_590_969_600) == 27);
+        assert!(monthly.count(&1_593_561_600) == 0);
+    }
+
+    #[test]
+    fn test_that_employer_contribution_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "emergency_cash_min": 4000.0,
+              "flows": [
+                 {
+                     "flow_type": "EmploymentPAYE",
+                     "value": 4000.0,
+                     "schedule": {
+                         "schedule_type": "EndOfMonth"
+                     },
+                     "employer_contribution": {
+                         "contribution_type": "Tiered",
+                         "tiers": [[0.03, 0.03], [0.05, 0.06]]
+                     },
+                     "salary_sacrifice": true,
+                     "pass_on_ni_saving": true
+                 },
+                 {
+                     "flow_type": "Employment",
+                     "value": 4000.0,
+                     "schedule": {
+                         "schedule_type": "EndOfMonth"
+                     },
+                     "employer_contribution": {
+                         "contribution_type": "Matched",
+                         "match_rate": 1.0,
+                         "cap": 0.05
+                     }
+                 }
+              ]
+          }"#;
+        UKSimConfig::parse(data).unwrap();
+    }
+
     #[test]
     fn test_that_stacks_config_loads() {
         let data = r#"
@@ -329,6 +763,256 @@ mod tests {
         UKSimConfig::parse(data).unwrap();
     }
 
+    #[test]
+    fn test_that_stack_optional_fields_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "lifetime_pension_contributions": 10.0,
+              "emergency_cash_min": 4000.0,
+              "contribution_pct": 0.1,
+              "stacks": [
+                 {
+                     "stack_type": "Lisa",
+                     "value": 4000.0,
+                     "date_of_birth": 645408000,
+                     "first_home_purchase": 1592179200
+                 },
+                 {
+                     "stack_type": "Jisa",
+                     "value": 1000.0,
+                     "date_of_birth": 1592179200,
+                     "contribution": 2000.0
+                 },
+                 {
+                     "stack_type": "Sipp",
+                     "value": 1000.0,
+                     "carry_forward": [10000.0, 20000.0, 0.0],
+                     "drawdown_start": 1592179200,
+                     "relief_method": "ReliefAtSource"
+                 },
+                 {
+                     "stack_type": "StudentLoan",
+                     "value": 45000.0,
+                     "plan": "Plan2",
+                     "first_repayment": 1617580800
+                 }
+              ]
+          }"#;
+        UKSimConfig::parse(data).unwrap();
+    }
+
+    #[test]
+    fn test_that_defined_benefit_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "flows": [
+                {
+                    "flow_type": "DefinedBenefit",
+                    "value": 40000.0,
+                    "schedule": {
+                        "schedule_type": "StartOfMonth"
+                    },
+                    "accrued": 5000.0,
+                    "accrual_rate": 0.0175,
+                    "normal_pension_date": 2524608000,
+                    "retirement_date": 2461449600,
+                    "early_retirement_factor": 0.05,
+                    "commutation_pct": 0.2
+                }
+              ]
+          }"#;
+        UKSimConfig::parse(data).unwrap();
+    }
+
+    #[test]
+    fn test_that_property_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "stacks": [
+                {
+                    "stack_type": "Property",
+                    "value": 250000.0,
+                    "base_cost": 180000.0,
+                    "sale_date": 2524608000
+                },
+                {
+                    "stack_type": "Property",
+                    "value": 400000.0,
+                    "main_residence": true
+                }
+              ],
+              "flows": [
+                {
+                    "flow_type": "Rental",
+                    "value": 1000.0,
+                    "schedule": {
+                        "schedule_type": "StartOfMonth"
+                    },
+                    "property": 0
+                }
+              ]
+          }"#;
+        UKSimConfig::parse(data).unwrap();
+    }
+
+    #[test]
+    fn test_that_child_benefit_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "flows": [
+                {
+                    "flow_type": "ChildBenefit",
+                    "schedule": {
+                        "schedule_type": "EndOfMonth"
+                    },
+                    "children": [
+                        {
+                            "date_of_birth": 1592179200
+                        },
+                        {
+                            "date_of_birth": 1592179200,
+                            "end_age": 20
+                        }
+                    ]
+                }
+              ]
+          }"#;
+        UKSimConfig::parse(data).unwrap();
+    }
+
+    #[test]
+    fn test_that_estate_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "estate": {
+                  "date_of_death": 2524608000,
+                  "residence": 400000.0,
+                  "include_pensions": true,
+                  "transferred_nil_rate_band": 1.0
+              }
+          }"#;
+        let config = UKSimConfig::parse(data).unwrap();
+        assert!(config.estate.unwrap().date_of_death == Some(2524608000));
+    }
+
+    #[test]
+    fn test_that_mortality_config_samples_date_of_death() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "mortality": {
+                  "date_of_birth": -315619200,
+                  "sex": "Male",
+                  "basis": "Period"
+              }
+          }"#;
+        let config = UKSimConfig::parse(data).unwrap();
+        let start_date = 1735689600;
+        let date_of_death = config
+            .sample_date_of_death(&start_date, &mut rand::thread_rng())
+            .unwrap();
+        assert!(date_of_death >= start_date);
+    }
+
+    #[test]
+    fn test_that_annuity_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "mortality": {
+                  "date_of_birth": -315619200,
+                  "sex": "Female"
+              },
+              "annuity": {
+                  "age": 70,
+                  "pct": 0.5,
+                  "indexation": "RPI"
+              }
+          }"#;
+        let config = UKSimConfig::parse(data).unwrap();
+        assert!(config.annuity.unwrap().age == 70);
+    }
+
+    #[test]
+    fn test_that_residency_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "residency": "Scotland",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1
+          }"#;
+        let config = UKSimConfig::parse(data).unwrap();
+        assert!(matches!(config.residency, Some(Residency::Scotland)));
+    }
+
+    #[test]
+    fn test_that_fiscal_drag_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "fiscal_drag": {
+                  "policy_type": "Custom",
+                  "rates": [0.0, 0.02, 0.02]
+              },
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1
+          }"#;
+        let config = UKSimConfig::parse(data).unwrap();
+        assert!(config.fiscal_drag.unwrap().rates.unwrap().len() == 3);
+    }
+
+    #[test]
+    fn test_that_bed_and_isa_config_loads() {
+        let data = r#"
+          {
+              "starting_cash": 1000.0,
+              "nic": "A",
+              "emergency_cash_min": 4000.0,
+              "lifetime_pension_contributions": 10.0,
+              "contribution_pct": 0.1,
+              "bed_and_isa": true,
+              "bed_and_sipp": 5000.0
+          }"#;
+        let config = UKSimConfig::parse(data).unwrap();
+        assert!(config.bed_and_isa.unwrap());
+        assert!(config.bed_and_sipp.unwrap() == 5000.0);
+    }
+
     #[test]
     #[should_panic]
     fn test_that_bad_input_unwrap_panics() {
@@ -1,17 +1,20 @@
 use alator::types::{CashValue, DateTime};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
-use crate::stack::CanTransfer;
+use crate::stack::{repay_student_loans, student_loan_due, CanTransfer, ReliefMethod};
 use crate::input::{HashMapSourceSim, SimDataSource};
-use crate::schedule::Schedule;
+use crate::mortality::LifeTable;
+use crate::schedule::{calendar_year, years_between, Schedule, Step};
 use crate::strat::InvestmentStrategy;
 use crate::tax::uk::TaxPeriod;
 use crate::sim::uk::UKSimulationState;
 
-trait WillFlow<S: InvestmentStrategy> {
+trait WillFlow<S: InvestmentStrategy, D: SimDataSource> {
     //Check should only deposit funds into cash. We need to track deposits to other accounts so it
     //makes sense to only credit investment accounts from one place (when the portfolio is
     //rebalanced).
-    fn check(&self, curr: &i64, state: &mut UKSimulationState<S>);
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>);
     fn get_value(&self) -> CashValue;
     fn set_value(&mut self, cash: &f64);
 }
@@ -20,18 +23,22 @@ trait WillFlow<S: InvestmentStrategy> {
 //changes to the flow state is monthly. If it is more than this, the value will be updated too
 //often.
 #[derive(Clone, Debug)]
-pub enum Flow {
-    Employment(InflationLinkedGrowth, Employment),
+pub enum Flow<D: SimDataSource = HashMapSourceSim> {
+    Employment(InflationLinkedGrowth<D>, Employment),
     EmploymentStaticGrowth(StaticGrowth, Employment),
-    EmploymentPAYE(InflationLinkedGrowth, EmploymentPAYE),
+    EmploymentPAYE(InflationLinkedGrowth<D>, EmploymentPAYE),
     EmploymentPAYEStaticGrowth(StaticGrowth, EmploymentPAYE),
     Rental(Rental),
     Expense(Expense),
-    InflationLinkedExpense(InflationLinkedGrowth, Expense),
+    InflationLinkedExpense(InflationLinkedGrowth<D>, Expense),
     PctOfIncomeExpense(PctOfIncomeExpense),
+    ChildBenefit(ChildBenefit),
+    Annuity(Annuity),
+    InflationLinkedAnnuity(InflationLinkedGrowth<D>, Annuity),
+    DefinedBenefit(DefinedBenefit<D>),
 }
 
-impl Flow {
+impl<D: SimDataSource> Flow<D> {
     pub fn is_expense(&self) -> bool {
         match self {
             Flow::Expense(_) | Flow::InflationLinkedExpense(_, _) | Flow::PctOfIncomeExpense(_) => {
@@ -44,7 +51,7 @@ impl Flow {
     pub fn check<S: InvestmentStrategy>(
         &mut self,
         curr: &DateTime,
-        state: &mut UKSimulationState<S>,
+        state: &mut UKSimulationState<S, D>,
     ) {
         //Sometimes the check call will be made to the underlying object. If the data references
         //some additional data, i.e. inflation, then the call is made to the growth object instead
@@ -58,28 +65,40 @@ impl Flow {
             Flow::Expense(val) => val.check(curr, state),
             Flow::InflationLinkedExpense(growth, val) => growth.check(curr, state, val),
             Flow::PctOfIncomeExpense(val) => val.check(curr, state),
+            Flow::ChildBenefit(val) => val.check(curr, state),
+            Flow::Annuity(val) => val.check(curr, state),
+            Flow::InflationLinkedAnnuity(growth, val) => growth.check(curr, state, val),
+            Flow::DefinedBenefit(val) => val.check(curr, state),
         }
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct InflationLinkedGrowth {
-    source: HashMapSourceSim,
+pub struct InflationLinkedGrowth<D: SimDataSource> {
+    source: D,
     schedule: Schedule,
+    //Yearly schedules uprate by inflation over the trailing year rather than the daily rate
+    annual: bool,
 }
 
-impl InflationLinkedGrowth {
-    fn check<F: WillFlow<S>, S: InvestmentStrategy>(
+impl<D: SimDataSource> InflationLinkedGrowth<D> {
+    fn check<F: WillFlow<S, D>, S: InvestmentStrategy>(
         &mut self,
         curr: &i64,
-        state: &mut UKSimulationState<S>,
+        state: &mut UKSimulationState<S, D>,
         target: &mut F,
     ) {
         target.check(curr, state);
-        if self.schedule.check(curr) {
+        let times = self.schedule.count(curr) as i32;
+        if times > 0 {
             let curr_val = target.get_value();
-            if let Some(inflation) = self.source.get_current_inflation() {
-                let new_val = *curr_val * (1.0 + inflation);
+            let inflation = if self.annual {
+                Some(self.source.get_trailing_year_inflation())
+            } else {
+                self.source.get_current_inflation()
+            };
+            if let Some(inflation) = inflation {
+                let new_val = *curr_val * (1.0 + inflation).powi(times);
                 target.set_value(&new_val);
             } else {
                 panic!("Created fixed growth rate employement income with bad date");
@@ -88,32 +107,162 @@ impl InflationLinkedGrowth {
     }
 }
 
-impl InflationLinkedGrowth {
-    fn new(source: HashMapSourceSim, schedule: Schedule) -> Self {
-        Self { source, schedule }
+impl<D: SimDataSource> InflationLinkedGrowth<D> {
+    fn new(source: D, schedule: Schedule) -> Self {
+        Self {
+            source,
+            schedule,
+            annual: false,
+        }
+    }
+
+    fn yearly(source: D, schedule: Schedule) -> Self {
+        Self {
+            source,
+            schedule,
+            annual: true,
+        }
     }
 }
 
+//Growth rate is applied on each day, monthly ticks compound the rate over the days in the tick
 #[derive(Clone, Debug)]
 pub struct StaticGrowth {
     growth_rate: f64,
+    schedule: Schedule,
 }
 
 impl StaticGrowth {
-    fn check<F: WillFlow<S>, S: InvestmentStrategy>(
+    fn check<F: WillFlow<S, D>, S: InvestmentStrategy, D: SimDataSource>(
         &mut self,
         curr: &i64,
-        state: &mut UKSimulationState<S>,
+        state: &mut UKSimulationState<S, D>,
         target: &mut F,
     ) {
         target.check(curr, state);
+        let days = self.schedule.count(curr) as i32;
         let curr_val = target.get_value();
-        let new_val = *curr_val * (1.0 + self.growth_rate);
+        let new_val = *curr_val * (1.0 + self.growth_rate).powi(days);
         target.set_value(&new_val);
     }
 
-    fn new(growth_rate: f64) -> Self {
-        Self { growth_rate }
+    fn new(growth_rate: f64, step: &Step) -> Self {
+        Self {
+            growth_rate,
+            schedule: Schedule::EveryDay.for_step(step),
+        }
+    }
+}
+
+//Employer contribution as a percentage of gross pay
+#[derive(Clone, Debug)]
+pub enum EmployerContribution {
+    None,
+    Flat(f64),
+    //(Match rate, cap), employer matches the employee percentage at the match rate up to the cap
+    Matched(f64, f64),
+    //(Minimum employee percentage, employer percentage), employer pays the highest tier reached
+    Tiered(Vec<(f64, f64)>),
+}
+
+impl EmployerContribution {
+    pub fn pct(&self, employee_pct: &f64) -> f64 {
+        match self {
+            EmployerContribution::None => 0.0,
+            EmployerContribution::Flat(pct) => *pct,
+            EmployerContribution::Matched(rate, cap) => {
+                let matched = *employee_pct * *rate;
+                if matched > *cap {
+                    *cap
+                } else {
+                    matched
+                }
+            }
+            EmployerContribution::Tiered(tiers) => {
+                let mut pct = 0.0;
+                for (min, employer_pct) in tiers {
+                    if employee_pct >= min && *employer_pct > pct {
+                        pct = *employer_pct;
+                    }
+                }
+                pct
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PensionScheme {
+    employer: EmployerContribution,
+    //Employee contribution is taken from gross pay before income tax and NI
+    salary_sacrifice: bool,
+    //Employer adds the NI they save through salary sacrifice to the contribution
+    pass_on_ni_saving: bool,
+}
+
+impl PensionScheme {
+    //Pays employee and employer contributions into the pension. Returns the employee contribution
+    //that has to be deducted from pay and the part of that which is deducted before income tax.
+    fn pay<S: InvestmentStrategy, D: SimDataSource>(
+        &self,
+        gross_pay: &f64,
+        state: &mut UKSimulationState<S, D>,
+    ) -> (CashValue, CashValue) {
+        let contribution = *gross_pay * state.contribution_pct;
+        //Anything over the allowance stays in pay
+        let (contributed, _remainder) = state.sipp.deposit_wrapper(&contribution);
+        state.paid_into_sipp_since_start =
+            state.paid_into_sipp_since_start.clone() + contributed.clone();
+
+        let mut employer_contribution = *gross_pay * self.employer.pct(&state.contribution_pct);
+        let paid = if self.salary_sacrifice {
+            //Sacrificed pay is an employer contribution, so isn't deducted from income again at
+            //the annual tax calculation
+            state.employer_contributions_annual =
+                state.employer_contributions_annual.clone() + contributed.clone();
+            if self.pass_on_ni_saving {
+                employer_contribution += *contributed * *state.tax_config.employer_ni_rate();
+            }
+            (contributed, CashValue::default())
+        } else {
+            match state.sipp.relief_method() {
+                ReliefMethod::NetPay => {
+                    state.sipp_contributions_annual =
+                        state.sipp_contributions_annual.clone() + contributed.clone();
+                    (contributed.clone(), contributed)
+                }
+                ReliefMethod::ReliefAtSource => {
+                    //Contribution is gross, employee pays from net pay and the provider claims
+                    //basic rate relief
+                    state.relief_at_source_contributions_annual =
+                        state.relief_at_source_contributions_annual.clone() + contributed.clone();
+                    let net = *contributed * (1.0 - *state.tax_config.basic_income_rate());
+                    (CashValue::from(net), CashValue::default())
+                }
+            }
+        };
+
+        if employer_contribution > 0.0 {
+            //Employer contributions over the allowance are lost
+            let (employer_contributed, _lost) = state.sipp.deposit_wrapper(&employer_contribution);
+            state.paid_into_sipp_since_start =
+                state.paid_into_sipp_since_start.clone() + employer_contributed.clone();
+            state.employer_contributions_annual =
+                state.employer_contributions_annual.clone() + employer_contributed;
+        }
+        paid
+    }
+
+    pub fn new(
+        employer: EmployerContribution,
+        salary_sacrifice: bool,
+        pass_on_ni_saving: bool,
+    ) -> Self {
+        Self {
+            employer,
+            salary_sacrifice,
+            pass_on_ni_saving,
+        }
     }
 }
 
@@ -121,26 +270,25 @@ impl StaticGrowth {
 pub struct Employment {
     value: CashValue,
     schedule: Schedule,
+    pension: PensionScheme,
 }
 
-impl<S: InvestmentStrategy> WillFlow<S> for Employment {
-    fn check(&self, curr: &i64, state: &mut UKSimulationState<S>) {
-        if self.schedule.check(curr) {
+impl<S: InvestmentStrategy, D: SimDataSource> WillFlow<S, D> for Employment {
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>) {
+        for _i in 0..self.schedule.count(curr) {
             //Non-paye employment income deducts contributions but doesn't take income tax or NI
             //until annual tax date
-            state.non_paye_income_annual =
-                state.non_paye_income_annual.clone() + self.value.clone();
+            let (contributed, _pre_tax) = self.pension.pay(&self.value, state);
+            let mut pay = *self.value;
+            if self.pension.salary_sacrifice {
+                pay -= *contributed;
+            }
+            state.non_paye_income_annual = state.non_paye_income_annual.clone() + pay.into();
 
-            let contribution = *self.value * state.contribution_pct;
-            let (contributed, remainder) = state.sipp.deposit_wrapper(&contribution);
-            state.sipp_contributions_annual =
-                state.sipp_contributions_annual.clone() + contributed.clone();
-            state.paid_into_sipp_since_start =
-                state.paid_into_sipp_since_start.clone() + contributed.clone();
-            let net_pay = *self.value - *contributed + *remainder;
+            let net_pay = *self.value - *contributed;
             state.bank.deposit(&net_pay);
 
-            state.gross_income_annual = state.gross_income_annual.clone() + self.value.clone();
+            state.gross_income_annual = state.gross_income_annual.clone() + pay.into();
             state.net_income_annual = state.net_income_annual.clone() + net_pay.into();
             state.income_paid_in_curr_loop =
                 state.income_paid_in_curr_loop.clone() + net_pay.into();
@@ -157,20 +305,35 @@ impl<S: InvestmentStrategy> WillFlow<S> for Employment {
 }
 
 impl Employment {
-    pub fn static_growth(value: CashValue, schedule: Schedule, growth: f64) -> Flow {
-        let growth = StaticGrowth::new(growth);
-        let employment = Employment::new(value, schedule);
+    pub fn static_growth<D: SimDataSource>(
+        value: CashValue,
+        schedule: Schedule,
+        growth: f64,
+        pension: PensionScheme,
+        step: &Step,
+    ) -> Flow<D> {
+        let growth = StaticGrowth::new(growth, step);
+        let employment = Employment::new(value, schedule, pension);
         Flow::EmploymentStaticGrowth(growth, employment)
     }
 
-    pub fn flow(value: CashValue, schedule: Schedule, source: HashMapSourceSim) -> Flow {
-        let income = Employment::new(value, schedule.clone());
+    pub fn flow<D: SimDataSource>(
+        value: CashValue,
+        schedule: Schedule,
+        source: D,
+        pension: PensionScheme,
+    ) -> Flow<D> {
+        let income = Employment::new(value, schedule.clone(), pension);
         let data = InflationLinkedGrowth::new(source, schedule);
         Flow::Employment(data, income)
     }
 
-    pub fn new(value: CashValue, schedule: Schedule) -> Self {
-        Self { value, schedule }
+    pub fn new(value: CashValue, schedule: Schedule, pension: PensionScheme) -> Self {
+        Self {
+            value,
+            schedule,
+            pension,
+        }
     }
 }
 
@@ -178,37 +341,46 @@ impl Employment {
 pub struct EmploymentPAYE {
     value: CashValue,
     schedule: Schedule,
+    pension: PensionScheme,
 }
 
-impl<S: InvestmentStrategy> WillFlow<S> for EmploymentPAYE {
-    fn check(&self, curr: &i64, state: &mut UKSimulationState<S>) {
-        if self.schedule.check(curr) {
+impl<S: InvestmentStrategy, D: SimDataSource> WillFlow<S, D> for EmploymentPAYE {
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>) {
+        for _i in 0..self.schedule.count(curr) {
             //Have to deduct income tax and NI and SIPP contributions
-            let contribution = *self.value * state.contribution_pct;
-            let (contributed, remainder) = state.sipp.deposit_wrapper(&contribution);
-            state.sipp_contributions_annual =
-                state.sipp_contributions_annual.clone() + contributed.clone();
-            state.paid_into_sipp_since_start =
-                state.paid_into_sipp_since_start.clone() + contributed.clone();
-
-            //Takes both income tax and NI
-            let paye_paid = TaxPeriod::paye(
-                &self.value,
-                &contributed,
-                state.nic_group,
-                &state.tax_config,
-            );
+            let (contributed, pre_tax) = self.pension.pay(&self.value, state);
+
+            //Takes both income tax and NI, sacrificed pay is removed before either is calculated
+            let paye_paid = if self.pension.salary_sacrifice {
+                let pay = *self.value - *contributed;
+                TaxPeriod::paye(&pay, &0.0, state.nic_group, &state.tax_config)
+            } else {
+                TaxPeriod::paye(&self.value, &pre_tax, state.nic_group, &state.tax_config)
+            };
             state.tax_paid_annual = state.tax_paid_annual.clone() + paye_paid.total();
-            state.tax_paid_paye_annual = state.tax_paid_paye_annual.clone() + paye_paid.total();
+            //Income tax is checked again at the annual calculation, NI through PAYE is final
+            state.tax_paid_paye_annual =
+                state.tax_paid_paye_annual.clone() + paye_paid.income_tax();
+            state.ni_paid_paye_annual = state.ni_paid_paye_annual.clone() + paye_paid.ni();
+
+            let mut pay = *self.value;
+            if self.pension.salary_sacrifice {
+                pay -= *contributed;
+            }
+
+            //Student loan deductions are taken against the monthly threshold so this assumes that
+            //pay is monthly
+            let loan_due = student_loan_due(&state.student_loans, &pay, true);
+            let student_loan_paid = repay_student_loans(&mut state.student_loans, &loan_due);
+            state.student_loan_paid_paye_annual =
+                state.student_loan_paid_paye_annual.clone() + student_loan_paid.clone();
 
             //We don't deduct paye paid from bank but deduct it straight from gross_pay
-            let net_pay = *self.value + *remainder - *contributed - *paye_paid.total();
+            let net_pay = *self.value - *contributed - *paye_paid.total() - *student_loan_paid;
             state.bank.deposit(&net_pay);
 
-            state.paye_income_annual = state.paye_income_annual.clone() + net_pay.into();
-            state.tax_paid_annual = state.tax_paid_annual.clone() + paye_paid.total();
-
-            state.gross_income_annual = state.gross_income_annual.clone() + self.value.clone();
+            state.paye_income_annual = state.paye_income_annual.clone() + pay.into();
+            state.gross_income_annual = state.gross_income_annual.clone() + pay.into();
             state.net_income_annual = state.net_income_annual.clone() + net_pay.into();
             state.income_paid_in_curr_loop =
                 state.income_paid_in_curr_loop.clone() + net_pay.into();
@@ -225,20 +397,35 @@ impl<S: InvestmentStrategy> WillFlow<S> for EmploymentPAYE {
 }
 
 impl EmploymentPAYE {
-    pub fn static_growth(value: CashValue, schedule: Schedule, growth: f64) -> Flow {
-        let growth = StaticGrowth::new(growth);
-        let employment = EmploymentPAYE::new(value, schedule);
+    pub fn static_growth<D: SimDataSource>(
+        value: CashValue,
+        schedule: Schedule,
+        growth: f64,
+        pension: PensionScheme,
+        step: &Step,
+    ) -> Flow<D> {
+        let growth = StaticGrowth::new(growth, step);
+        let employment = EmploymentPAYE::new(value, schedule, pension);
         Flow::EmploymentPAYEStaticGrowth(growth, employment)
     }
 
-    pub fn flow(value: CashValue, schedule: Schedule, source: HashMapSourceSim) -> Flow {
-        let income = EmploymentPAYE::new(value, schedule.clone());
+    pub fn flow<D: SimDataSource>(
+        value: CashValue,
+        schedule: Schedule,
+        source: D,
+        pension: PensionScheme,
+    ) -> Flow<D> {
+        let income = EmploymentPAYE::new(value, schedule.clone(), pension);
         let data = InflationLinkedGrowth::new(source, schedule);
         Flow::EmploymentPAYE(data, income)
     }
 
-    pub fn new(value: CashValue, schedule: Schedule) -> Self {
-        Self { value, schedule }
+    pub fn new(value: CashValue, schedule: Schedule, pension: PensionScheme) -> Self {
+        Self {
+            value,
+            schedule,
+            pension,
+        }
     }
 }
 
@@ -246,17 +433,28 @@ impl EmploymentPAYE {
 pub struct Rental {
     value: CashValue,
     schedule: Schedule,
+    //Position of the let property in the simulation, rent stops when it is sold and moves with
+    //the value of the property
+    property: Option<usize>,
 }
 
-impl<S: InvestmentStrategy> WillFlow<S> for Rental {
-    fn check(&self, curr: &i64, state: &mut UKSimulationState<S>) {
-        if self.schedule.check(curr) {
-            state.rental_income_annual = state.rental_income_annual.clone() + self.value.clone();
-            state.bank.deposit(&self.value);
-
-            state.gross_income_annual = state.gross_income_annual.clone() + self.value.clone();
-            state.income_paid_in_curr_loop =
-                state.income_paid_in_curr_loop.clone() + self.value.clone();
+impl<S: InvestmentStrategy, D: SimDataSource> WillFlow<S, D> for Rental {
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>) {
+        for _i in 0..self.schedule.count(curr) {
+            let value = match self.property {
+                Some(pos) => match state.properties.get(pos) {
+                    Some(property) if !property.is_sold() => {
+                        CashValue::from(*self.value * property.growth())
+                    }
+                    _ => return,
+                },
+                None => self.value.clone(),
+            };
+            state.rental_income_annual = state.rental_income_annual.clone() + value.clone();
+            state.bank.deposit(&value);
+
+            state.gross_income_annual = state.gross_income_annual.clone() + value.clone();
+            state.income_paid_in_curr_loop = state.income_paid_in_curr_loop.clone() + value;
         }
     }
 
@@ -270,12 +468,20 @@ impl<S: InvestmentStrategy> WillFlow<S> for Rental {
 }
 
 impl Rental {
-    pub fn new(value: CashValue, schedule: Schedule) -> Self {
-        Self { value, schedule }
+    pub fn new(value: CashValue, schedule: Schedule, property: Option<usize>) -> Self {
+        Self {
+            value,
+            schedule,
+            property,
+        }
     }
 
-    pub fn flow(value: CashValue, schedule: Schedule) -> Flow {
-        Flow::Rental(Self::new(value, schedule))
+    pub fn flow<D: SimDataSource>(
+        value: CashValue,
+        schedule: Schedule,
+        property: Option<usize>,
+    ) -> Flow<D> {
+        Flow::Rental(Self::new(value, schedule, property))
     }
 }
 
@@ -285,11 +491,10 @@ pub struct Expense {
     schedule: Schedule,
 }
 
-impl<S: InvestmentStrategy> WillFlow<S> for Expense {
-    fn check(&self, curr: &i64, state: &mut UKSimulationState<S>) {
-        if self.schedule.check(curr) {
-            state.expense_annual = state.expense_annual.clone() + self.value.clone().into();
-            state.bank.withdraw(&self.value);
+impl<S: InvestmentStrategy, D: SimDataSource> WillFlow<S, D> for Expense {
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>) {
+        for _i in 0..self.schedule.count(curr) {
+            state.pay_expense(&self.value);
         }
     }
 
@@ -307,17 +512,17 @@ impl Expense {
         Self { value, schedule }
     }
 
-    pub fn inflation_linked(
+    pub fn inflation_linked<D: SimDataSource>(
         value: CashValue,
         schedule: Schedule,
-        source: HashMapSourceSim,
-    ) -> Flow {
+        source: D,
+    ) -> Flow<D> {
         let expense = Expense::new(value, schedule.clone());
         let data = InflationLinkedGrowth::new(source, schedule);
         Flow::InflationLinkedExpense(data, expense)
     }
 
-    pub fn flow(value: CashValue, schedule: Schedule) -> Flow {
+    pub fn flow<D: SimDataSource>(value: CashValue, schedule: Schedule) -> Flow<D> {
         Flow::Expense(Self::new(value, schedule))
     }
 }
@@ -328,8 +533,9 @@ pub struct PctOfIncomeExpense {
     schedule: Schedule,
 }
 
-impl<S: InvestmentStrategy> WillFlow<S> for PctOfIncomeExpense {
-    fn check(&self, curr: &i64, state: &mut UKSimulationState<S>) {
+impl<S: InvestmentStrategy, D: SimDataSource> WillFlow<S, D> for PctOfIncomeExpense {
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>) {
+        //Charged once on each tick against the income paid on that tick
         if self.schedule.check(curr) {
             //This is correct, tried to fiddle with this and we need to make sure that income runs
             //before expense. If this isn't true then the simulation cannot continue/we generate
@@ -340,8 +546,7 @@ impl<S: InvestmentStrategy> WillFlow<S> for PctOfIncomeExpense {
                 panic!("Created PctOfIncomeExpense with no income");
             } else {
                 let expense_value = self.pct * *state.income_paid_in_curr_loop;
-                state.expense_annual = state.expense_annual.clone() + expense_value.into();
-                state.bank.withdraw(&expense_value);
+                state.pay_expense(&expense_value);
             }
         }
     }
@@ -360,7 +565,418 @@ impl PctOfIncomeExpense {
         Self { pct, schedule }
     }
 
-    pub fn flow(pct: f64, schedule: Schedule) -> Flow {
+    pub fn flow<D: SimDataSource>(pct: f64, schedule: Schedule) -> Flow<D> {
         Flow::PctOfIncomeExpense(Self::new(pct, schedule))
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct Child {
+    date_of_birth: DateTime,
+    //Child benefit stops at 16, or 20 if the child stays in approved education
+    end_age: i32,
+}
+
+impl Child {
+    fn is_eligible(&self, curr: &i64) -> bool {
+        *curr >= *self.date_of_birth && years_between(&self.date_of_birth, curr) < self.end_age
+    }
+
+    pub fn new(date_of_birth: DateTime, end_age: i32) -> Self {
+        Self {
+            date_of_birth,
+            end_age,
+        }
+    }
+}
+
+//Paid at the weekly rate in the tax config, the schedule is assumed to be monthly
+#[derive(Clone, Debug)]
+pub struct ChildBenefit {
+    children: Vec<Child>,
+    schedule: Schedule,
+}
+
+impl<S: InvestmentStrategy, D: SimDataSource> WillFlow<S, D> for ChildBenefit {
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>) {
+        for _i in 0..self.schedule.count(curr) {
+            let eligible = self.children.iter().filter(|c| c.is_eligible(curr)).count();
+            if eligible == 0 {
+                return;
+            }
+
+            let weekly = state.tax_config.child_benefit_weekly(eligible);
+            let value = *weekly * 52.0 / 12.0;
+            state.bank.deposit(&value);
+
+            //Isn't taxable income, but is charged back at the annual tax calculation for high
+            //incomes
+            state.child_benefit_annual = state.child_benefit_annual.clone() + value.into();
+            state.net_income_annual = state.net_income_annual.clone() + value.into();
+            state.income_paid_in_curr_loop =
+                state.income_paid_in_curr_loop.clone() + value.into();
+        }
+    }
+
+    fn get_value(&self) -> CashValue {
+        unimplemented!("Has no value")
+    }
+
+    fn set_value(&mut self, _val: &f64) {
+        unimplemented!("Has no value")
+    }
+}
+
+impl ChildBenefit {
+    pub fn new(children: Vec<Child>, schedule: Schedule) -> Self {
+        Self { children, schedule }
+    }
+
+    pub fn flow<D: SimDataSource>(children: Vec<Child>, schedule: Schedule) -> Flow<D> {
+        Flow::ChildBenefit(Self::new(children, schedule))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AnnuityIndexation {
+    Level,
+    //Inflation is used in place of RPI
+    #[serde(rename = "RPI")]
+    Rpi,
+}
+
+#[derive(Clone, Debug)]
+pub enum AnnuityAmount {
+    Fixed(f64),
+    PctOfSipp(f64),
+}
+
+//Lifetime income bought from the SIPP, taxed as pension income without NI. Paid monthly.
+#[derive(Clone, Debug)]
+pub struct Annuity {
+    value: CashValue,
+    schedule: Schedule,
+}
+
+impl<S: InvestmentStrategy, D: SimDataSource> WillFlow<S, D> for Annuity {
+    fn check(&self, curr: &i64, state: &mut UKSimulationState<S, D>) {
+        for _i in 0..self.schedule.count(curr) {
+            state.bank.deposit(&self.value);
+            state.pension_income_annual = state.pension_income_annual.clone() + self.value.clone();
+            state.gross_income_annual = state.gross_income_annual.clone() + self.value.clone();
+            state.net_income_annual = state.net_income_annual.clone() + self.value.clone();
+            state.income_paid_in_curr_loop =
+                state.income_paid_in_curr_loop.clone() + self.value.clone();
+        }
+    }
+
+    fn get_value(&self) -> CashValue {
+        self.value.clone()
+    }
+
+    fn set_value(&mut self, val: &f64) {
+        self.value = CashValue::from(*val);
+    }
+}
+
+impl Annuity {
+    pub fn new(value: CashValue, schedule: Schedule) -> Self {
+        Self { value, schedule }
+    }
+
+    //RPI-linked annuities are uprated on each anniversary of purchase
+    pub fn flow<D: SimDataSource>(
+        annual_income: &f64,
+        indexation: AnnuityIndexation,
+        purchase_date: &DateTime,
+        source: D,
+        step: &Step,
+    ) -> Flow<D> {
+        let schedule = Schedule::StartOfMonth.for_step(step);
+        let annuity = Self::new(CashValue::from(*annual_income / 12.0), schedule);
+        match indexation {
+            AnnuityIndexation::Level => Flow::Annuity(annuity),
+            AnnuityIndexation::Rpi => {
+                let purchase: OffsetDateTime = purchase_date.clone().into();
+                //Anniversaries late in the month are moved so that they happen every year
+                let day = purchase.day().min(28) as i32;
+                let month = purchase.month() as i32;
+                let schedule = Schedule::EveryYear(day, month).for_step(step);
+                let growth = InflationLinkedGrowth::yearly(source, schedule);
+                Flow::InflationLinkedAnnuity(growth, annuity)
+            }
+        }
+    }
+}
+
+//Buys an annuity from the SIPP once the purchase date is reached. Priced from the current interest
+//rate and the owner's mortality, RPI-linked annuities are priced at the real rate.
+#[derive(Clone, Debug)]
+pub struct AnnuityPurchase {
+    date: DateTime,
+    date_of_birth: i64,
+    amount: AnnuityAmount,
+    indexation: AnnuityIndexation,
+    life_table: LifeTable,
+}
+
+impl AnnuityPurchase {
+    pub fn is_due(&self, curr: &DateTime) -> bool {
+        *curr >= self.date
+    }
+
+    pub fn amount(&self, sipp_value: &f64) -> f64 {
+        match self.amount {
+            AnnuityAmount::Fixed(amount) => amount.min(*sipp_value),
+            AnnuityAmount::PctOfSipp(pct) => sipp_value * pct,
+        }
+    }
+
+    pub fn annual_income(&self, price: &f64, curr: &i64, rate: &f64, inflation: &f64) -> f64 {
+        let rate = match self.indexation {
+            AnnuityIndexation::Level => *rate,
+            AnnuityIndexation::Rpi => ((1.0 + rate) / (1.0 + inflation)) - 1.0,
+        };
+        let age = years_between(&self.date_of_birth, curr);
+        price / self.life_table.annuity_factor(age, calendar_year(curr), &rate)
+    }
+
+    pub fn flow<D: SimDataSource>(
+        &self,
+        annual_income: &f64,
+        curr: &DateTime,
+        source: D,
+        step: &Step,
+    ) -> Flow<D> {
+        Annuity::flow(annual_income, self.indexation, curr, source, step)
+    }
+
+    pub fn new(
+        date: DateTime,
+        date_of_birth: i64,
+        amount: AnnuityAmount,
+        indexation: AnnuityIndexation,
+        life_table: LifeTable,
+    ) -> Self {
+        Self {
+            date,
+            date_of_birth,
+            amount,
+            indexation,
+            life_table,
+        }
+    }
+}
+
+//Pension input for defined benefit accrual is the increase in pension multiplied by this factor
+const DB_PENSION_INPUT_FACTOR: f64 = 16.0;
+const SECONDS_IN_YEAR: f64 = 365.25 * 86_400.0;
+
+//Accrues salary times the accrual rate on each schedule hit (assumed monthly) until retirement, with
+//the accrued pension revalued by CPI at the start of each tax year. From retirement the pension,
+//adjusted for early or late retirement and any commutation, is paid on the schedule and increased
+//by CPI each year. Inflation is used in place of CPI.
+#[derive(Clone, Debug)]
+pub struct DefinedBenefit<D: SimDataSource> {
+    //Annual pension
+    accrued: f64,
+    salary: f64,
+    accrual_rate: f64,
+    normal_pension_date: DateTime,
+    retirement_date: DateTime,
+    //Reduction for each year before normal pension date
+    early_retirement_factor: f64,
+    //Increase for each year after normal pension date
+    late_retirement_factor: f64,
+    //Percentage of pension given up for a lump sum, and the lump sum for each unit of pension
+    commutation_pct: f64,
+    commutation_factor: f64,
+    //Maximum annual revaluation of the accrued pension
+    revaluation_cap: f64,
+    in_payment: bool,
+    schedule: Schedule,
+    source: D,
+}
+
+impl<D: SimDataSource> DefinedBenefit<D> {
+    //Years early is negative for late retirement
+    pub fn retirement_factor_logic(years_early: &f64, early: &f64, late: &f64) -> f64 {
+        if *years_early >= 0.0 {
+            (1.0 - (early * years_early)).max(0.0)
+        } else {
+            1.0 + (late * -years_early)
+        }
+    }
+
+    fn retirement_factor(&self) -> f64 {
+        let years_early =
+            (*self.normal_pension_date - *self.retirement_date) as f64 / SECONDS_IN_YEAR;
+        Self::retirement_factor_logic(
+            &years_early,
+            &self.early_retirement_factor,
+            &self.late_retirement_factor,
+        )
+    }
+
+    //Returns (pension after commutation, lump sum)
+    pub fn commutation_logic(pension: &f64, pct: &f64, factor: &f64) -> (f64, f64) {
+        let given_up = pension * pct;
+        (pension - given_up, given_up * factor)
+    }
+
+    fn retire<S: InvestmentStrategy>(&mut self, state: &mut UKSimulationState<S, D>) {
+        let pension = self.accrued * self.retirement_factor();
+        let (pension, lump_sum) =
+            Self::commutation_logic(&pension, &self.commutation_pct, &self.commutation_factor);
+        self.accrued = pension;
+        self.in_payment = true;
+        //Lump sum is tax-free so doesn't count as income
+        if lump_sum > 0.0 {
+            state.bank.deposit(&lump_sum);
+        }
+    }
+
+    pub fn check<S: InvestmentStrategy>(
+        &mut self,
+        curr: &i64,
+        state: &mut UKSimulationState<S, D>,
+    ) {
+        if !self.in_payment && *curr >= *self.retirement_date {
+            self.retire(state);
+        }
+
+        if state.tax_year_start_schedule.check(curr) {
+            let inflation = self.source.get_trailing_year_inflation();
+            self.accrued *= 1.0 + inflation.min(self.revaluation_cap);
+            self.salary *= 1.0 + inflation;
+        }
+
+        let times = self.schedule.count(curr) as f64;
+        if times == 0.0 {
+            return;
+        }
+
+        if self.in_payment {
+            let value = CashValue::from(times * self.accrued / 12.0);
+            state.bank.deposit(&value);
+            state.pension_income_annual = state.pension_income_annual.clone() + value.clone();
+            state.gross_income_annual = state.gross_income_annual.clone() + value.clone();
+            state.net_income_annual = state.net_income_annual.clone() + value.clone();
+            state.income_paid_in_curr_loop = state.income_paid_in_curr_loop.clone() + value;
+        } else {
+            let accrual = times * (self.salary / 12.0) * self.accrual_rate;
+            self.accrued += accrual;
+            let pension_input = accrual * DB_PENSION_INPUT_FACTOR;
+            state.sipp.add_defined_benefit_input(&pension_input);
+            state.defined_benefit_input_annual =
+                state.defined_benefit_input_annual.clone() + pension_input.into();
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        accrued: f64,
+        salary: f64,
+        accrual_rate: f64,
+        normal_pension_date: DateTime,
+        retirement_date: DateTime,
+        early_retirement_factor: f64,
+        late_retirement_factor: f64,
+        commutation_pct: f64,
+        commutation_factor: f64,
+        revaluation_cap: f64,
+        schedule: Schedule,
+        source: D,
+    ) -> Self {
+        Self {
+            accrued,
+            salary,
+            accrual_rate,
+            normal_pension_date,
+            retirement_date,
+            early_retirement_factor,
+            late_retirement_factor,
+            commutation_pct,
+            commutation_factor,
+            revaluation_cap,
+            in_payment: false,
+            schedule,
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AnnuityAmount, AnnuityIndexation, AnnuityPurchase, Child, DefinedBenefit,
+        EmployerContribution,
+    };
+    use crate::input::HashMapSourceSim;
+    use crate::mortality::{LifeTable, LifeTableBasis, Sex};
+
+    type DB = DefinedBenefit<HashMapSourceSim>;
+
+    #[test]
+    fn test_that_employer_contribution_rules_calculate() {
+        let flat = EmployerContribution::Flat(0.05);
+        assert!(flat.pct(&0.0) == 0.05);
+
+        //Matches 50% of employee contribution up to 4%
+        let matched = EmployerContribution::Matched(0.5, 0.04);
+        assert!(matched.pct(&0.04) == 0.02);
+        assert!(matched.pct(&0.10) == 0.04);
+
+        let tiered = EmployerContribution::Tiered(vec![(0.03, 0.03), (0.05, 0.06), (0.08, 0.10)]);
+        assert!(tiered.pct(&0.02) == 0.0);
+        assert!(tiered.pct(&0.06) == 0.06);
+        assert!(tiered.pct(&0.08) == 0.10);
+    }
+
+    #[test]
+    fn test_that_child_benefit_stops_at_end_age() {
+        //Born 1990-06-15
+        let child = Child::new(645_408_000.into(), 16);
+        //Before birth, at 15 and at 16
+        assert!(!child.is_eligible(&0));
+        assert!(child.is_eligible(&1_118_793_600));
+        assert!(!child.is_eligible(&1_150_329_600));
+    }
+
+    #[test]
+    fn test_that_defined_benefit_adjusts_for_retirement_date() {
+        //Two years early at 4% a year, one year late at 5% a year
+        let early = DB::retirement_factor_logic(&2.0, &0.04, &0.05);
+        let late = DB::retirement_factor_logic(&-1.0, &0.04, &0.05);
+        assert!((early - 0.92).abs() < 0.0001);
+        assert!((late - 1.05).abs() < 0.0001);
+
+        //Quarter of pension given up at 12:1
+        let (pension, lump_sum) = DB::commutation_logic(&20_000.0, &0.25, &12.0);
+        assert!(pension == 15_000.0);
+        assert!(lump_sum == 60_000.0);
+    }
+
+    #[test]
+    fn test_that_rpi_linked_annuity_pays_less_initially() {
+        let purchase = |indexation| {
+            AnnuityPurchase::new(
+                0.into(),
+                -315_619_200,
+                AnnuityAmount::PctOfSipp(0.5),
+                indexation,
+                LifeTable::new(Sex::Male, LifeTableBasis::Cohort),
+            )
+        };
+        let level = purchase(AnnuityIndexation::Level);
+        let rpi = purchase(AnnuityIndexation::Rpi);
+        assert!(level.amount(&100_000.0) == 50_000.0);
+
+        //Purchased at 65 in 2025
+        let date = 1_735_689_600;
+        let level_income = level.annual_income(&100_000.0, &date, &0.045, &0.03);
+        let rpi_income = rpi.annual_income(&100_000.0, &date, &0.045, &0.03);
+        assert!(rpi_income < level_income);
+        assert!(level_income > 0.0);
+    }
+}
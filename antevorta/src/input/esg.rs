@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use alator::{broker::Quote, clock::Clock, input::QuotesHashMap};
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::parametric::{cholesky, CapitalMarketAssumptions};
+use super::SimDataRep;
+
+//Annual inflation follows an AR(1) around the mean, vol is the unconditional volatility of annual
+//inflation so the shock is scaled down as persistence rises
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InflationParams {
+    pub mean: f64,
+    pub vol: f64,
+    pub persistence: f64,
+}
+
+impl InflationParams {
+    pub fn next(&self, prev: &f64, shock: &f64) -> f64 {
+        let shock_vol = self.vol * (1.0 - self.persistence.powi(2)).max(0.0).sqrt();
+        self.mean + (self.persistence * (prev - self.mean)) + (shock_vol * shock)
+    }
+}
+
+//Correlation is between the inflation shock and annual asset returns, ordered in the same way as
+//the symbols. Correlation between assets is taken from the assumptions.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EconomicScenarioParams {
+    pub inflation: InflationParams,
+    pub assets: CapitalMarketAssumptions,
+    pub inflation_correlation: Vec<f64>,
+}
+
+impl EconomicScenarioParams {
+    //Inflation is first in the joint matrix
+    fn joint_correlation(&self) -> Vec<Vec<f64>> {
+        let n = self.assets.symbols.len();
+        if self.inflation_correlation.len() != n {
+            panic!("Inflation correlation must have a value for every symbol");
+        }
+        let mut res = vec![vec![0.0; n + 1]; n + 1];
+        res[0][0] = 1.0;
+        for (i, corr) in self.inflation_correlation.iter().enumerate() {
+            res[0][i + 1] = *corr;
+            res[i + 1][0] = *corr;
+            res[i + 1][1..].copy_from_slice(&self.assets.correlation[i]);
+        }
+        res
+    }
+}
+
+fn to_daily(annual: f64) -> f64 {
+    ((1.0 + annual).powf(1.0 / 365.0)) - 1.0
+}
+
+//Inflation is drawn once for each calendar year, starting from the mean, and compounded daily in
+//the same way as `daily_data_generator_static`
+pub fn daily_data_generator_inflation_ar1(
+    params: &InflationParams,
+    clock: Clock,
+    rng: &mut impl Rng,
+) -> SimDataRep {
+    let mut res = HashMap::new();
+    let mut year: Option<i32> = None;
+    let mut inflation = params.mean;
+    for date in clock.borrow().peek() {
+        let offset: OffsetDateTime = date.clone().into();
+        if year != Some(offset.year()) {
+            let shock: f64 = StandardNormal.sample(rng);
+            inflation = params.next(&inflation, &shock);
+        }
+        year = Some(offset.year());
+        res.insert(date, to_daily(inflation));
+    }
+    res
+}
+
+//The annual inflation shock is spread evenly across the days of the year so that annual returns,
+//rather than daily returns, have the correlation given. Prices start at 100.
+pub fn economic_scenario_generator(
+    params: &EconomicScenarioParams,
+    clock: Clock,
+    rng: &mut impl Rng,
+) -> (QuotesHashMap, SimDataRep) {
+    let symbols = &params.assets.symbols;
+    let factor = cholesky(&params.joint_correlation());
+    let drift = params.assets.daily_log_drift();
+    let days_sqrt = 365.0_f64.sqrt();
+
+    let mut quotes: QuotesHashMap = HashMap::new();
+    let mut inflation = HashMap::new();
+    let mut prices = vec![100.0; symbols.len()];
+
+    let mut year: Option<i32> = None;
+    let mut inflation_shock: f64 = 0.0;
+    let mut annual_inflation = params.inflation.mean;
+    for date in clock.borrow().peek() {
+        let offset: OffsetDateTime = date.clone().into();
+        if year != Some(offset.year()) {
+            inflation_shock = StandardNormal.sample(rng);
+            annual_inflation = params.inflation.next(&annual_inflation, &inflation_shock);
+        }
+        year = Some(offset.year());
+
+        let day_quotes = symbols
+            .iter()
+            .zip(prices.iter())
+            .map(|(symbol, price)| Quote::new(*price, *price, date.clone(), symbol))
+            .collect();
+        quotes.insert(date.clone(), day_quotes);
+        inflation.insert(date, to_daily(annual_inflation));
+
+        let shocks: Vec<f64> = (0..symbols.len())
+            .map(|_| StandardNormal.sample(rng))
+            .collect();
+        for (i, price) in prices.iter_mut().enumerate() {
+            let row = &factor[i + 1];
+            let common = row[0] * inflation_shock / days_sqrt;
+            let own: f64 = (0..=i).map(|j| row[j + 1] * shocks[j]).sum();
+            let vol_daily = params.assets.vols[i] / days_sqrt;
+            *price *= (drift[i] + (vol_daily * (common + own))).exp();
+        }
+    }
+    (quotes, inflation)
+}
+
+#[cfg(test)]
+mod tests {
+    use alator::clock::ClockBuilder;
+    use alator::types::Frequency;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::{BTreeMap, HashMap};
+    use time::OffsetDateTime;
+
+    use super::{
+        daily_data_generator_inflation_ar1, economic_scenario_generator, EconomicScenarioParams,
+        InflationParams,
+    };
+    use crate::input::{daily_data_generator_parametric_normal, CapitalMarketAssumptions};
+    use alator::types::DateTime;
+
+    //Compounds daily values within each calendar year
+    fn annual(series: &HashMap<DateTime, f64>) -> Vec<f64> {
+        let mut years: BTreeMap<i32, f64> = BTreeMap::new();
+        for (date, val) in series {
+            let offset: OffsetDateTime = date.clone().into();
+            let year = years.entry(offset.year()).or_insert(1.0);
+            *year *= 1.0 + val;
+        }
+        years.values().map(|v| v - 1.0).collect()
+    }
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    fn std(values: &[f64]) -> f64 {
+        let m = mean(values);
+        (values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+    }
+
+    fn corr(x: &[f64], y: &[f64]) -> f64 {
+        let (mx, my) = (mean(x), mean(y));
+        let cov = x.iter().zip(y.iter()).map(|(a, b)| (a - mx) * (b - my)).sum::<f64>()
+            / x.len() as f64;
+        cov / (std(x) * std(y))
+    }
+
+    #[test]
+    fn test_that_parametric_normal_uses_variance() {
+        let clock = ClockBuilder::with_length_in_days(0, 365 * 1000)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let series = daily_data_generator_parametric_normal(0.02, 0.0004, clock);
+        let years = annual(&series);
+        assert!((mean(&years) - 0.02).abs() < 0.003);
+        assert!((std(&years) - 0.02).abs() < 0.003);
+    }
+
+    #[test]
+    fn test_that_ar1_inflation_matches_inputs() {
+        let clock = ClockBuilder::with_length_in_days(0, 365 * 1000)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let params = InflationParams {
+            mean: 0.03,
+            vol: 0.02,
+            persistence: 0.6,
+        };
+        let mut rng = StdRng::seed_from_u64(10);
+        let series = daily_data_generator_inflation_ar1(&params, clock, &mut rng);
+        let years = annual(&series);
+
+        assert!((mean(&years) - 0.03).abs() < 0.005);
+        assert!((std(&years) - 0.02).abs() < 0.003);
+        let autocorr = corr(&years[1..], &years[..years.len() - 1]);
+        assert!((autocorr - 0.6).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_that_asset_returns_are_correlated_with_inflation() {
+        let clock = ClockBuilder::with_length_in_days(0, 365 * 500)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let params = EconomicScenarioParams {
+            inflation: InflationParams {
+                mean: 0.03,
+                vol: 0.03,
+                persistence: 0.0,
+            },
+            assets: CapitalMarketAssumptions {
+                symbols: vec!["EQUITY".to_string(), "BOND".to_string()],
+                expected_returns: vec![0.07, 0.03],
+                vols: vec![0.2, 0.08],
+                correlation: vec![vec![1.0, 0.2], vec![0.2, 1.0]],
+            },
+            inflation_correlation: vec![-0.3, -0.6],
+        };
+        let mut rng = StdRng::seed_from_u64(10);
+        let (quotes, inflation) = economic_scenario_generator(&params, clock, &mut rng);
+
+        //Price on the first day of each year
+        let mut year_start: BTreeMap<i32, (f64, f64)> = BTreeMap::new();
+        for (date, day_quotes) in &quotes {
+            let offset: OffsetDateTime = date.clone().into();
+            if offset.ordinal() == 1 {
+                year_start.insert(offset.year(), (*day_quotes[0].bid, *day_quotes[1].bid));
+            }
+        }
+        let prices: Vec<(f64, f64)> = year_start.values().cloned().collect();
+        let equity: Vec<f64> = prices.windows(2).map(|p| (p[1].0 / p[0].0) - 1.0).collect();
+        let bond: Vec<f64> = prices.windows(2).map(|p| (p[1].1 / p[0].1) - 1.0).collect();
+        let years = annual(&inflation);
+        let years = &years[..equity.len()];
+
+        assert!((mean(years) - 0.03).abs() < 0.005);
+        assert!((std(years) - 0.03).abs() < 0.005);
+        assert!((std(&equity) - 0.2).abs() < 0.03);
+        assert!((std(&bond) - 0.08).abs() < 0.01);
+        assert!((corr(&equity, years) + 0.3).abs() < 0.15);
+        assert!((corr(&bond, years) + 0.6).abs() < 0.15);
+        assert!((corr(&equity, &bond) - 0.2).abs() < 0.15);
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use alator::{broker::Quote, clock::Clock, input::QuotesHashMap};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Error;
+use time::OffsetDateTime;
+
+use super::{HashMapSourceSim, HashMapSourceSimBuilder, SimDataRep};
+
+//Jordà-Schularick-Taylor Macrohistory Database, GDP-weighted across 18 countries from 1871 to
+//2020. Returns are annual and nominal in local currency. Scripts that build these are in data/jst.
+const WORLD_EQUITY: &str = include_str!("../../../data/jst/world_equity.json");
+const WORLD_BOND: &str = include_str!("../../../data/jst/world_bond.json");
+const WORLD_MACRO: &str = include_str!("../../../data/jst/world_macro.json");
+
+pub const JST_EQUITY: &str = "JST_EQUITY";
+pub const JST_BOND: &str = "JST_BOND";
+
+#[derive(Clone, Debug, Deserialize)]
+struct JstReturn {
+    year: i32,
+    nom: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct JstMacro {
+    year: i32,
+    inflation: f64,
+    rate: f64,
+}
+
+//Joint observation for one historical year
+#[derive(Clone, Debug)]
+pub struct JstYear {
+    pub year: i32,
+    pub equity: f64,
+    pub bond: f64,
+    pub inflation: f64,
+    pub rate: f64,
+}
+
+//Restricts the years sampled from, blocks of consecutive years keep some of the persistence in
+//inflation and rates
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct JstParams {
+    pub start_year: Option<i32>,
+    pub end_year: Option<i32>,
+    //Defaults to one, each simulated year is drawn independently
+    pub block_length: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct JstHistory {
+    years: Vec<JstYear>,
+}
+
+impl JstHistory {
+    pub fn years(&self) -> &[JstYear] {
+        &self.years
+    }
+
+    //Years are drawn in blocks with replacement, a block that runs off the end of the history
+    //wraps around to the start
+    pub fn sample(&self, count: usize, params: &JstParams, rng: &mut impl Rng) -> Vec<JstYear> {
+        let years: Vec<&JstYear> = self
+            .years
+            .iter()
+            .filter(|y| params.start_year.is_none_or(|start| y.year >= start))
+            .filter(|y| params.end_year.is_none_or(|end| y.year <= end))
+            .collect();
+        if years.is_empty() {
+            panic!("No JST history in the years given");
+        }
+
+        let block_length = params.block_length.unwrap_or(1).max(1);
+        let mut res = Vec::with_capacity(count);
+        while res.len() < count {
+            let start = rng.gen_range(0..years.len());
+            for pos in 0..block_length {
+                if res.len() == count {
+                    break;
+                }
+                res.push(years[(start + pos) % years.len()].clone());
+            }
+        }
+        res
+    }
+
+    pub fn world() -> Self {
+        Self::parse(WORLD_EQUITY, WORLD_BOND, WORLD_MACRO).unwrap()
+    }
+
+    //Only years that are in every series are kept
+    pub fn parse(equity: &str, bond: &str, macro_data: &str) -> Result<Self, Error> {
+        let equity: Vec<JstReturn> = serde_json::from_str(equity)?;
+        let bond: HashMap<i32, f64> = serde_json::from_str::<Vec<JstReturn>>(bond)?
+            .into_iter()
+            .map(|r| (r.year, r.nom))
+            .collect();
+        let macro_data: HashMap<i32, JstMacro> =
+            serde_json::from_str::<Vec<JstMacro>>(macro_data)?
+                .into_iter()
+                .map(|m| (m.year, m))
+                .collect();
+
+        let mut years = Vec::new();
+        for eq in equity {
+            if let (Some(bond), Some(m)) = (bond.get(&eq.year), macro_data.get(&eq.year)) {
+                years.push(JstYear {
+                    year: eq.year,
+                    equity: eq.nom,
+                    bond: *bond,
+                    inflation: m.inflation,
+                    rate: m.rate,
+                });
+            }
+        }
+        years.sort_by_key(|y| y.year);
+        Ok(Self { years })
+    }
+}
+
+fn to_daily(annual: f64) -> f64 {
+    ((1.0 + annual).powf(1.0 / 365.0)) - 1.0
+}
+
+//Each calendar year in the clock takes the next sampled year. Returns and inflation are
+//compounded daily in the same way as `daily_data_generator_static`, rates are the annual rate on
+//each day. Prices start at 100.
+pub fn jst_data_generator(
+    sampled: &[JstYear],
+    clock: Clock,
+) -> (QuotesHashMap, SimDataRep, SimDataRep) {
+    let mut quotes: QuotesHashMap = HashMap::new();
+    let mut inflation = HashMap::new();
+    let mut rates = HashMap::new();
+
+    let mut year: Option<i32> = None;
+    let mut pos = 0;
+    let mut equity_price = 100.0;
+    let mut bond_price = 100.0;
+    for date in clock.borrow().peek() {
+        let offset: OffsetDateTime = date.clone().into();
+        if year.is_some() && year != Some(offset.year()) {
+            pos += 1;
+        }
+        year = Some(offset.year());
+        let curr = &sampled[pos.min(sampled.len() - 1)];
+
+        let q_equity = Quote::new(equity_price, equity_price, date.clone(), JST_EQUITY);
+        let q_bond = Quote::new(bond_price, bond_price, date.clone(), JST_BOND);
+        quotes.insert(date.clone(), vec![q_equity, q_bond]);
+        inflation.insert(date.clone(), to_daily(curr.inflation));
+        rates.insert(date, curr.rate);
+
+        equity_price *= 1.0 + to_daily(curr.equity);
+        bond_price *= 1.0 + to_daily(curr.bond);
+    }
+    (quotes, inflation, rates)
+}
+
+//Quotes are given for `JST_EQUITY` and `JST_BOND`, house prices are flat
+pub fn build_hashmapsource_jst(
+    clock: Clock,
+    history: &JstHistory,
+    params: &JstParams,
+) -> HashMapSourceSim {
+    let first = clock.borrow().now();
+    let last = clock.borrow().peek().last().cloned().unwrap_or_else(|| first.clone());
+    let first_year = OffsetDateTime::from(first).year();
+    let last_year = OffsetDateTime::from(last).year();
+    let count = (last_year - first_year + 1) as usize;
+
+    let sampled = history.sample(count, params, &mut thread_rng());
+    let (quotes, inflation, rates) = jst_data_generator(&sampled, Rc::clone(&clock));
+    let house_price_rets = super::daily_data_generator_static(0.0, Rc::clone(&clock));
+
+    HashMapSourceSimBuilder::start()
+        .with_clock(Rc::clone(&clock))
+        .with_rates(rates)
+        .with_inflation(inflation)
+        .with_house_prices(house_price_rets)
+        .with_quotes(quotes)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use alator::clock::ClockBuilder;
+    use alator::input::DataSource;
+    use alator::types::Frequency;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::rc::Rc;
+
+    use super::{jst_data_generator, JstHistory, JstParams, JstYear, JST_BOND, JST_EQUITY};
+    use crate::input::{HashMapSourceSimBuilder, SimDataSource};
+
+    #[test]
+    fn test_that_world_history_loads() {
+        let history = JstHistory::world();
+        assert!(history.years().len() > 140);
+        assert!(history.years().first().unwrap().year == 1871);
+        //Equity returns are higher than bond returns over the long run
+        let years = history.years().len() as f64;
+        let equity: f64 = history.years().iter().map(|y| y.equity).sum::<f64>() / years;
+        let bond: f64 = history.years().iter().map(|y| y.bond).sum::<f64>() / years;
+        assert!(equity > bond);
+    }
+
+    #[test]
+    fn test_that_sample_keeps_blocks_and_window() {
+        let history = JstHistory::world();
+        let params = JstParams {
+            start_year: Some(1950),
+            end_year: Some(2000),
+            block_length: Some(5),
+        };
+        let mut rng = StdRng::seed_from_u64(10);
+        let sampled = history.sample(12, &params, &mut rng);
+        assert!(sampled.len() == 12);
+        assert!(sampled.iter().all(|y| y.year >= 1950 && y.year <= 2000));
+        //Within a block years are consecutive unless the block wraps
+        for pos in 1..5 {
+            let diff = sampled[pos].year - sampled[pos - 1].year;
+            assert!(diff == 1 || diff == -50);
+        }
+    }
+
+    #[test]
+    fn test_that_annual_returns_are_served_daily() {
+        //Clock covers the whole of 1970 and 1971
+        let clock = ClockBuilder::with_length_in_days(0, 729)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let sampled = vec![
+            JstYear {
+                year: 1974,
+                equity: -0.3,
+                bond: 0.05,
+                inflation: 0.15,
+                rate: 0.1,
+            },
+            JstYear {
+                year: 1975,
+                equity: 0.4,
+                bond: 0.1,
+                inflation: 0.2,
+                rate: 0.08,
+            },
+        ];
+        let (quotes, inflation, rates) = jst_data_generator(&sampled, Rc::clone(&clock));
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_quotes(quotes)
+            .with_inflation(inflation)
+            .with_rates(rates)
+            .build();
+
+        assert!(source.get_current_interest_rate().unwrap() == 0.1);
+        for _i in 0..365 {
+            clock.borrow_mut().tick();
+        }
+        let equity = source.get_quote(JST_EQUITY).unwrap();
+        let bond = source.get_quote(JST_BOND).unwrap();
+        assert!((*equity.bid - 70.0).abs() < 0.001);
+        assert!((*bond.bid - 105.0).abs() < 0.001);
+        assert!(source.get_current_interest_rate().unwrap() == 0.08);
+    }
+}
@@ -7,12 +7,47 @@ use alator::{
     input::{DataSource, DividendsHashMap, QuotesHashMap},
     types::DateTime,
 };
-use rand::thread_rng;
-use rand_distr::{Distribution, Normal};
-use time::OffsetDateTime;
+use rand::{thread_rng, Rng};
+use rand_distr::{Distribution, Normal, StandardNormal};
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, OffsetDateTime};
+
+mod esg;
+mod jst;
+mod parametric;
+mod scenario;
+mod vec;
+
+pub use self::esg::{
+    daily_data_generator_inflation_ar1, economic_scenario_generator, EconomicScenarioParams,
+    InflationParams,
+};
+pub use self::jst::{
+    build_hashmapsource_jst, jst_data_generator, JstHistory, JstParams, JstYear, JST_BOND,
+    JST_EQUITY,
+};
+pub use self::parametric::{cholesky, daily_quote_generator, CapitalMarketAssumptions, ReturnModel};
+pub use self::scenario::StressScenario;
+pub use self::vec::VecSourceSim;
 
 type SimDataRep = HashMap<DateTime, f64>;
 
+const SECONDS_IN_DAY: i64 = 86_400;
+
+//Same day of the month, clamped to the end of shorter months, so that trailing periods don't fail
+//on the 31st or the 29th of February
+fn months_before(date: &DateTime, months: i32) -> DateTime {
+    let offset: OffsetDateTime = date.clone().into();
+    let total = (offset.year() * 12) + (offset.month() as i32 - 1) - months;
+    let year = total.div_euclid(12);
+    let month = Month::try_from((total.rem_euclid(12) + 1) as u8).unwrap();
+    let date = (1..=offset.day())
+        .rev()
+        .find_map(|day| Date::from_calendar_date(year, month, day).ok())
+        .unwrap();
+    offset.replace_date(date).into()
+}
+
 //Data layout always has daily frequency but the series below typically occur at a higher
 //frequency. In order to keep these at a daily frequency we downsample them and expect them to
 //increment (and be called) on a daily frequency, even in flows which typically only activate per month.
@@ -54,38 +89,12 @@ impl SimDataSource for HashMapSourceSim {
 
     fn get_trailing_month_inflation(&self) -> f64 {
         let now = self.inner.clock.borrow().now();
-        let now_offset: OffsetDateTime = now.clone().into();
-
-        let last_month = now_offset.month().previous();
-        let last_month_offset = now_offset.replace_month(last_month).unwrap();
-        let last_month_internal: DateTime = last_month_offset.into();
-
-        let mut tmp = 1.0;
-        for date in self.inner.clock.borrow().peek() {
-            if date > last_month_internal && date < now {
-                //peek returns values that definitely exist so we can unwrap safely
-                let val = self.inner.inflation.get(&now).unwrap().clone();
-                tmp *= 1.0 + val;
-            }
-        }
-        (tmp / 1.0) - 1.0
+        self.trailing_inflation(&months_before(&now, 1), &now)
     }
 
     fn get_trailing_year_inflation(&self) -> f64 {
         let now = self.inner.clock.borrow().now();
-        let now_offset: OffsetDateTime = now.clone().into();
-        let last_year = now_offset.replace_year(now_offset.year() - 1).unwrap();
-        let last_year_internal: DateTime = last_year.into();
-
-        let mut tmp = 1.0;
-        for date in self.inner.clock.borrow().peek() {
-            if date > last_year_internal && date < now {
-                //peek returns values that definitely exist so we can unwrap safely
-                let val = self.inner.inflation.get(&now).unwrap().clone();
-                tmp *= 1.0 + val;
-            }
-        }
-        (tmp / 1.0) - 1.0
+        self.trailing_inflation(&months_before(&now, 12), &now)
     }
 
     fn get_current_inflation(&self) -> Option<f64> {
@@ -124,6 +133,25 @@ impl DataSource for HashMapSourceSim {
 }
 
 impl HashMapSourceSim {
+    //Inflation on each date is a daily rate that holds until the next date, so this also works when
+    //the clock isn't daily. Only days after the start and before the end are counted.
+    fn trailing_inflation(&self, start: &DateTime, end: &DateTime) -> f64 {
+        let dates = self.inner.clock.borrow().peek();
+        let first_day = **start + SECONDS_IN_DAY;
+
+        let mut tmp = 1.0;
+        for (pos, date) in dates.iter().enumerate() {
+            let next = dates.get(pos + 1).map_or(**date + SECONDS_IN_DAY, |next| **next);
+            let days = (next.min(**end) - (**date).max(first_day)) / SECONDS_IN_DAY;
+            if days > 0 {
+                //peek returns values that definitely exist so we can unwrap safely
+                let val = *self.inner.inflation.get(date).unwrap();
+                tmp *= (1.0 + val).powi(days as i32);
+            }
+        }
+        (tmp / 1.0) - 1.0
+    }
+
     pub fn new(
         clock: Clock,
         inflation: SimDataRep,
@@ -237,6 +265,225 @@ pub fn build_hashmapsource_with_quotes_with_inflation(
         .build()
 }
 
+//Rates and house prices are static at zero unless parameters are given
+pub fn build_hashmapsource_with_quotes_with_inflation_with_params(
+    clock: Clock,
+    quotes: QuotesHashMap,
+    dividends: DividendsHashMap,
+    annual_inflation_mu: f64,
+    annual_inflation_var: f64,
+    short_rate: Option<&ShortRateParams>,
+    house_prices: Option<&HousePriceParams>,
+) -> HashMapSourceSim {
+    let (inflation, rates) = match short_rate {
+        Some(short_rate) => daily_data_generator_inflation_with_short_rate(
+            annual_inflation_mu,
+            annual_inflation_var,
+            short_rate,
+            Rc::clone(&clock),
+        ),
+        None => (
+            daily_data_generator_parametric_normal(
+                annual_inflation_mu,
+                annual_inflation_var,
+                Rc::clone(&clock),
+            ),
+            daily_data_generator_static(0.0, Rc::clone(&clock)),
+        ),
+    };
+    let house_price_rets = match house_prices {
+        Some(house_prices) => daily_data_generator_house_prices(house_prices, Rc::clone(&clock)),
+        None => daily_data_generator_static(0.0, Rc::clone(&clock)),
+    };
+
+    HashMapSourceSimBuilder::start()
+        .with_clock(Rc::clone(&clock))
+        .with_rates(rates)
+        .with_inflation(inflation)
+        .with_house_prices(house_price_rets)
+        .with_quotes(quotes)
+        .with_dividends(dividends)
+        .build()
+}
+
+//Moves a source onto a clock with fewer dates, such as a monthly clock, so that both can be built
+//from the same data. Each date covers the days of the source up to the next date: house price
+//returns are compounded, dividends are paid together, and inflation is the daily rate that
+//compounds to the same value over the period. Quotes and rates are taken on the date, so every
+//date in the clock must be in the source.
+pub fn build_hashmapsource_for_clock(source: &HashMapSourceSim, clock: Clock) -> HashMapSourceSim {
+    let source_dates = source.inner.clock.borrow().peek();
+    let dates = clock.borrow().peek();
+
+    let mut inflation = HashMap::new();
+    let mut rates = HashMap::new();
+    let mut house_price_rets = HashMap::new();
+    let mut quotes: QuotesHashMap = HashMap::new();
+    let mut dividends: DividendsHashMap = HashMap::new();
+
+    let mut pos = 0;
+    for (i, date) in dates.iter().enumerate() {
+        while pos < source_dates.len() && source_dates[pos] < *date {
+            pos += 1;
+        }
+
+        let mut inflation_growth: f64 = 1.0;
+        let mut house_price_growth = 1.0;
+        let mut days = 0;
+        let mut period_dividends: Vec<Dividend> = Vec::new();
+        while pos < source_dates.len()
+            && dates.get(i + 1).is_none_or(|next| source_dates[pos] < *next)
+        {
+            let source_date = &source_dates[pos];
+            inflation_growth *= 1.0 + source.inner.inflation.get(source_date).unwrap_or(&0.0);
+            house_price_growth *=
+                1.0 + source.inner.house_price_rets.get(source_date).unwrap_or(&0.0);
+            if let Some(paid) = source.inner.dividends.get(source_date) {
+                period_dividends.extend(paid.iter().cloned());
+            }
+            days += 1;
+            pos += 1;
+        }
+
+        if days > 0 {
+            let daily_inflation = inflation_growth.powf(1.0 / days as f64) - 1.0;
+            inflation.insert(date.clone(), daily_inflation);
+            house_price_rets.insert(date.clone(), house_price_growth - 1.0);
+        }
+        if let Some(rate) = source.inner.rates.get(date) {
+            rates.insert(date.clone(), *rate);
+        }
+        if let Some(date_quotes) = source.inner.quotes.get(date) {
+            quotes.insert(date.clone(), date_quotes.clone());
+        }
+        if !period_dividends.is_empty() {
+            dividends.insert(date.clone(), period_dividends);
+        }
+    }
+
+    HashMapSourceSimBuilder::start()
+        .with_clock(Rc::clone(&clock))
+        .with_rates(rates)
+        .with_inflation(inflation)
+        .with_house_prices(house_price_rets)
+        .with_quotes(quotes)
+        .with_dividends(dividends)
+        .build()
+}
+
+//Annual house price returns
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum HousePriceParams {
+    Static(f64),
+    Parametric { mu: f64, var: f64 },
+    //Each calendar year draws a return, with replacement, from the history given
+    Bootstrap(Vec<f64>),
+}
+
+pub fn daily_data_generator_house_prices(params: &HousePriceParams, clock: Clock) -> SimDataRep {
+    match params {
+        HousePriceParams::Static(mu) => daily_data_generator_static(*mu, clock),
+        HousePriceParams::Parametric { mu, var } => {
+            daily_data_generator_parametric_normal(*mu, *var, clock)
+        }
+        HousePriceParams::Bootstrap(history) => daily_data_generator_bootstrap(history, clock),
+    }
+}
+
+pub fn daily_data_generator_bootstrap(history: &[f64], clock: Clock) -> SimDataRep {
+    //Annual return is reduced down to a compounded daily equivalent in the same way as
+    //`daily_data_generator_static`, the return is held for the whole calendar year
+    let mut res = HashMap::new();
+    if history.is_empty() {
+        return daily_data_generator_static(0.0, clock);
+    }
+
+    let mut rng = thread_rng();
+    let mut year: Option<i32> = None;
+    let mut mu_daily = 0.0;
+    for date in clock.borrow().peek() {
+        let offset: OffsetDateTime = date.clone().into();
+        if year != Some(offset.year()) {
+            year = Some(offset.year());
+            let annual = history[rng.gen_range(0..history.len())];
+            mu_daily = ((1.0 + annual).powf(1.0 / 365.0)) - 1.0;
+        }
+        res.insert(date, mu_daily);
+    }
+    res
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum ShortRateModel {
+    Vasicek,
+    //Volatility scales with the square root of the rate so rates stay positive
+    CIR,
+}
+
+//Parameters are annual, rates are stored as an annual rate on each day
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShortRateParams {
+    pub model: ShortRateModel,
+    pub initial: f64,
+    pub mean_reversion: f64,
+    pub long_run_mean: f64,
+    pub vol: f64,
+    //Correlation between the daily shocks to inflation and rates
+    pub inflation_correlation: Option<f64>,
+}
+
+impl ShortRateParams {
+    //Euler step over one day with the shock given
+    pub fn step(&self, rate: &f64, shock: &f64) -> f64 {
+        let dt = 1.0 / 365.0;
+        let drift = self.mean_reversion * (self.long_run_mean - rate) * dt;
+        let diffusion = match self.model {
+            ShortRateModel::Vasicek => self.vol,
+            ShortRateModel::CIR => self.vol * rate.max(0.0).sqrt(),
+        };
+        let next = rate + drift + (diffusion * dt.sqrt() * shock);
+        match self.model {
+            ShortRateModel::Vasicek => next,
+            ShortRateModel::CIR => next.max(0.0),
+        }
+    }
+}
+
+//Inflation is drawn in the same way as `daily_data_generator_parametric_normal`, the shock to
+//rates is correlated with the shock to inflation
+pub fn daily_data_generator_inflation_with_short_rate(
+    inflation_mu_annual: f64,
+    inflation_var_annual: f64,
+    short_rate: &ShortRateParams,
+    clock: Clock,
+) -> (SimDataRep, SimDataRep) {
+    let mut inflation = HashMap::new();
+    let mut rates = HashMap::new();
+
+    let mu_daily = if inflation_mu_annual == 0.0 {
+        0.0
+    } else {
+        ((1.0 + inflation_mu_annual).powf(1.0 / 365.0)) - 1.0
+    };
+    //Daily shocks sum to the annual variance
+    let std_daily = (inflation_var_annual / 365.0).sqrt();
+    let correlation = short_rate.inflation_correlation.unwrap_or(0.0);
+
+    let mut rng = thread_rng();
+    let mut rate = short_rate.initial;
+    for date in clock.borrow().peek() {
+        let inflation_shock: f64 = StandardNormal.sample(&mut rng);
+        let independent_shock: f64 = StandardNormal.sample(&mut rng);
+        let rate_shock = (correlation * inflation_shock)
+            + ((1.0 - correlation.powi(2)).sqrt() * independent_shock);
+
+        inflation.insert(date.clone(), mu_daily + (std_daily * inflation_shock));
+        rates.insert(date, rate);
+        rate = short_rate.step(&rate, &rate_shock);
+    }
+    (inflation, rates)
+}
+
 pub fn daily_data_generator_static(mu_annual: f64, clock: Clock) -> SimDataRep {
     //Inflation data is received at an annual frequency, and then is reduced down to a compounded
     //daily equivalent. This calculates inflation over all days including weekends. This will very
@@ -281,10 +528,11 @@ pub fn daily_data_generator_parametric_normal(
         return daily_data_generator_static(mu_annual, clock);
     }
 
-    let var_daily = var_annual / 365.0_f64.sqrt();
+    //Normal takes the standard deviation, daily shocks sum to the annual variance
+    let std_daily = (var_annual / 365.0).sqrt();
 
     let mut rng = thread_rng();
-    let dist = Normal::new(mu_daily, var_daily).unwrap();
+    let dist = Normal::new(mu_daily, std_daily).unwrap();
 
     //Sets value on every day using daily-compounded growth
     for date in clock.borrow().peek() {
@@ -325,3 +573,159 @@ pub fn build_hashmapsource_random(clock: Clock) -> HashMapSourceSim {
         .with_quotes(fake_data)
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use alator::clock::ClockBuilder;
+    use alator::types::Frequency;
+    use std::collections::HashSet;
+    use std::rc::Rc;
+    use time::OffsetDateTime;
+
+    use super::{
+        build_hashmapsource_for_clock, build_hashmapsource_random, daily_data_generator_bootstrap,
+        daily_data_generator_static, HashMapSourceSimBuilder, ShortRateModel, ShortRateParams,
+        SimDataSource,
+    };
+    use crate::schedule::{build_clock, Step};
+    use alator::input::DataSource;
+
+    #[test]
+    fn test_that_bootstrap_holds_return_for_calendar_year() {
+        let clock = ClockBuilder::with_length_in_days(1, 365 * 3)
+            .with_frequency(&Frequency::Daily)
+            .build();
+
+        //Single return is the same as the static generator
+        let single = daily_data_generator_bootstrap(&[0.05], Rc::clone(&clock));
+        let fixed = daily_data_generator_static(0.05, Rc::clone(&clock));
+        assert!(single == fixed);
+
+        let res = daily_data_generator_bootstrap(&[0.1, -0.1], Rc::clone(&clock));
+        for year in 1970..1973 {
+            let returns: HashSet<u64> = res
+                .iter()
+                .filter(|(date, _ret)| {
+                    let offset: OffsetDateTime = (*date).clone().into();
+                    offset.year() == year
+                })
+                .map(|(_date, ret)| ret.to_bits())
+                .collect();
+            assert!(returns.len() == 1);
+        }
+    }
+
+    #[test]
+    fn test_that_trailing_inflation_uses_each_day() {
+        //Starts on the 31st of January 1970, no inflation in the first year and then 0.1% a day
+        let clock = ClockBuilder::with_length_in_days(30 * 86_400, 400)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let start = *clock.borrow().now();
+        let inflation = clock
+            .borrow()
+            .peek()
+            .into_iter()
+            .map(|date| {
+                let value = if *date - start >= 365 * 86_400 { 0.001 } else { 0.0 };
+                (date, value)
+            })
+            .collect();
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_inflation(inflation)
+            .build();
+
+        //Passes the end of March, which has no matching day in February
+        for _i in 0..365 {
+            source.get_trailing_month_inflation();
+            clock.borrow_mut().tick();
+        }
+        //Inflation on the current day isn't used for the days before it
+        assert!(source.get_current_inflation().unwrap() == 0.001);
+        assert!(source.get_trailing_year_inflation() == 0.0);
+        assert!(source.get_trailing_month_inflation() == 0.0);
+
+        for _i in 0..10 {
+            clock.borrow_mut().tick();
+        }
+        let expected = 1.001_f64.powi(10) - 1.0;
+        assert!((source.get_trailing_month_inflation() - expected).abs() < 1e-12);
+        assert!((source.get_trailing_year_inflation() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_that_short_rate_reverts_to_mean() {
+        let params = |model| ShortRateParams {
+            model,
+            initial: 0.08,
+            mean_reversion: 0.5,
+            long_run_mean: 0.03,
+            vol: 0.0,
+            inflation_correlation: None,
+        };
+
+        for model in [ShortRateModel::Vasicek, ShortRateModel::CIR] {
+            let params = params(model);
+            let mut rate = params.initial;
+            for _i in 0..(365 * 20) {
+                rate = params.step(&rate, &0.0);
+            }
+            assert!((rate - 0.03).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn test_that_cir_stays_positive() {
+        let params = ShortRateParams {
+            model: ShortRateModel::CIR,
+            initial: 0.001,
+            mean_reversion: 0.1,
+            long_run_mean: 0.01,
+            vol: 0.5,
+            inflation_correlation: None,
+        };
+        assert!(params.step(&0.001, &-10.0) >= 0.0);
+    }
+
+    #[test]
+    fn test_that_monthly_source_matches_daily() {
+        //Covers 1970 and 1971
+        let daily_clock = build_clock(0, 729, &Step::Daily);
+        let random = build_hashmapsource_random(Rc::clone(&daily_clock));
+        let daily = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&daily_clock))
+            .with_quotes(random.inner.quotes.clone())
+            .with_inflation(daily_data_generator_static(0.03, Rc::clone(&daily_clock)))
+            .with_house_prices(daily_data_generator_static(0.05, Rc::clone(&daily_clock)))
+            .with_rates(daily_data_generator_static(0.02, Rc::clone(&daily_clock)))
+            .build();
+        let monthly_clock = build_clock(0, 729, &Step::Monthly);
+        let monthly = build_hashmapsource_for_clock(&daily, Rc::clone(&monthly_clock));
+        assert!(monthly_clock.borrow().peek().len() == 24);
+
+        //Ticks are on the first of January 1970 and February 1970
+        let daily_inflation = daily.get_current_inflation().unwrap();
+        assert!((monthly.get_current_inflation().unwrap() - daily_inflation).abs() < 1e-12);
+        let daily_house = ((1.05_f64).powf(1.0 / 365.0)) - 1.0;
+        let january = (1.0 + daily_house).powi(31) - 1.0;
+        assert!((monthly.get_current_house_price_return().unwrap() - january).abs() < 1e-12);
+
+        //Moves both clocks to the first of January 1971
+        for _i in 0..365 {
+            daily_clock.borrow_mut().tick();
+        }
+        for _i in 0..12 {
+            monthly_clock.borrow_mut().tick();
+        }
+        assert!(*daily_clock.borrow().now() == *monthly_clock.borrow().now());
+        assert!(*daily.get_quote("ABC").unwrap().bid == *monthly.get_quote("ABC").unwrap().bid);
+        let daily_rate = daily.get_current_interest_rate().unwrap();
+        assert!(monthly.get_current_interest_rate().unwrap() == daily_rate);
+        let year_diff = daily.get_trailing_year_inflation() - monthly.get_trailing_year_inflation();
+        let month_diff =
+            daily.get_trailing_month_inflation() - monthly.get_trailing_month_inflation();
+        assert!(year_diff.abs() < 1e-12 && month_diff.abs() < 1e-12);
+        assert!(monthly.get_trailing_year_inflation() > 0.029);
+    }
+}
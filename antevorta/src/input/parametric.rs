@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use alator::{broker::Quote, clock::Clock, input::QuotesHashMap};
+use rand::Rng;
+use rand_distr::{ChiSquared, Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+//Annual arithmetic expected returns and volatilities, the correlation matrix is ordered in the
+//same way as the symbols
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CapitalMarketAssumptions {
+    pub symbols: Vec<String>,
+    pub expected_returns: Vec<f64>,
+    pub vols: Vec<f64>,
+    pub correlation: Vec<Vec<f64>>,
+}
+
+impl CapitalMarketAssumptions {
+    //Lower triangular factor of the covariance of daily returns, panics if the correlation matrix
+    //isn't positive definite
+    fn daily_factor(&self) -> Vec<Vec<f64>> {
+        let n = self.symbols.len();
+        if self.expected_returns.len() != n || self.vols.len() != n || self.correlation.len() != n
+        {
+            panic!("Capital market assumptions must have a value for every symbol");
+        }
+
+        let scale = (1.0 / 365.0_f64).sqrt();
+        cholesky(&self.correlation)
+            .iter()
+            .zip(self.vols.iter())
+            .map(|(row, vol)| row.iter().map(|c| c * vol * scale).collect())
+            .collect()
+    }
+
+    //Drift of daily log returns so that the expected simple return matches the assumption
+    pub(super) fn daily_log_drift(&self) -> Vec<f64> {
+        let dt = 1.0 / 365.0;
+        self.expected_returns
+            .iter()
+            .zip(self.vols.iter())
+            .map(|(mu, vol)| ((1.0 + mu).ln() - (0.5 * vol.powi(2))) * dt)
+            .collect()
+    }
+}
+
+pub fn cholesky(matrix: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = matrix.len();
+    let mut res = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        if matrix[i].len() != n {
+            panic!("Correlation matrix must be square");
+        }
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| res[i][k] * res[j][k]).sum();
+            if i == j {
+                let diag = matrix[i][i] - sum;
+                if diag <= 0.0 {
+                    panic!("Correlation matrix must be positive definite");
+                }
+                res[i][j] = diag.sqrt();
+            } else {
+                res[i][j] = (matrix[i][j] - sum) / res[j][j];
+            }
+        }
+    }
+    res
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ReturnModel {
+    //Geometric Brownian motion
+    Lognormal(CapitalMarketAssumptions),
+    //Shocks are multivariate Student-t, scaled to unit variance so vols are unchanged, with a
+    //common draw for the tails so large moves happen together
+    StudentT {
+        assumptions: CapitalMarketAssumptions,
+        degrees_of_freedom: f64,
+    },
+    //Two-state Markov chain with lognormal returns in each state, switching probabilities are
+    //annual and the state is checked daily
+    RegimeSwitching {
+        calm: CapitalMarketAssumptions,
+        stressed: CapitalMarketAssumptions,
+        calm_to_stressed: f64,
+        stressed_to_calm: f64,
+    },
+}
+
+struct RegimeState {
+    drift: Vec<f64>,
+    factor: Vec<Vec<f64>>,
+}
+
+impl RegimeState {
+    fn new(assumptions: &CapitalMarketAssumptions) -> Self {
+        Self {
+            drift: assumptions.daily_log_drift(),
+            factor: assumptions.daily_factor(),
+        }
+    }
+
+    fn log_returns(&self, shocks: &[f64]) -> Vec<f64> {
+        self.drift
+            .iter()
+            .enumerate()
+            .map(|(i, drift)| {
+                let diffusion: f64 = (0..=i).map(|j| self.factor[i][j] * shocks[j]).sum();
+                drift + diffusion
+            })
+            .collect()
+    }
+}
+
+fn daily_prob(annual: f64) -> f64 {
+    1.0 - (1.0 - annual.clamp(0.0, 1.0)).powf(1.0 / 365.0)
+}
+
+//Prices start at 100, the quote for each day is set before that day's return is applied
+pub fn daily_quote_generator(
+    model: &ReturnModel,
+    clock: Clock,
+    rng: &mut impl Rng,
+) -> QuotesHashMap {
+    let (symbols, states, switch_probs, t_dist) = match model {
+        ReturnModel::Lognormal(cma) => {
+            (cma.symbols.clone(), vec![RegimeState::new(cma)], None, None)
+        }
+        ReturnModel::StudentT {
+            assumptions,
+            degrees_of_freedom,
+        } => {
+            if *degrees_of_freedom <= 2.0 {
+                panic!("Student-t needs more than two degrees of freedom for finite variance");
+            }
+            (
+                assumptions.symbols.clone(),
+                vec![RegimeState::new(assumptions)],
+                None,
+                Some((ChiSquared::new(*degrees_of_freedom).unwrap(), *degrees_of_freedom)),
+            )
+        }
+        ReturnModel::RegimeSwitching {
+            calm,
+            stressed,
+            calm_to_stressed,
+            stressed_to_calm,
+        } => {
+            if calm.symbols != stressed.symbols {
+                panic!("Regimes must have the same symbols");
+            }
+            (
+                calm.symbols.clone(),
+                vec![RegimeState::new(calm), RegimeState::new(stressed)],
+                Some([daily_prob(*calm_to_stressed), daily_prob(*stressed_to_calm)]),
+                None,
+            )
+        }
+    };
+
+    let mut res: QuotesHashMap = HashMap::new();
+    let mut prices = vec![100.0; symbols.len()];
+    let mut regime = 0;
+    for date in clock.borrow().peek() {
+        let quotes = symbols
+            .iter()
+            .zip(prices.iter())
+            .map(|(symbol, price)| Quote::new(*price, *price, date.clone(), symbol))
+            .collect();
+        res.insert(date, quotes);
+
+        let mut shocks: Vec<f64> = (0..symbols.len())
+            .map(|_| StandardNormal.sample(rng))
+            .collect();
+        if let Some((chi, dof)) = &t_dist {
+            let w: f64 = chi.sample(rng);
+            let scale = ((dof - 2.0) / w).sqrt();
+            shocks.iter_mut().for_each(|z| *z *= scale);
+        }
+
+        let log_returns = states[regime].log_returns(&shocks);
+        for (price, ret) in prices.iter_mut().zip(log_returns.iter()) {
+            *price *= ret.exp();
+        }
+
+        if let Some(probs) = &switch_probs {
+            if rng.gen::<f64>() < probs[regime] {
+                regime = 1 - regime;
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use alator::clock::ClockBuilder;
+    use alator::input::QuotesHashMap;
+    use alator::types::Frequency;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::rc::Rc;
+
+    use super::{cholesky, daily_quote_generator, CapitalMarketAssumptions, ReturnModel};
+
+    fn two_assets(vol: f64) -> CapitalMarketAssumptions {
+        CapitalMarketAssumptions {
+            symbols: vec!["ABC".to_string(), "BCD".to_string()],
+            expected_returns: vec![0.07, 0.03],
+            vols: vec![vol, vol / 2.0],
+            correlation: vec![vec![1.0, 0.5], vec![0.5, 1.0]],
+        }
+    }
+
+    //Daily simple returns for each symbol in date order
+    fn returns(quotes: &QuotesHashMap) -> Vec<Vec<f64>> {
+        let mut dates: Vec<_> = quotes.keys().cloned().collect();
+        dates.sort_by_key(|d| **d);
+        let mut res = vec![Vec::new(), Vec::new()];
+        for pair in dates.windows(2) {
+            let prev = quotes.get(&pair[0]).unwrap();
+            let curr = quotes.get(&pair[1]).unwrap();
+            for (i, series) in res.iter_mut().enumerate() {
+                series.push((*curr[i].bid / *prev[i].bid) - 1.0);
+            }
+        }
+        res
+    }
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    fn std(values: &[f64]) -> f64 {
+        let m = mean(values);
+        (values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+    }
+
+    fn corr(x: &[f64], y: &[f64]) -> f64 {
+        let (mx, my) = (mean(x), mean(y));
+        let cov = x.iter().zip(y.iter()).map(|(a, b)| (a - mx) * (b - my)).sum::<f64>()
+            / x.len() as f64;
+        cov / (std(x) * std(y))
+    }
+
+    fn excess_kurtosis(values: &[f64]) -> f64 {
+        let m = mean(values);
+        let s = std(values);
+        values.iter().map(|v| ((v - m) / s).powi(4)).sum::<f64>() / values.len() as f64 - 3.0
+    }
+
+    #[test]
+    fn test_that_cholesky_factors_correlation() {
+        let matrix = vec![vec![1.0, 0.5], vec![0.5, 1.0]];
+        let res = cholesky(&matrix);
+        assert!(res[0][1] == 0.0);
+        let rebuilt = (res[1][0] * res[1][0]) + (res[1][1] * res[1][1]);
+        assert!((rebuilt - 1.0).abs() < 1e-12);
+        assert!((res[1][0] * res[0][0] - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_that_lognormal_matches_assumptions() {
+        let clock = ClockBuilder::with_length_in_days(0, 365 * 200)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let mut rng = StdRng::seed_from_u64(10);
+        let model = ReturnModel::Lognormal(two_assets(0.2));
+        let rets = returns(&daily_quote_generator(&model, clock, &mut rng));
+
+        assert!((mean(&rets[0]) * 365.0 - 0.07).abs() < 0.03);
+        assert!((std(&rets[0]) * 365.0_f64.sqrt() - 0.2).abs() < 0.01);
+        assert!((std(&rets[1]) * 365.0_f64.sqrt() - 0.1).abs() < 0.005);
+        assert!((corr(&rets[0], &rets[1]) - 0.5).abs() < 0.02);
+        assert!(excess_kurtosis(&rets[0]).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_that_student_t_has_fat_tails() {
+        let clock = ClockBuilder::with_length_in_days(0, 365 * 200)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let mut rng = StdRng::seed_from_u64(10);
+        let model = ReturnModel::StudentT {
+            assumptions: two_assets(0.2),
+            degrees_of_freedom: 5.0,
+        };
+        let rets = returns(&daily_quote_generator(&model, clock, &mut rng));
+
+        assert!((std(&rets[0]) * 365.0_f64.sqrt() - 0.2).abs() < 0.02);
+        assert!((corr(&rets[0], &rets[1]) - 0.5).abs() < 0.05);
+        assert!(excess_kurtosis(&rets[0]) > 1.0);
+    }
+
+    #[test]
+    fn test_that_regime_switching_mixes_vols() {
+        let clock = ClockBuilder::with_length_in_days(0, 365 * 200)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let mut rng = StdRng::seed_from_u64(10);
+        let model = |calm_to_stressed| ReturnModel::RegimeSwitching {
+            calm: two_assets(0.1),
+            stressed: two_assets(0.4),
+            calm_to_stressed,
+            stressed_to_calm: 0.5,
+        };
+
+        //Never leaves the calm state
+        let calm = returns(&daily_quote_generator(&model(0.0), Rc::clone(&clock), &mut rng));
+        assert!((std(&calm[0]) * 365.0_f64.sqrt() - 0.1).abs() < 0.005);
+
+        let mixed = returns(&daily_quote_generator(&model(0.2), clock, &mut rng));
+        let vol = std(&mixed[0]) * 365.0_f64.sqrt();
+        assert!(vol > 0.15 && vol < 0.35);
+        //Mixture of normals has fat tails
+        assert!(excess_kurtosis(&mixed[0]) > 0.5);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use alator::{broker::Quote, input::QuotesHashMap};
+use serde::{Deserialize, Serialize};
+
+use super::HashMapSourceSim;
+
+//Historical episode that replaces a window of a generated path. Returns and inflation are simple
+//values for each period of the episode, periods are an equal share of the year so monthly data
+//has twelve periods a year.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StressScenario {
+    pub name: String,
+    //Days from the start of the simulation to the start of the episode
+    pub offset_days: usize,
+    pub periods_per_year: usize,
+    //Symbols that aren't given keep the generated returns
+    pub returns: HashMap<String, Vec<f64>>,
+    //Generated inflation is kept if this isn't given
+    pub inflation: Option<Vec<f64>>,
+}
+
+impl StressScenario {
+    //Spreads the value for each period evenly across the days in that period, so the daily values
+    //compound to the period value
+    pub fn daily(&self, values: &[f64]) -> Vec<f64> {
+        if self.periods_per_year == 0 || self.periods_per_year > 365 {
+            panic!("Stress scenario must have between one and 365 periods a year");
+        }
+        let period_days = 365.0 / self.periods_per_year as f64;
+        let mut res = Vec::new();
+        for (i, value) in values.iter().enumerate() {
+            let start = (i as f64 * period_days).round() as usize;
+            let end = ((i + 1) as f64 * period_days).round() as usize;
+            let days = end - start;
+            let daily = (1.0 + value).powf(1.0 / days as f64) - 1.0;
+            res.extend(std::iter::repeat_n(daily, days));
+        }
+        res
+    }
+
+    //Prices before the episode are unchanged, within the episode they follow the historical
+    //returns, and after it they follow the generated returns from the level the episode ends at.
+    //Dividends per share are unchanged. The source must be daily, the result shares its clock so
+    //this has to be called before the clock moves.
+    pub fn apply(&self, source: &HashMapSourceSim) -> HashMapSourceSim {
+        let inner = &source.inner;
+        let dates = inner.clock.borrow().peek();
+        let returns: HashMap<&str, Vec<f64>> = self
+            .returns
+            .iter()
+            .map(|(symbol, rets)| (symbol.as_str(), self.daily(rets)))
+            .collect();
+
+        //Ratio of the stressed price to the generated price, and the last generated price
+        let mut scale: HashMap<String, f64> = HashMap::new();
+        let mut last: HashMap<String, f64> = HashMap::new();
+        let mut quotes: QuotesHashMap = HashMap::new();
+        for (pos, date) in dates.iter().enumerate() {
+            if let Some(date_quotes) = inner.quotes.get(date) {
+                let mut stressed = Vec::with_capacity(date_quotes.len());
+                for quote in date_quotes {
+                    let symbol = quote.symbol.to_string();
+                    //The return on each day of the episode moves the price to the next day
+                    let episode_ret = (pos > self.offset_days)
+                        .then(|| pos - 1 - self.offset_days)
+                        .and_then(|day| returns.get(symbol.as_str())?.get(day));
+
+                    let generated = *quote.bid;
+                    let curr = scale.entry(symbol.clone()).or_insert(1.0);
+                    if let (Some(ret), Some(last_generated)) = (episode_ret, last.get(&symbol)) {
+                        *curr *= (1.0 + ret) * last_generated / generated;
+                    }
+                    last.insert(symbol.clone(), generated);
+                    stressed.push(Quote::new(
+                        *quote.bid * *curr,
+                        *quote.ask * *curr,
+                        date.clone(),
+                        &symbol,
+                    ));
+                }
+                quotes.insert(date.clone(), stressed);
+            }
+        }
+
+        let mut inflation = inner.inflation.clone();
+        if let Some(episode) = &self.inflation {
+            for (day, value) in self.daily(episode).into_iter().enumerate() {
+                if let Some(date) = dates.get(self.offset_days + day) {
+                    inflation.insert(date.clone(), value);
+                }
+            }
+        }
+
+        HashMapSourceSim::new(
+            Rc::clone(&inner.clock),
+            inflation,
+            inner.rates.clone(),
+            inner.house_price_rets.clone(),
+            quotes,
+            inner.dividends.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alator::broker::Quote;
+    use alator::clock::ClockBuilder;
+    use alator::input::{DataSource, QuotesHashMap};
+    use alator::types::Frequency;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use super::StressScenario;
+    use crate::input::{daily_data_generator_static, HashMapSourceSimBuilder, SimDataSource};
+
+    #[test]
+    fn test_that_scenario_replaces_window() {
+        let clock = ClockBuilder::with_length_in_days(0, 365 * 4)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let daily_return = 1.05_f64.powf(1.0 / 365.0) - 1.0;
+        let mut quotes: QuotesHashMap = HashMap::new();
+        let mut price = 100.0;
+        for date in clock.borrow().peek() {
+            let q_abc = Quote::new(price, price, date.clone(), "ABC");
+            let q_bcd = Quote::new(price, price, date.clone(), "BCD");
+            quotes.insert(date, vec![q_abc, q_bcd]);
+            price *= 1.0 + daily_return;
+        }
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_quotes(quotes)
+            .with_inflation(daily_data_generator_static(0.02, Rc::clone(&clock)))
+            .build();
+
+        //A year of monthly falls with high inflation, starting after a year
+        let mut returns = HashMap::new();
+        returns.insert("ABC".to_string(), vec![-0.05; 12]);
+        let scenario = StressScenario {
+            name: "Crash".to_string(),
+            offset_days: 365,
+            periods_per_year: 12,
+            returns,
+            inflation: Some(vec![0.01; 12]),
+        };
+        let stressed = scenario.apply(&source);
+        assert_eq!(scenario.daily(&[0.0; 12]).len(), 365);
+
+        let mut prices = Vec::new();
+        let mut inflation = Vec::new();
+        while clock.borrow().has_next() {
+            let abc = *stressed.get_quote("ABC").unwrap().bid;
+            let bcd = *stressed.get_quote("BCD").unwrap().bid;
+            assert!((bcd - *source.get_quote("BCD").unwrap().bid).abs() < 1e-9);
+            prices.push(abc);
+            inflation.push(stressed.get_current_inflation().unwrap());
+            clock.borrow_mut().tick();
+        }
+
+        let growth = |start: usize, end: usize| (prices[end] / prices[start]) - 1.0;
+        let inflation_growth = |start: usize, end: usize| {
+            inflation[start..end].iter().fold(1.0, |acc, v| acc * (1.0 + v)) - 1.0
+        };
+        assert!((prices[365] - 105.0).abs() < 1e-6);
+        assert!((growth(365, 730) - (0.95_f64.powi(12) - 1.0)).abs() < 1e-9);
+        assert!((growth(730, 1095) - 0.05).abs() < 1e-9);
+        assert!((inflation_growth(0, 365) - 0.02).abs() < 1e-9);
+        assert!((inflation_growth(365, 730) - (1.01_f64.powi(12) - 1.0)).abs() < 1e-9);
+        assert!((inflation_growth(730, 1095) - 0.02).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,297 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use alator::{
+    broker::{Dividend, Quote},
+    clock::Clock,
+    input::{DataSource, DividendsHashMap, QuotesHashMap},
+};
+
+use super::{months_before, HashMapSourceSim, SimDataRep, SimDataSource, SECONDS_IN_DAY};
+
+#[derive(Debug)]
+pub struct VecSourceSimInner {
+    clock: Clock,
+    dates: Vec<i64>,
+    //Position of the last date queried, the clock only moves forward so this is usually either
+    //the current position or the one before
+    pos: Cell<usize>,
+    symbols: HashMap<String, usize>,
+    inflation: Vec<Option<f64>>,
+    //Inflation compounded over all the days before each position, inflation on each date holds
+    //until the next date
+    inflation_index: Vec<f64>,
+    //Index on the first day of the trailing period for each position
+    trailing_month_base: Vec<f64>,
+    trailing_year_base: Vec<f64>,
+    rates: Vec<Option<f64>>,
+    house_price_rets: Vec<Option<f64>>,
+    quotes: Vec<Vec<Quote>>,
+    //Position of each symbol id within the quotes for that date
+    quote_positions: Vec<Vec<Option<usize>>>,
+    dividends: Vec<Vec<Dividend>>,
+}
+
+//Holds the same data as `HashMapSourceSim` but every series is indexed by position in the clock,
+//so a query is a lookup into a contiguous array rather than a hash of the date. Trailing inflation
+//is calculated from running products rather than iterating over the clock.
+#[derive(Clone, Debug)]
+pub struct VecSourceSim {
+    inner: Rc<VecSourceSimInner>,
+}
+
+impl VecSourceSim {
+    fn position(&self) -> Option<usize> {
+        let now = *self.inner.clock.borrow().now();
+        let dates = &self.inner.dates;
+        let last = self.inner.pos.get();
+        for pos in [last, last + 1] {
+            if dates.get(pos) == Some(&now) {
+                self.inner.pos.set(pos);
+                return Some(pos);
+            }
+        }
+        let pos = dates.binary_search(&now).ok()?;
+        self.inner.pos.set(pos);
+        Some(pos)
+    }
+
+    fn trailing_inflation(&self, bases: &[f64]) -> f64 {
+        match self.position() {
+            Some(pos) => (self.inner.inflation_index[pos] / bases[pos]) - 1.0,
+            None => 0.0,
+        }
+    }
+
+    pub fn symbol_id(&self, symbol: &str) -> Option<usize> {
+        self.inner.symbols.get(symbol).copied()
+    }
+
+    pub fn get_quote_by_id(&self, symbol_id: usize) -> Option<&Quote> {
+        let pos = self.position()?;
+        let quote_pos = (*self.inner.quote_positions[pos].get(symbol_id)?)?;
+        Some(&self.inner.quotes[pos][quote_pos])
+    }
+
+    pub fn new(
+        clock: Clock,
+        inflation: SimDataRep,
+        rates: SimDataRep,
+        house_price_rets: SimDataRep,
+        mut quotes: QuotesHashMap,
+        mut dividends: DividendsHashMap,
+    ) -> Self {
+        let clock_dates = clock.borrow().peek();
+        let dates: Vec<i64> = clock_dates.iter().map(|date| **date).collect();
+
+        let mut symbols: HashMap<String, usize> = HashMap::new();
+        let mut quotes_by_pos = Vec::with_capacity(dates.len());
+        let mut quote_positions = Vec::with_capacity(dates.len());
+        let mut dividends_by_pos = Vec::with_capacity(dates.len());
+        for date in &clock_dates {
+            let date_quotes = quotes.remove(date).unwrap_or_default();
+            let mut positions: Vec<Option<usize>> = vec![None; symbols.len()];
+            for (quote_pos, quote) in date_quotes.iter().enumerate() {
+                let next_id = symbols.len();
+                let id = *symbols.entry(quote.symbol.to_string()).or_insert(next_id);
+                if id >= positions.len() {
+                    positions.resize(id + 1, None);
+                }
+                positions[id] = Some(quote_pos);
+            }
+            quotes_by_pos.push(date_quotes);
+            quote_positions.push(positions);
+            dividends_by_pos.push(dividends.remove(date).unwrap_or_default());
+        }
+
+        let inflation: Vec<Option<f64>> =
+            clock_dates.iter().map(|date| inflation.get(date).copied()).collect();
+        let mut inflation_index = Vec::with_capacity(dates.len() + 1);
+        let mut curr = 1.0;
+        inflation_index.push(curr);
+        for (pos, val) in inflation.iter().enumerate() {
+            let days = dates
+                .get(pos + 1)
+                .map_or(1, |next| (next - dates[pos]) / SECONDS_IN_DAY);
+            curr *= (1.0 + val.unwrap_or(0.0)).powi(days as i32);
+            inflation_index.push(curr);
+        }
+
+        //Index part way through the date before, trailing periods exclude the start date and the
+        //current date so the base is a day after the start
+        let index_at = |time: i64| -> f64 {
+            let pos = dates.partition_point(|d| *d <= time);
+            if pos == 0 {
+                return 1.0;
+            }
+            let days = (time - dates[pos - 1]) as f64 / SECONDS_IN_DAY as f64;
+            inflation_index[pos - 1] * (1.0 + inflation[pos - 1].unwrap_or(0.0)).powf(days)
+        };
+        let base_after = |months: i32| -> Vec<f64> {
+            clock_dates
+                .iter()
+                .map(|date| index_at(*months_before(date, months) + SECONDS_IN_DAY))
+                .collect()
+        };
+        let trailing_month_base = base_after(1);
+        let trailing_year_base = base_after(12);
+
+        let tmp = VecSourceSimInner {
+            clock: Rc::clone(&clock),
+            pos: Cell::new(0),
+            symbols,
+            inflation,
+            inflation_index,
+            trailing_month_base,
+            trailing_year_base,
+            rates: clock_dates.iter().map(|date| rates.get(date).copied()).collect(),
+            house_price_rets: clock_dates
+                .iter()
+                .map(|date| house_price_rets.get(date).copied())
+                .collect(),
+            quotes: quotes_by_pos,
+            quote_positions,
+            dividends: dividends_by_pos,
+            dates,
+        };
+        Self {
+            inner: Rc::new(tmp),
+        }
+    }
+}
+
+impl From<&HashMapSourceSim> for VecSourceSim {
+    fn from(source: &HashMapSourceSim) -> Self {
+        let inner = &source.inner;
+        VecSourceSim::new(
+            Rc::clone(&inner.clock),
+            inner.inflation.clone(),
+            inner.rates.clone(),
+            inner.house_price_rets.clone(),
+            inner.quotes.clone(),
+            inner.dividends.clone(),
+        )
+    }
+}
+
+impl SimDataSource for VecSourceSim {
+    fn get_current_house_price_return(&self) -> Option<f64> {
+        self.inner.house_price_rets[self.position()?]
+    }
+
+    fn get_trailing_month_inflation(&self) -> f64 {
+        self.trailing_inflation(&self.inner.trailing_month_base)
+    }
+
+    fn get_trailing_year_inflation(&self) -> f64 {
+        self.trailing_inflation(&self.inner.trailing_year_base)
+    }
+
+    fn get_current_inflation(&self) -> Option<f64> {
+        self.inner.inflation[self.position()?]
+    }
+
+    fn get_current_interest_rate(&self) -> Option<f64> {
+        self.inner.rates[self.position()?]
+    }
+}
+
+impl DataSource for VecSourceSim {
+    fn get_quote(&self, symbol: &str) -> Option<&Quote> {
+        self.get_quote_by_id(self.symbol_id(symbol)?)
+    }
+
+    fn get_quotes(&self) -> Option<&Vec<Quote>> {
+        let quotes = &self.inner.quotes[self.position()?];
+        if quotes.is_empty() {
+            return None;
+        }
+        Some(quotes)
+    }
+
+    fn get_dividends(&self) -> Option<&Vec<Dividend>> {
+        let dividends = &self.inner.dividends[self.position()?];
+        if dividends.is_empty() {
+            return None;
+        }
+        Some(dividends)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alator::clock::ClockBuilder;
+    use alator::input::DataSource;
+    use alator::types::Frequency;
+    use std::rc::Rc;
+
+    use super::VecSourceSim;
+    use crate::input::{
+        build_hashmapsource_for_clock, build_hashmapsource_random,
+        daily_data_generator_parametric_normal, HashMapSourceSim, HashMapSourceSimBuilder,
+        SimDataSource,
+    };
+    use crate::schedule::{build_clock, Step};
+    use alator::clock::Clock;
+
+    fn assert_sources_match(clock: Clock, hashmap: HashMapSourceSim) {
+        let vec = VecSourceSim::from(&hashmap);
+        while clock.borrow().has_next() {
+            assert_eq!(hashmap.get_current_inflation(), vec.get_current_inflation());
+            assert_eq!(
+                hashmap.get_current_interest_rate(),
+                vec.get_current_interest_rate()
+            );
+            assert_eq!(
+                hashmap.get_quote("BCD").map(|q| *q.bid),
+                vec.get_quote("BCD").map(|q| *q.bid)
+            );
+            assert!(vec.get_quote("XYZ").is_none());
+            let month_diff =
+                hashmap.get_trailing_month_inflation() - vec.get_trailing_month_inflation();
+            let year_diff =
+                hashmap.get_trailing_year_inflation() - vec.get_trailing_year_inflation();
+            assert!(month_diff.abs() < 1e-9 && year_diff.abs() < 1e-9);
+            clock.borrow_mut().tick();
+        }
+    }
+
+    #[test]
+    fn test_that_vec_source_matches_hashmap_source() {
+        //Starts on the 31st of January 1970 so the trailing month has to be clamped
+        let clock = ClockBuilder::with_length_in_days(30 * 86_400, 800)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let random = build_hashmapsource_random(Rc::clone(&clock));
+        let inflation = daily_data_generator_parametric_normal(0.03, 0.001, Rc::clone(&clock));
+        let rates = daily_data_generator_parametric_normal(0.02, 0.001, Rc::clone(&clock));
+        let hashmap = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_quotes(random.inner.quotes.clone())
+            .with_inflation(inflation)
+            .with_rates(rates)
+            .build();
+
+        let monthly_clock = build_clock(30 * 86_400, 800, &Step::Monthly);
+        let monthly = build_hashmapsource_for_clock(&hashmap, Rc::clone(&monthly_clock));
+        assert_sources_match(clock, hashmap);
+        assert_sources_match(monthly_clock, monthly);
+    }
+
+    #[test]
+    fn test_that_symbol_ids_are_stable() {
+        let clock = ClockBuilder::with_length_in_days(0, 10)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let vec = VecSourceSim::from(&build_hashmapsource_random(Rc::clone(&clock)));
+        let abc = vec.symbol_id("ABC").unwrap();
+        assert_ne!(abc, vec.symbol_id("BCD").unwrap());
+
+        clock.borrow_mut().tick();
+        clock.borrow_mut().tick();
+        let quote = vec.get_quote_by_id(abc).unwrap();
+        assert_eq!(quote.symbol, "ABC");
+        assert!(vec.get_dividends().is_none());
+    }
+}
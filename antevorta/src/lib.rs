@@ -1,10 +1,11 @@
 pub mod config;
 pub mod output;
 pub mod input;
+pub mod mortality;
 pub mod schedule;
 pub mod strat;
+pub mod tax;
 
 pub(crate) mod flow;
 pub(crate) mod sim;
 pub(crate) mod stack;
-pub(crate) mod tax;
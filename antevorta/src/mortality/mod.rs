@@ -0,0 +1,182 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::schedule::{calendar_year, years_between};
+
+//Probability of death within a year (qx) for each age. This is a parametric approximation, not ONS
+//data: a Makeham-style curve that grows exponentially with age and flattens at the oldest ages,
+//chosen so that life expectancy is close to current UK levels. Tables end at 110 and death is
+//certain after this.
+const PARAMETRIC_LIFE_TABLES: &str = include_str!("parametric_life_tables.json");
+
+const SECONDS_IN_YEAR: i64 = 365 * 86_400;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum Sex {
+    Male,
+    Female,
+}
+
+//Period tables use mortality in the base year for every year, cohort tables apply a flat annual
+//improvement to mortality after the base year so lifespan depends on year of birth. The flat rate
+//is an assumption, it does not vary by age or year as a projection would
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum LifeTableBasis {
+    Period,
+    Cohort,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ParametricLifeTables {
+    base_year: i32,
+    improvement: f64,
+    male: Vec<f64>,
+    female: Vec<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LifeTable {
+    qx: Vec<f64>,
+    base_year: i32,
+    improvement: f64,
+    basis: LifeTableBasis,
+}
+
+impl LifeTable {
+    //Probability of dying within a year for someone of `age` in calendar `year`
+    pub fn qx(&self, age: i32, year: i32) -> f64 {
+        if age < 0 {
+            return 0.0;
+        }
+        let base = match self.qx.get(age as usize) {
+            Some(q) => *q,
+            None => return 1.0,
+        };
+        match self.basis {
+            LifeTableBasis::Period => base,
+            LifeTableBasis::Cohort => {
+                let years = (year - self.base_year).max(0);
+                base * (1.0 - self.improvement).powi(years)
+            }
+        }
+    }
+
+    //Present value of one a year paid monthly for life. Monthly payments are approximated by annual
+    //payments in arrears plus 11/24.
+    pub fn annuity_factor(&self, age: i32, year: i32, rate: &f64) -> f64 {
+        let mut survival = 1.0;
+        let mut factor = 0.0;
+        let mut years = 0;
+        while survival > 0.0 {
+            survival *= 1.0 - self.qx(age + years, year + years);
+            years += 1;
+            factor += survival / (1.0 + rate).powi(years);
+        }
+        factor + (11.0 / 24.0)
+    }
+
+    //Walks forward year by year from the start date, the date of death falls at a uniformly
+    //distributed point within the year of death
+    pub fn sample_date_of_death(
+        &self,
+        date_of_birth: &i64,
+        start_date: &i64,
+        rng: &mut impl Rng,
+    ) -> i64 {
+        let start_age = years_between(date_of_birth, start_date);
+        let start_year = calendar_year(start_date);
+
+        let mut years_lived = 0;
+        loop {
+            let age = start_age + years_lived;
+            let q = self.qx(age, start_year + years_lived);
+            if rng.gen::<f64>() < q {
+                break;
+            }
+            years_lived += 1;
+        }
+
+        let within_year = (rng.gen::<f64>() * SECONDS_IN_YEAR as f64) as i64;
+        start_date + (years_lived as i64 * SECONDS_IN_YEAR) + within_year
+    }
+
+    pub fn new(sex: Sex, basis: LifeTableBasis) -> Self {
+        let tables: ParametricLifeTables = serde_json::from_str(PARAMETRIC_LIFE_TABLES).unwrap();
+        let qx = match sex {
+            Sex::Male => tables.male,
+            Sex::Female => tables.female,
+        };
+        Self {
+            qx,
+            base_year: tables.base_year,
+            improvement: tables.improvement,
+            basis,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{LifeTable, LifeTableBasis, Sex};
+
+    #[test]
+    fn test_that_cohort_mortality_improves() {
+        let period = LifeTable::new(Sex::Male, LifeTableBasis::Period);
+        let cohort = LifeTable::new(Sex::Male, LifeTableBasis::Cohort);
+        assert!(period.qx(80, 2040) == period.qx(80, 2021));
+        assert!(cohort.qx(80, 2040) < cohort.qx(80, 2021));
+        assert!(period.qx(120, 2021) == 1.0);
+    }
+
+    #[test]
+    fn test_that_annuity_factor_falls_with_age_and_rate() {
+        let table = LifeTable::new(Sex::Male, LifeTableBasis::Period);
+        let at_65 = table.annuity_factor(65, 2025, &0.04);
+        assert!(at_65 > table.annuity_factor(75, 2025, &0.04));
+        assert!(at_65 > table.annuity_factor(65, 2025, &0.06));
+        //Level annuity rate at 65 is roughly 6-9% of the purchase price
+        assert!(1.0 / at_65 > 0.05 && 1.0 / at_65 < 0.1);
+    }
+
+    #[test]
+    fn test_that_sampled_lifespan_uses_calendar_year() {
+        //Certain death in the base year and none after, so the person survives only if the first
+        //year of life sampled is after the base year
+        let table = LifeTable {
+            qx: vec![1.0; 71],
+            base_year: 2022,
+            improvement: 1.0,
+            basis: LifeTableBasis::Cohort,
+        };
+        let mut rng = StdRng::seed_from_u64(10);
+
+        //Born 2 Jan 1958, starting 2 Jan 2023 at 65, which is in the 2022 tax year
+        let date_of_birth = -378_604_800;
+        let start_date = 1_672_617_600;
+        let death = table.sample_date_of_death(&date_of_birth, &start_date, &mut rng);
+        assert!(death >= start_date + 6 * 365 * 86_400);
+    }
+
+    #[test]
+    fn test_that_sampled_lifespan_is_sensible() {
+        let table = LifeTable::new(Sex::Female, LifeTableBasis::Period);
+        let mut rng = StdRng::seed_from_u64(10);
+
+        //Born 1 Jan 1960, starting 1 Jan 2025 at 65
+        let date_of_birth = -315_619_200;
+        let start_date = 1_735_689_600;
+        let runs = 2_000;
+        let mut total_years = 0.0;
+        for _i in 0..runs {
+            let death = table.sample_date_of_death(&date_of_birth, &start_date, &mut rng);
+            assert!(death >= start_date);
+            total_years += (death - start_date) as f64 / (365.0 * 86_400.0);
+        }
+        //Remaining life expectancy at 65 for women is roughly 21 years
+        let mean = total_years / runs as f64;
+        assert!(mean > 18.0 && mean < 24.0);
+    }
+}
@@ -4,8 +4,10 @@ use alator::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::input::SimDataSource;
 use crate::sim::uk::UKSimulationState;
 use crate::strat::InvestmentStrategy;
+use crate::tax::uk::{UKEstateBreakdown, UKTaxBreakdown};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct UKSimulationOutput {
@@ -15,6 +17,16 @@ pub struct UKSimulationOutput {
     pub expense: Vec<f64>,
     pub tax_paid: Vec<f64>,
     pub sipp_contributions: Vec<f64>,
+    pub employer_contributions: Vec<f64>,
+    pub jisa_value: Vec<f64>,
+    pub property_value: Vec<f64>,
+    pub student_loan_repaid: Vec<f64>,
+    pub student_loan_balance: Vec<f64>,
+    pub tax_breakdown: Vec<UKTaxBreakdown>,
+    pub estate: UKEstateBreakdown,
+    pub date_of_death: Option<i64>,
+    //Set if the simulation ran out of money, this can only happen before death
+    pub ruin_date: Option<i64>,
     pub ret: f64,
     pub cagr: f64,
     pub vol: f64,
@@ -34,15 +46,20 @@ pub struct UKSimulationOutput {
 }
 
 impl UKSimulationOutput {
-    pub fn get_final_value<S: InvestmentStrategy>(sim: &UKSimulationState<S>) -> CashValue {
+    pub fn get_final_value<S: InvestmentStrategy, D: SimDataSource>(
+        sim: &UKSimulationState<S, D>,
+    ) -> CashValue {
         let total_value = *sim.isa_snapshot.last().unwrap().portfolio_value
             + *sim.gia_snapshot.last().unwrap().portfolio_value
             + *sim.sipp_snapshot.last().unwrap().portfolio_value
-            + **sim.cash.last().unwrap();
+            + **sim.cash.last().unwrap()
+            + sim.property_value.last().map(|v| **v).unwrap_or(0.0);
         CashValue::from(total_value)
     }
 
-    pub fn get_output<S: InvestmentStrategy>(sim: &UKSimulationState<S>) -> UKSimulationOutput {
+    pub fn get_output<S: InvestmentStrategy, D: SimDataSource>(
+        sim: &UKSimulationState<S, D>,
+    ) -> UKSimulationOutput {
         let mut joined_snaps = Vec::new();
         for (isa, sipp, gia) in sim
             .isa_snapshot
@@ -94,6 +111,15 @@ impl UKSimulationOutput {
             expense: sim.expense.iter().map(|v| **v).collect(),
             tax_paid: sim.tax_paid.iter().map(|v| **v).collect(),
             sipp_contributions: sim.sipp_contributions.iter().map(|v| **v).collect(),
+            employer_contributions: sim.employer_contributions.iter().map(|v| **v).collect(),
+            jisa_value: sim.jisa_value.iter().map(|v| **v).collect(),
+            property_value: sim.property_value.iter().map(|v| **v).collect(),
+            student_loan_repaid: sim.student_loan_repaid.iter().map(|v| **v).collect(),
+            student_loan_balance: sim.student_loan_balance.iter().map(|v| **v).collect(),
+            tax_breakdown: sim.tax_breakdown.clone(),
+            estate: sim.estate(),
+            date_of_death: sim.date_of_death.as_ref().map(|d| **d),
+            ruin_date: sim.ruin_date.as_ref().map(|d| **d),
         }
     }
 }
@@ -1,6 +1,8 @@
-use time::{Duration, OffsetDateTime, Weekday};
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration, OffsetDateTime, Weekday};
 
-use alator::types::DateTime;
+use alator::clock::{Clock, ClockBuilder};
+use alator::types::{DateTime, Frequency};
 
 type Day = i32;
 type Month = i32;
@@ -15,6 +17,11 @@ pub enum Schedule {
     EveryMonth(Day),
     StartOfMonth,
     EveryDay,
+    //Fires if the inner schedule would fire on any day from the date until the end of the month,
+    //used when the clock ticks monthly
+    Monthly(Box<Schedule>),
+    //Fires with the inner schedule up to and including the date
+    Until(Box<Schedule>, DateTime),
 }
 
 impl Schedule {
@@ -38,6 +45,7 @@ impl Schedule {
                     Some(DateTime::from(last_month.unix_timestamp()))
                 }
                 Schedule::EveryDay => None,
+                Schedule::Monthly(inner) | Schedule::Until(inner, _) => inner.last_period(date),
             },
             _ => None,
         }
@@ -58,12 +66,225 @@ impl Schedule {
                 Schedule::EveryMonth(day) => d.day() == *day as u8,
                 Schedule::EveryDay => true,
                 Schedule::StartOfMonth => d.date().day() == 1,
+                Schedule::Monthly(inner) => rest_of_month(d).any(|day| inner.check(&day)),
+                Schedule::Until(inner, end) => *epoch_date <= **end && inner.check(epoch_date),
             },
             _ => false,
         }
     }
+
+    //Number of times the schedule fires in the tick starting at the date. Monthly schedules count
+    //each day that the inner schedule fires on so that flows are paid as often as with daily ticks.
+    pub fn count(&self, epoch_date: &i64) -> usize {
+        match (self, OffsetDateTime::from_unix_timestamp(*epoch_date)) {
+            (Schedule::Monthly(inner), Ok(d)) => {
+                rest_of_month(d).filter(|day| inner.check(day)).count()
+            }
+            _ => usize::from(self.check(epoch_date)),
+        }
+    }
+
+    pub fn for_step(self, step: &Step) -> Schedule {
+        match (step, &self) {
+            (Step::Monthly, Schedule::Monthly(_)) | (Step::Daily, _) => self,
+            (Step::Monthly, _) => Schedule::Monthly(Box::new(self)),
+        }
+    }
+}
+
+//Epoch of each day from the date until the end of its month
+fn rest_of_month(date: OffsetDateTime) -> impl Iterator<Item = i64> {
+    let month = date.month();
+    (0..31)
+        .map(move |days| date + Duration::days(days))
+        .take_while(move |day| day.month() == month)
+        .map(|day| day.unix_timestamp())
+}
+
+//Length of each tick of the simulation clock. Monthly ticks fall on the first of each month, except
+//for the first tick which is on the start date. Everything that happens within a month happens on
+//that month's tick.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Step {
+    Daily,
+    Monthly,
+}
+
+//Dates are the start date and then the first of every month up to the end date, at the same time
+//of day as the start date
+pub fn monthly_dates(start_date: &i64, end_date: &i64) -> Vec<DateTime> {
+    let mut res = vec![DateTime::from(*start_date)];
+    let start = match OffsetDateTime::from_unix_timestamp(*start_date) {
+        Ok(d) => d,
+        _ => return res,
+    };
+    let (mut year, mut month) = (start.year(), start.month());
+    loop {
+        month = month.next();
+        if month == time::Month::January {
+            year += 1;
+        }
+        let date = start.replace_date(Date::from_calendar_date(year, month, 1).unwrap());
+        if date.unix_timestamp() > *end_date {
+            break;
+        }
+        res.push(DateTime::from(date.unix_timestamp()));
+    }
+    res
+}
+
+//Daily clocks have a tick on each day from the start date, length is the same as
+//`ClockBuilder::with_length_in_days`
+pub fn build_clock(start_date: i64, length_in_days: i64, step: &Step) -> Clock {
+    match step {
+        Step::Daily => ClockBuilder::with_length_in_days(start_date, length_in_days)
+            .with_frequency(&Frequency::Daily)
+            .build(),
+        Step::Monthly => {
+            let end_date = start_date + (length_in_days * 86_400);
+            ClockBuilder::from_fixed(monthly_dates(&start_date, &end_date)).build()
+        }
+    }
+}
+
+//Whole years between two dates, used for ages. Returns zero if either date is invalid.
+pub fn years_between(start: &i64, end: &i64) -> i32 {
+    let start_date = OffsetDateTime::from_unix_timestamp(*start);
+    let end_date = OffsetDateTime::from_unix_timestamp(*end);
+    match (start_date, end_date) {
+        (Ok(s), Ok(e)) => {
+            let mut years = e.year() - s.year();
+            if (e.month() as u8, e.day()) < (s.month() as u8, s.day()) {
+                years -= 1;
+            }
+            years
+        }
+        _ => 0,
+    }
+}
+
+//Same day and month a number of calendar years later, 29 February moves to 28 February. Returns
+//zero if the date is invalid.
+pub fn years_after(date: &i64, years: i32) -> i64 {
+    match OffsetDateTime::from_unix_timestamp(*date) {
+        Ok(d) => {
+            let year = d.year() + years;
+            let moved = match d.replace_year(year) {
+                Ok(moved) => moved,
+                Err(_) => d.replace_day(28).unwrap().replace_year(year).unwrap(),
+            };
+            moved.unix_timestamp()
+        }
+        _ => 0,
+    }
+}
+
+//Calendar year that the date falls in. Returns zero if the date is invalid.
+pub fn calendar_year(date: &i64) -> i32 {
+    match OffsetDateTime::from_unix_timestamp(*date) {
+        Ok(d) => d.year(),
+        _ => 0,
+    }
+}
+
+//UK tax year that the date falls in, identified by the calendar year in which it starts. Tax years
+//start on 6 April. Returns zero if the date is invalid.
+pub fn tax_year(date: &i64) -> i32 {
+    match OffsetDateTime::from_unix_timestamp(*date) {
+        Ok(d) => {
+            if (d.month() as u8, d.day()) < (4, 6) {
+                d.year() - 1
+            } else {
+                d.year()
+            }
+        }
+        _ => 0,
+    }
 }
 
 pub trait Schedulable {
     fn check(&self, date: &DateTime) -> bool;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{calendar_year, monthly_dates, tax_year, years_after, years_between, Schedule, Step};
+
+    #[test]
+    fn test_that_years_between_counts_whole_years() {
+        //1990-06-15 to 2020-06-14 and 2020-06-15
+        assert!(years_between(&645_408_000, &1_592_092_800) == 29);
+        assert!(years_between(&645_408_000, &1_592_179_200) == 30);
+    }
+
+    #[test]
+    fn test_that_years_after_adds_calendar_years() {
+        //1990-04-06 to 2020-04-06, 2000-02-29 to 2001-02-28
+        assert!(years_after(&639_360_000, 30) == 1_586_131_200);
+        assert!(years_after(&951_782_400, 1) == 983_318_400);
+    }
+
+    #[test]
+    fn test_that_tax_year_starts_on_sixth_april() {
+        //2020-04-05 and 2020-04-06
+        assert!(tax_year(&1_586_044_800) == 2019);
+        assert!(tax_year(&1_586_131_200) == 2020);
+        assert!(calendar_year(&1_586_044_800) == 2020);
+    }
+
+    #[test]
+    fn test_that_monthly_schedule_fires_once_in_the_month() {
+        //2020-01-15 to 2020-12-31
+        let dates = monthly_dates(&1_579_046_400, &1_609_372_800);
+        assert!(dates.len() == 12);
+        assert!(*dates[1] == 1_580_515_200);
+
+        let end_of_month = Schedule::EveryMonth(27).for_step(&Step::Monthly);
+        assert!(dates.iter().all(|date| end_of_month.check(date)));
+
+        let tax_year_start = Schedule::EveryYear(2, 4).for_step(&Step::Monthly);
+        let fired: Vec<&alator::types::DateTime> = dates
+            .iter()
+            .filter(|date| tax_year_start.check(date))
+            .collect();
+        assert!(fired.len() == 1);
+        assert!(tax_year(fired[0]) == 2019);
+
+        //First tick is mid-month so doesn't pick up the start of January
+        let start_of_month = Schedule::StartOfMonth.for_step(&Step::Monthly);
+        assert!(!start_of_month.check(&dates[0]));
+        assert!(start_of_month.check(&dates[1]));
+    }
+
+    #[test]
+    fn test_that_monthly_schedule_counts_days_in_the_tick() {
+        //2020-01-15 has 17 days left in the month, 2020-02-01 is a leap year February
+        let every_day = Schedule::EveryDay.for_step(&Step::Monthly);
+        assert!(every_day.count(&1_579_046_400) == 17);
+        assert!(every_day.count(&1_580_515_200) == 29);
+        assert!(Schedule::EveryDay.count(&1_580_515_200) == 1);
+
+        //Fridays in February 2020
+        let fridays = Schedule::EveryFriday.for_step(&Step::Monthly);
+        assert!(fridays.count(&1_580_515_200) == 4);
+        assert!(
+            Schedule::StartOfMonth
+                .for_step(&Step::Monthly)
+                .count(&1_579_046_400)
+                == 0
+        );
+    }
+
+    #[test]
+    fn test_that_until_stops_after_date() {
+        //2020-01-15 to 2020-12-31, ends 2020-06-27
+        let dates = monthly_dates(&1_579_046_400, &1_609_372_800);
+        let until = Schedule::Until(Box::new(Schedule::EveryMonth(27)), 1_593_216_000_i64.into());
+        assert!(until.check(&1_593_216_000));
+        assert!(!until.check(&1_595_808_000));
+
+        //Monthly ticks pick up the last payment in the month of the end date
+        let monthly = until.for_step(&Step::Monthly);
+        assert!(dates.iter().filter(|date| monthly.check(date)).count() == 6);
+    }
+}
@@ -4,13 +4,19 @@ use alator::types::StrategySnapshot;
 use alator::clock::Clock;
 use alator::types::CashValue;
 
-use crate::stack::{CanTransfer, TransferResult, BankAcc, Gia, Isa, Sipp};
+use crate::stack::{
+    repay_student_loans, student_loan_due, CanTransfer, TransferResult, BankAcc, Gia,
+    Isa, Jisa, Lisa, LoanEvent, Mortgage, Property, Sipp, StudentLoan, UKAccount,
+};
 use crate::input::HashMapSourceSim;
 use crate::input::SimDataSource;
-use crate::schedule::Schedule;
+use crate::schedule::{tax_year, Schedule, Step};
 use crate::strat::InvestmentStrategy;
-use crate::tax::uk::{NIC, UKTaxInput, TaxPeriod, UKTaxConfig};
-use crate::flow::Flow;
+use crate::tax::uk::{
+    EstateConfig, InheritanceTax, InheritanceTaxConfig, NIC, UKEstateBreakdown, UKTaxBreakdown,
+    UKTaxInput, TaxPeriod, UKTaxConfig, UKTaxTables,
+};
+use crate::flow::{AnnuityPurchase, Flow};
 
 #[derive(Clone, Debug)]
 pub struct UKSimulationPerformanceAnnualFrame {
@@ -37,27 +43,59 @@ pub enum SimState {
     //If in unrecoverable state, all further mutations to state stop should only happen when we run
     //out of money
     Unrecoverable,
+    //Estate has been valued at the date of death, no further mutations
+    Deceased,
 }
 
 //Each loop we check for rebalance, check tax, and then check for user-defined income events.
-pub struct UKSimulationState<S: InvestmentStrategy> {
+pub struct UKSimulationState<S: InvestmentStrategy, D: SimDataSource = HashMapSourceSim> {
     //Has to be ordered, tax has to be calculated first
     pub nic_group: NIC,
     pub annual_tax_schedule: Schedule,
     pub perf_schedule: Schedule,
+    //Bed-and-ISA/SIPP and JISA contributions run once a year, the day after taxes have been paid
+    //so that any gains fall into the new tax year. With a monthly clock both schedules fire on the
+    //April tick, taxes are always paid first in `update`.
+    pub tax_year_start_schedule: Schedule,
+    //Date of the last annual tax calculation, gains realised after this are taxed at the next one
+    pub last_tax_date: DateTime,
+    //Schedules above are already set for the step, this is used for flows created during the
+    //simulation
+    pub step: Step,
+    pub bed_and_isa: bool,
+    //Maximum net amount moved from GIA to SIPP, relief is added on top
+    pub bed_and_sipp: Option<f64>,
     pub clock: Clock,
     pub contribution_pct: f64,
     pub emergency_fund_minimum: f64,
-    pub source: HashMapSourceSim,
+    pub source: D,
     //At the moment, clients are not modifying the actual list of flows but the internal state can
     //change
-    pub flows: Vec<Flow>,
+    pub flows: Vec<Flow<D>>,
     pub bank: BankAcc,
     pub sipp: Sipp<S>,
     pub gia: Gia<S>,
     pub isa: Isa<S>,
+    pub lisa: Option<Lisa<S>>,
+    //JISA belongs to the child so isn't included in the value of the simulation
+    pub jisa: Option<Jisa<S>>,
+    //JISA becomes the child's adult ISA at 18, this still isn't part of the estate
+    pub child_isa: Option<Isa<S>>,
+    pub jisa_contribution: f64,
+    pub annuity_purchase: Option<AnnuityPurchase>,
+    pub student_loans: Vec<StudentLoan<D>>,
+    pub properties: Vec<Property<D>>,
+    pub mortgages: Vec<Mortgage<D>>,
     pub tax_config: UKTaxConfig,
+    pub tax_tables: UKTaxTables,
     pub sim_state: SimState,
+    //If this isn't set then the estate is valued at the end of the simulation
+    pub date_of_death: Option<DateTime>,
+    pub estate_config: EstateConfig,
+    pub iht_config: InheritanceTaxConfig,
+    pub estate: Option<UKEstateBreakdown>,
+    //Date that the simulation entered an unrecoverable state
+    pub ruin_date: Option<DateTime>,
 
     //All of this state is flushed at some point
     pub income_paid_in_curr_loop: CashValue,
@@ -67,12 +105,24 @@ pub struct UKSimulationState<S: InvestmentStrategy> {
     pub tax_paid_annual: CashValue,
     //This isn't used for reporting, this is used in tax calculations
     pub tax_paid_paye_annual: CashValue,
+    pub ni_paid_paye_annual: CashValue,
+    pub student_loan_paid_paye_annual: CashValue,
+    pub child_benefit_annual: CashValue,
     pub non_paye_income_annual: CashValue,
     pub paye_income_annual: CashValue,
+    pub pension_income_annual: CashValue,
     pub savings_income_annual: CashValue,
     pub rental_income_annual: CashValue,
     pub self_employment_income_annual: CashValue,
+    //Gains on the sale of property that isn't the main residence
+    pub residential_capital_gains_annual: CashValue,
     pub sipp_contributions_annual: CashValue,
+    //Gross of basic rate relief, this extends the basic rate band at the annual tax calculation
+    pub relief_at_source_contributions_annual: CashValue,
+    //Includes salary sacrifice, this isn't deducted from income at the annual tax calculation
+    pub employer_contributions_annual: CashValue,
+    //Defined benefit accrual is added to adjusted income for the annual allowance taper
+    pub defined_benefit_input_annual: CashValue,
     //Persists over the life of simulation
     //`StrategySnapshot` diffs the cash values so we have to provide total
     //sum
@@ -84,15 +134,22 @@ pub struct UKSimulationState<S: InvestmentStrategy> {
     pub sipp_snapshot: Vec<StrategySnapshot>,
     pub gia_snapshot: Vec<StrategySnapshot>,
     pub cash: Vec<CashValue>,
+    pub jisa_value: Vec<CashValue>,
+    pub property_value: Vec<CashValue>,
     pub gross_income: Vec<CashValue>,
     pub net_income: Vec<CashValue>,
     pub expense: Vec<CashValue>,
     //includes paye
     pub tax_paid: Vec<CashValue>,
     pub sipp_contributions: Vec<CashValue>,
+    pub employer_contributions: Vec<CashValue>,
+    //Includes PAYE and self-assessment repayments
+    pub student_loan_repaid: Vec<CashValue>,
+    pub student_loan_balance: Vec<CashValue>,
+    pub tax_breakdown: Vec<UKTaxBreakdown>,
 }
 
-impl<S: InvestmentStrategy> UKSimulationState<S> {
+impl<S: InvestmentStrategy, D: SimDataSource> UKSimulationState<S, D> {
     fn clear_loop(&mut self) {
         self.income_paid_in_curr_loop = CashValue::from(0.0);
     }
@@ -101,12 +158,80 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
     ///as a result, will not have any tracking data to return
     pub fn get_total_value(&self) -> CashValue {
         let total_value = *self.isa.liquidation_value()
+            + *self.lisa_value()
             + *self.gia.liquidation_value()
             + *self.sipp.liquidation_value()
-            + *self.bank.balance;
+            + *self.bank.balance
+            + *self.property_value();
         CashValue::from(total_value)
     }
 
+    //Estate is ISA, LISA, GIA, bank and property, the SIPP is only included depending on the date
+    //of death and configuration. JISA belongs to the child and student loans are written off on
+    //death. The main residence is added to any residence given in the config.
+    pub fn estate(&self) -> UKEstateBreakdown {
+        if let Some(estate) = &self.estate {
+            return estate.clone();
+        }
+
+        let curr_date = *self.clock.borrow().now();
+        let mut residence = 0.0;
+        let mut property = 0.0;
+        for p in &self.properties {
+            if p.is_main_residence() {
+                residence += *p.value();
+            } else {
+                property += *p.value();
+            }
+        }
+        //Mortgages are secured on the main residence first and then on other property, negative
+        //equity is taken from the rest of the estate
+        let debt = *self.mortgage_balance();
+        let residence_debt = debt.min(residence);
+        residence -= residence_debt;
+        property -= debt - residence_debt;
+        let assets = *self.isa.liquidation_value()
+            + *self.lisa_value()
+            + *self.gia.liquidation_value()
+            + *self.bank.balance
+            + property;
+        let pensions = *self.sipp.liquidation_value();
+        let estate_config = EstateConfig {
+            residence: self.estate_config.residence + residence,
+            ..self.estate_config.clone()
+        };
+        let input = estate_config.input(&assets, &pensions, &curr_date);
+        InheritanceTax::calc(&input, &self.iht_config, &curr_date)
+    }
+
+    //Equity after the outstanding mortgages, this can be negative
+    fn property_value(&self) -> CashValue {
+        let value = self.properties.iter().map(|p| *p.value()).sum::<f64>();
+        CashValue::from(value - *self.mortgage_balance())
+    }
+
+    fn mortgage_balance(&self) -> CashValue {
+        CashValue::from(self.mortgages.iter().map(|m| *m.balance()).sum::<f64>())
+    }
+
+    fn check_death(&mut self, curr_date: &DateTime) -> bool {
+        if let Some(date_of_death) = &self.date_of_death {
+            if curr_date >= date_of_death {
+                self.estate = Some(self.estate());
+                self.sim_state = SimState::Deceased;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn lisa_value(&self) -> CashValue {
+        if let Some(lisa) = &self.lisa {
+            return lisa.liquidation_value();
+        }
+        CashValue::default()
+    }
+
     pub fn update(&mut self) {
         match self.sim_state {
             SimState::Ready => {
@@ -114,8 +239,23 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
                 self.isa.check();
                 self.gia.check();
                 self.sipp.check();
+                if let Some(lisa) = self.lisa.as_mut() {
+                    lisa.check();
+                }
+                if let Some(jisa) = self.jisa.as_mut() {
+                    jisa.check();
+                }
+                if let Some(child_isa) = self.child_isa.as_mut() {
+                    child_isa.check();
+                }
+                for property in self.properties.iter_mut() {
+                    property.check();
+                }
 
                 let curr_date = self.clock.borrow().now();
+                if self.check_death(&curr_date) {
+                    return;
+                }
 
                 //Must be triggered early because if the strategy needs to generate cash to pay
                 //taxes then we record the cash flow out but the trades don't get executed until
@@ -123,17 +263,36 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
                 //record the state before this happens.
                 self.update_tracker();
 
+                self.pay_cash_interest(&curr_date);
                 self.rebalance_cash();
-                //Only triggers when schedule is met
+                self.check_student_loans();
+                self.pay_mortgages();
+                //Only triggers when schedule is met, has to run before anything on the tax year
+                //start schedule as both fire on the same tick with a monthly clock
                 self.pay_taxes(&curr_date);
+                self.bed_and_wrap(&curr_date);
+                self.convert_jisa();
+                self.contribute_jisa(&curr_date);
+                self.first_home_purchase();
+                self.sell_properties();
+                self.purchase_annuity(&curr_date);
 
                 self.isa.rebalance();
                 self.gia.rebalance();
                 self.sipp.rebalance();
+                if let Some(lisa) = self.lisa.as_mut() {
+                    lisa.rebalance();
+                }
+                if let Some(jisa) = self.jisa.as_mut() {
+                    jisa.rebalance();
+                }
+                if let Some(child_isa) = self.child_isa.as_mut() {
+                    child_isa.rebalance();
+                }
 
                 //We cannot pass the reference to self to flows whilst iterating over flows which are also
                 //on self, we therefore need to clone
-                let mut cloned_flows: Vec<Flow> = self.flows.to_vec();
+                let mut cloned_flows: Vec<Flow<D>> = self.flows.to_vec();
                 for flow in cloned_flows.iter_mut() {
                     flow.check(&curr_date, self);
                 }
@@ -146,8 +305,17 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
                 self.isa.finish();
                 self.gia.finish();
                 self.sipp.finish();
+                if let Some(lisa) = self.lisa.as_mut() {
+                    lisa.finish();
+                }
+                if let Some(jisa) = self.jisa.as_mut() {
+                    jisa.finish();
+                }
+                if let Some(child_isa) = self.child_isa.as_mut() {
+                    child_isa.finish();
+                }
             }
-            SimState::Unrecoverable => {}
+            SimState::Unrecoverable | SimState::Deceased => {}
         }
     }
 
@@ -158,9 +326,13 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
         if self.perf_schedule.check(&curr_date) {
             let trailing_month_inflation = self.source.get_trailing_month_inflation();
 
+            //LISA is reported with the ISA, deposits are tracked in the same way but the bonus
+            //shows up as performance
             let isa_snapshot = StrategySnapshot {
                 date: curr_date.clone(),
-                portfolio_value: self.isa.liquidation_value(),
+                portfolio_value: CashValue::from(
+                    *self.isa.liquidation_value() + *self.lisa_value(),
+                ),
                 net_cash_flow: self.paid_into_isa_since_start.clone(),
                 inflation: trailing_month_inflation.clone(),
             };
@@ -180,6 +352,14 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
             self.gia_snapshot.push(gia_snapshot);
             self.sipp_snapshot.push(sipp_snapshot);
             self.cash.push(self.bank.balance.clone());
+            if let Some(jisa) = &self.jisa {
+                self.jisa_value.push(jisa.liquidation_value());
+            } else if let Some(child_isa) = &self.child_isa {
+                self.jisa_value.push(child_isa.liquidation_value());
+            }
+            if !self.properties.is_empty() {
+                self.property_value.push(self.property_value());
+            }
         }
     }
 
@@ -191,14 +371,22 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
     // balance which happens for non-paye income).
     // We don't check the date here because this should only be called by taxation paying
     // code that has already performed this check.
-    fn clear_annual(&mut self) {
+    fn clear_annual(&mut self, student_loan_self_assessment: &f64) {
         self.gross_income.push(self.gross_income_annual.clone());
         self.net_income.push(self.net_income_annual.clone());
         self.expense.push(self.expense_annual.clone());
         //This is confusing but we don't need to PAYE here
         self.tax_paid.push(self.tax_paid_annual.clone());
-        self.sipp_contributions
-            .push(self.sipp_contributions_annual.clone());
+        self.sipp_contributions.push(
+            self.sipp_contributions_annual.clone()
+                + self.relief_at_source_contributions_annual.clone(),
+        );
+        self.employer_contributions
+            .push(self.employer_contributions_annual.clone());
+        self.student_loan_repaid.push(CashValue::from(
+            *self.student_loan_paid_paye_annual + student_loan_self_assessment,
+        ));
+        self.student_loan_balance.push(self.student_loan_balance_total());
 
         //Reset the annual trackers to zero
         self.gross_income_annual = CashValue::from(0.0);
@@ -207,11 +395,182 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
         self.tax_paid_annual = CashValue::from(0.0);
         self.non_paye_income_annual = CashValue::from(0.0);
         self.paye_income_annual = CashValue::from(0.0);
+        self.pension_income_annual = CashValue::from(0.0);
         self.savings_income_annual = CashValue::from(0.0);
         self.rental_income_annual = CashValue::from(0.0);
         self.self_employment_income_annual = CashValue::from(0.0);
+        self.residential_capital_gains_annual = CashValue::from(0.0);
         self.sipp_contributions_annual = CashValue::from(0.0);
+        self.relief_at_source_contributions_annual = CashValue::from(0.0);
+        self.employer_contributions_annual = CashValue::from(0.0);
+        self.defined_benefit_input_annual = CashValue::from(0.0);
         self.tax_paid_paye_annual = CashValue::from(0.0);
+        self.ni_paid_paye_annual = CashValue::from(0.0);
+        self.student_loan_paid_paye_annual = CashValue::from(0.0);
+        self.child_benefit_annual = CashValue::from(0.0);
+
+        self.isa.tax_year_end();
+        self.sipp.tax_year_end();
+        if let Some(lisa) = self.lisa.as_mut() {
+            lisa.tax_year_end();
+        }
+        if let Some(jisa) = self.jisa.as_mut() {
+            jisa.tax_year_end();
+        }
+        if let Some(child_isa) = self.child_isa.as_mut() {
+            child_isa.tax_year_end();
+        }
+    }
+
+    fn convert_jisa(&mut self) {
+        if let Some(jisa) = &self.jisa {
+            if jisa.is_adult() {
+                self.child_isa = self.jisa.take().map(Jisa::into_adult_isa);
+            }
+        }
+    }
+
+    //Parent pays a fixed amount into the JISA from the bank at the start of each tax year, this
+    //leaves the simulation so is recorded as an expense
+    fn contribute_jisa(&mut self, curr_date: &DateTime) {
+        if self.jisa_contribution <= 0.0 {
+            return;
+        }
+
+        if self.tax_year_start_schedule.check(curr_date) {
+            //Nothing is paid once the JISA has become an adult ISA
+            if let Some(jisa) = self.jisa.as_mut() {
+                let mut contribution = self.jisa_contribution;
+                if contribution > *self.bank.balance {
+                    contribution = *self.bank.balance;
+                }
+                if let TransferResult::Success = self.bank.withdraw(&contribution) {
+                    let (deposited, remainder) = jisa.deposit_wrapper(&contribution);
+                    self.bank.deposit(&remainder);
+                    self.expense_annual = self.expense_annual.clone() + deposited;
+                }
+            }
+        }
+    }
+
+    //LISA balance is withdrawn penalty-free when the first home is bought and spent on the
+    //purchase, so it is counted as an expense rather than going to the bank
+    fn first_home_purchase(&mut self) {
+        if let Some(lisa) = self.lisa.as_mut() {
+            let value = lisa.withdraw_for_first_home();
+            if *value > 0.0 {
+                self.expense_annual = CashValue::from(*self.expense_annual + *value);
+                self.paid_into_isa_since_start =
+                    CashValue::from(*self.paid_into_isa_since_start - *value);
+            }
+        }
+    }
+
+    //Proceeds go to the bank and are invested from there, the gain is taxed at the end of the tax
+    //year
+    fn sell_properties(&mut self) {
+        for property in self.properties.iter_mut() {
+            if property.sale_due() {
+                let (proceeds, gain) = property.sell();
+                self.bank.deposit(&proceeds);
+                self.residential_capital_gains_annual =
+                    self.residential_capital_gains_annual.clone() + gain;
+            }
+        }
+    }
+
+    //Annuity is priced at purchase and added to the flows
+    fn purchase_annuity(&mut self, curr_date: &DateTime) {
+        let is_due = match &self.annuity_purchase {
+            Some(purchase) => purchase.is_due(curr_date),
+            None => false,
+        };
+        if !is_due {
+            return;
+        }
+
+        let purchase = self.annuity_purchase.take().unwrap();
+        let amount = purchase.amount(&self.sipp.liquidation_value());
+        let price = self.sipp.purchase_annuity(&amount);
+        if *price <= 0.0 {
+            return;
+        }
+        self.paid_into_sipp_since_start =
+            CashValue::from(*self.paid_into_sipp_since_start - *price);
+
+        let rate = self.source.get_current_interest_rate().unwrap_or(0.0);
+        //Current inflation is daily, pricing needs an annual rate
+        let inflation = self.source.get_trailing_year_inflation();
+        let annual_income = purchase.annual_income(&price, curr_date, &rate, &inflation);
+        self.flows.push(purchase.flow(
+            &annual_income,
+            curr_date,
+            self.source.clone(),
+            &self.step,
+        ));
+    }
+
+    //Sells GIA holdings and buys them back inside the ISA, and optionally the SIPP, using the new
+    //tax year's allowances. We only sell enough to crystallise gains up to the CGT exempt amount.
+    //The sales are recorded in the GIA trade history so the gains are picked up by the next
+    //tax calculation.
+    fn bed_and_wrap(&mut self, curr_date: &DateTime) {
+        if !self.bed_and_isa && self.bed_and_sipp.is_none() {
+            return;
+        }
+
+        if self.tax_year_start_schedule.check(curr_date) {
+            let gia_value = *self.gia.liquidation_value();
+            if gia_value <= 0.0 {
+                return;
+            }
+
+            //Maximum amount that can be sold before we crystallise more than the exempt amount
+            //left after gains already realised this tax year, if there are no gains then we can
+            //sell everything. Cash has no gain and is taken before holdings are sold.
+            let holdings_value = *self.gia.holdings_value();
+            let gain = *self.gia.get_unrealised_gain(curr_date);
+            let mut can_sell = gia_value;
+            if gain > 0.0 && holdings_value > 0.0 {
+                let gain_pct = gain / holdings_value;
+                let realised = *self.gia.get_capital_gains(curr_date, &self.last_tax_date);
+                let exempt = (*self.tax_config.capital_gains_allowance() - realised).max(0.0);
+                let cash = gia_value - holdings_value;
+                if cash + exempt / gain_pct < can_sell {
+                    can_sell = cash + exempt / gain_pct;
+                }
+            }
+
+            if self.bed_and_isa {
+                let transferred = self.isa.transfer_wrapper(&mut self.gia, &can_sell);
+                self.paid_into_isa_since_start =
+                    CashValue::from(*self.paid_into_isa_since_start + *transferred);
+                self.paid_into_gia_since_start =
+                    CashValue::from(*self.paid_into_gia_since_start - *transferred);
+                can_sell -= *transferred;
+            }
+
+            if let Some(sipp_max) = self.bed_and_sipp {
+                let sipp_amount = if sipp_max < can_sell {
+                    sipp_max
+                } else {
+                    can_sell
+                };
+                if sipp_amount > 0.0 {
+                    let relief_rate = *self.tax_config.basic_income_rate();
+                    let (net, gross) =
+                        self.sipp
+                            .transfer_with_relief(&mut self.gia, &sipp_amount, &relief_rate);
+                    self.paid_into_sipp_since_start =
+                        CashValue::from(*self.paid_into_sipp_since_start + *gross);
+                    //Contributions outside of payroll always receive relief at source
+                    self.relief_at_source_contributions_annual =
+                        self.relief_at_source_contributions_annual.clone() + gross;
+                    self.paid_into_gia_since_start =
+                        CashValue::from(*self.paid_into_gia_since_start - *net);
+                }
+            }
+        }
     }
 
     fn rebalance_cash(&mut self) {
@@ -224,7 +583,17 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
             self.bank.withdraw(&excess_cash);
             //If we are over ISA deposit limit, invest what is possible then return the remainder
             //which can go into GIA
-            let (deposited, remainder) = self.isa.deposit_wrapper(&excess_cash);
+            //LISA is filled first because of the bonus, and uses up the ISA allowance
+            let mut isa_cash = excess_cash;
+            if let Some(lisa) = self.lisa.as_mut() {
+                let (deposited, remainder) =
+                    lisa.deposit_wrapper(&isa_cash, &self.isa.remaining_allowance());
+                self.isa.use_allowance(&deposited);
+                self.paid_into_isa_since_start =
+                    CashValue::from(*self.paid_into_isa_since_start + *deposited);
+                isa_cash = *remainder;
+            }
+            let (deposited, remainder) = self.isa.deposit_wrapper(&isa_cash);
             self.paid_into_isa_since_start =
                 CashValue::from(*self.paid_into_isa_since_start + *deposited);
             if *remainder > 0.0 {
@@ -235,15 +604,132 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
         }
     }
 
+    //Bank pays the short rate monthly, this is taxed as savings income
+    fn pay_cash_interest(&mut self, curr_date: &DateTime) {
+        if self.perf_schedule.check(curr_date) {
+            let rate = self.source.get_current_interest_rate().unwrap_or(0.0).max(0.0);
+            let interest = *self.bank.balance * (rate / 12.0);
+            if interest > 0.0 {
+                self.bank.deposit(&interest);
+                self.savings_income_annual =
+                    CashValue::from(*self.savings_income_annual + interest);
+                self.net_income_annual = CashValue::from(*self.net_income_annual + interest);
+            }
+        }
+    }
+
+    fn student_loan_balance_total(&self) -> CashValue {
+        CashValue::from(
+            self.student_loans
+                .iter()
+                .map(|loan| *loan.balance())
+                .sum::<f64>(),
+        )
+    }
+
+    //Interest on Plan 2 depends on income, we use the income from the last complete tax year
+    //Mortgages are paid from the bank and then from investments in the same way as expenses
+    fn pay_mortgages(&mut self) {
+        for pos in 0..self.mortgages.len() {
+            let event = self.mortgages[pos].pay(None, &mut self.bank);
+            if let Some(LoanEvent::PaymentFailure(due)) = event {
+                if let TransferResult::Failure = self.liquidate_for_payment(&due) {
+                    let curr_date = self.clock.borrow().now();
+                    self.ruin(&curr_date);
+                    return;
+                }
+                self.bank.deposit(&due);
+                self.mortgages[pos].pay(None, &mut self.bank);
+            }
+        }
+    }
+
+    fn check_student_loans(&mut self) {
+        let income = self.gross_income.last().map(|v| **v).unwrap_or(0.0);
+        for loan in self.student_loans.iter_mut() {
+            loan.check(&income);
+        }
+    }
+
+    //Income that isn't taxed through PAYE is repaid through self-assessment on the annual
+    //threshold, net of anything already deducted through PAYE. Returns the amount repaid.
+    fn repay_student_loans_self_assessment(&mut self) -> CashValue {
+        let non_paye_income = *self.non_paye_income_annual
+            + *self.self_employment_income_annual
+            + *self.rental_income_annual;
+        if self.student_loans.is_empty() || non_paye_income <= 0.0 {
+            return CashValue::from(0.0);
+        }
+
+        let income = non_paye_income + *self.paye_income_annual;
+        let due = *student_loan_due(&self.student_loans, &income, false)
+            - *self.student_loan_paid_paye_annual;
+        if due <= 0.0 {
+            return CashValue::from(0.0);
+        }
+        repay_student_loans(&mut self.student_loans, &due)
+    }
+
+    //Sells the ISA, then the GIA and then the LISA, after any penalty, to make a payment that the
+    //bank can't cover. Fails without selling anything if all of these together aren't enough.
+    fn liquidate_for_payment(&mut self, payment: &f64) -> TransferResult {
+        let isa_value = *self.isa.liquidation_value();
+        let gia_value = *self.gia.liquidation_value();
+        let lisa_value = self.lisa.as_ref().map_or(0.0, |lisa| *lisa.withdrawable_value());
+        if isa_value + gia_value + lisa_value < *payment {
+            return TransferResult::Failure;
+        }
+
+        let from_isa = payment.min(isa_value);
+        let from_gia = (payment - from_isa).min(gia_value);
+        let from_lisa = payment - from_isa - from_gia;
+        if from_isa > 0.0 {
+            self.isa.liquidate(&from_isa);
+        }
+        if from_gia > 0.0 {
+            self.gia.liquidate(&from_gia);
+            self.paid_into_gia_since_start =
+                CashValue::from(*self.paid_into_gia_since_start - from_gia);
+        }
+        if let Some(lisa) = self.lisa.as_mut().filter(|_| from_lisa > 0.0) {
+            lisa.liquidate(&from_lisa);
+        }
+        //LISA is tracked with the ISA
+        self.paid_into_isa_since_start =
+            CashValue::from(*self.paid_into_isa_since_start - from_isa - from_lisa);
+        TransferResult::Success
+    }
+
+    //Simulation has run out of money, all the accounts are zeroed and nothing runs after this
+    fn ruin(&mut self, curr_date: &DateTime) {
+        self.sim_state = SimState::Unrecoverable;
+        self.ruin_date = Some(curr_date.clone());
+        self.gia.zero();
+        self.isa.zero();
+        if let Some(lisa) = self.lisa.as_mut() {
+            lisa.zero();
+        }
+        self.sipp.zero();
+        self.bank.zero();
+    }
+
+    //Expenses are paid from the bank and then from the ISA and GIA in the same way as taxes. If
+    //there still isn't enough then the simulation has run out of money.
+    pub fn pay_expense(&mut self, amount: &f64) {
+        if let SimState::Unrecoverable = self.sim_state {
+            return;
+        }
+        self.expense_annual = CashValue::from(*self.expense_annual + *amount);
+        if let TransferResult::Failure = self.bank.withdraw(amount) {
+            if let TransferResult::Failure = self.liquidate_for_payment(amount) {
+                let curr_date = self.clock.borrow().now();
+                self.ruin(&curr_date);
+            }
+        }
+    }
+
     fn pay_taxes(&mut self, curr_date: &DateTime) {
         if self.annual_tax_schedule.check(curr_date) {
-            //Inflation is annualized but with daily frequency, so we should always have an annual
-            //number
-            let inflation = self.source.get_current_inflation().unwrap();
-            let curr_config = &self.tax_config;
-            let new_config = curr_config.apply_inflation(&inflation);
-            self.tax_config = new_config.clone();
-
             let mut capital_gains = 0.0;
             let mut dividends_received = 0.0;
 
@@ -252,57 +738,71 @@ impl<S: InvestmentStrategy> UKSimulationState<S> {
                 capital_gains += *self.gia.get_capital_gains(curr_date, &period_start);
                 dividends_received += *self.gia.get_dividends(curr_date, &period_start);
             }
+            self.last_tax_date = curr_date.clone();
+
+            //Personal contributions are deducted from threshold income, employer contributions
+            //and defined benefit accrual are added to adjusted income
+            let threshold_income = *self.gross_income_annual
+                - *self.sipp_contributions_annual
+                - *self.relief_at_source_contributions_annual;
+            let adjusted_income = *self.gross_income_annual
+                + *self.employer_contributions_annual
+                + *self.defined_benefit_input_annual;
+            let annual_allowance =
+                UKAccount::tapered_annual_allowance(&threshold_income, &adjusted_income);
+            let annual_allowance_excess = self.sipp.annual_allowance_excess(&annual_allowance);
 
             let input = UKTaxInput {
                 non_paye_employment: self.non_paye_income_annual.clone(),
                 paye_employment: self.paye_income_annual.clone(),
+                pension: self.pension_income_annual.clone(),
                 rental: self.rental_income_annual.clone(),
                 savings: self.savings_income_annual.clone(),
                 self_employment: self.self_employment_income_annual.clone(),
                 ni: self.nic_group,
                 paye_tax_paid: self.tax_paid_paye_annual.clone(),
+                paye_ni_paid: self.ni_paid_paye_annual.clone(),
                 contributions: self.sipp_contributions_annual.clone(),
                 capital_gains: capital_gains.into(),
+                residential_capital_gains: self.residential_capital_gains_annual.clone(),
                 dividend: dividends_received.into(),
+                annual_allowance_excess,
+                relief_at_source_contributions: self.relief_at_source_contributions_annual.clone(),
+                child_benefit: self.child_benefit_annual.clone(),
             };
 
             let output = TaxPeriod::calc(&input, &self.tax_config);
+            self.tax_breakdown.push(output.breakdown(tax_year(curr_date)));
             let tax_due = output.total();
-            if let TransferResult::Failure = self.bank.withdraw(&tax_due) {
+            //Self-assessment student loan repayments are collected with the tax bill but aren't
+            //tax
+            let student_loan_self_assessment = self.repay_student_loans_self_assessment();
+            let payment_due = tax_due.clone() + student_loan_self_assessment.clone();
+            if let TransferResult::Failure = self.bank.withdraw(&payment_due) {
                 //Not enough cash in bank to pay taxes, liquidate cash accounts if there is still
                 //not enough then enter unrecoverable state which pauses all forward progress with
                 //simulation
-
-                let cash_value = *self.gia.liquidation_value() + *self.isa.liquidation_value();
-                if cash_value < *tax_due {
-                    self.sim_state = SimState::Unrecoverable;
-                    //In unrecoverable state, zero all the accounts
-                    self.gia.zero();
-                    self.isa.zero();
-                    self.sipp.zero();
-                    self.bank.zero();
-                    self.clear_annual();
-                } else if *self.isa.liquidation_value() > *tax_due {
-                    self.isa.liquidate(&tax_due);
-                    self.paid_into_isa_since_start =
-                        CashValue::from(*self.paid_into_isa_since_start - *tax_due);
-                    self.tax_paid_annual = self.tax_paid_annual.clone() + tax_due;
-                    self.clear_annual();
+                if let TransferResult::Failure = self.liquidate_for_payment(&payment_due) {
+                    self.ruin(curr_date);
                 } else {
-                    let isa_value = self.isa.liquidation_value();
-                    self.isa.liquidate(&isa_value);
-                    self.paid_into_isa_since_start =
-                        CashValue::from(*self.paid_into_isa_since_start - *isa_value);
-                    let remainder = *tax_due - *isa_value;
-                    self.gia.liquidate(&remainder);
-                    self.paid_into_gia_since_start =
-                        CashValue::from(*self.paid_into_gia_since_start - remainder);
                     self.tax_paid_annual = self.tax_paid_annual.clone() + tax_due;
-                    self.clear_annual();
                 }
+                self.clear_annual(&student_loan_self_assessment);
             } else {
                 self.tax_paid_annual = self.tax_paid_annual.clone() + tax_due;
-                self.clear_annual();
+                self.clear_annual(&student_loan_self_assessment);
+            }
+
+            //Tax for the year that has ended uses that year's config, the next year's config is
+            //only used from here. Current inflation is a daily rate so thresholds are uprated by
+            //inflation over the year that has ended.
+            let inflation = self.source.get_trailing_year_inflation();
+            let next_tax_year = tax_year(curr_date) + 1;
+            self.tax_config = self
+                .tax_tables
+                .next(&self.tax_config, &next_tax_year, &inflation);
+            for loan in self.student_loans.iter_mut() {
+                loan.apply_inflation(&inflation);
             }
         }
     }
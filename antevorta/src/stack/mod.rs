@@ -1,11 +1,14 @@
+use std::collections::{HashMap, VecDeque};
+
 use alator::broker::{DividendPayment, Trade, TradeType};
 use alator::clock::Clock;
 use alator::strategy::StrategyEvent;
 use alator::types::{CashValue, DateTime};
+use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 
 use crate::input::SimDataSource;
-use crate::schedule::Schedule;
+use crate::schedule::{years_after, years_between, Schedule, Step};
 use crate::strat::InvestmentStrategy;
 
 
@@ -62,10 +65,14 @@ pub enum TransferResult {
 
 pub enum Stack<S: InvestmentStrategy, D: SimDataSource> {
     Isa(Isa<S>),
+    Lisa(Lisa<S>),
+    Jisa(Jisa<S>),
     Sipp(Sipp<S>),
     Gia(Gia<S>),
     BankAcc(BankAcc),
     Mortgage(Mortgage<D>),
+    StudentLoan(StudentLoan<D>),
+    Property(Property<D>),
 }
 
 //Calculates capital_gains realised in the current tax year
@@ -116,19 +123,61 @@ fn calculate_capital_gains(all_trades: &[Trade], tax_year_start: &i64) -> CashVa
     CashValue::from(capital_gain)
 }
 
+//Calculates the average cost of all holdings at the date of the last trade
+//Average cost is used because this is how UK capital gains are pooled (ignoring bed and breakfast
+//rules)
+fn calculate_cost_basis(all_trades: &[Trade]) -> CashValue {
+    //Symbol => (quantity, cost)
+    let mut pool: HashMap<String, (f64, f64)> = HashMap::new();
+    for trade in all_trades {
+        let entry = pool.entry(trade.symbol.clone()).or_insert((0.0, 0.0));
+        match trade.typ {
+            TradeType::Buy => {
+                entry.0 += *trade.quantity;
+                entry.1 += *trade.value;
+            }
+            TradeType::Sell => {
+                if entry.0 > 0.0 {
+                    let avg_price = entry.1 / entry.0;
+                    entry.1 -= avg_price * *trade.quantity;
+                    entry.0 -= *trade.quantity;
+                }
+            }
+        }
+    }
+    let cost: f64 = pool.values().map(|(_qty, cost)| cost).sum();
+    CashValue::from(cost)
+}
+
+const LISA_BONUS_RATE: f64 = 0.25;
+const LISA_WITHDRAWAL_PENALTY_RATE: f64 = 0.25;
+const LISA_LAST_CONTRIBUTION_AGE: i32 = 50;
+const LISA_PENALTY_FREE_AGE: i32 = 60;
+const JISA_ADULT_AGE: i32 = 18;
+
 #[allow(clippy::enum_variant_names)]
 pub enum UKAccount {
     IsaAnnualDepositThreshold,
-    SippAnnualContributionThreshold,
-    SippLifetimeContributionThreshold,
+    LisaAnnualDepositThreshold,
+    JisaAnnualDepositThreshold,
+    SippAnnualAllowance,
+    SippMinimumTaperedAllowance,
+    SippTaperThresholdIncome,
+    SippTaperAdjustedIncome,
+    SippMoneyPurchaseAnnualAllowance,
 }
 
 impl UKAccount {
     pub fn val(&self) -> CashValue {
         match self {
             UKAccount::IsaAnnualDepositThreshold => 20_000.0.into(),
-            UKAccount::SippAnnualContributionThreshold => 40_000.0.into(),
-            UKAccount::SippLifetimeContributionThreshold => 1_073_100.0.into(),
+            UKAccount::LisaAnnualDepositThreshold => 4_000.0.into(),
+            UKAccount::JisaAnnualDepositThreshold => 9_000.0.into(),
+            UKAccount::SippAnnualAllowance => 60_000.0.into(),
+            UKAccount::SippMinimumTaperedAllowance => 10_000.0.into(),
+            UKAccount::SippTaperThresholdIncome => 200_000.0.into(),
+            UKAccount::SippTaperAdjustedIncome => 260_000.0.into(),
+            UKAccount::SippMoneyPurchaseAnnualAllowance => 10_000.0.into(),
         }
     }
 
@@ -161,39 +210,71 @@ impl UKAccount {
     //Need to split this for testing
     pub fn sipp_deposit_logic(
         amount: &f64,
-        current_year_contribution_threshold: &f64,
-        lifetime_contribution_threshold: &f64,
+        available_allowance: &f64,
         current_year_contributions: &f64,
-        lifetime_contributions: &f64,
     ) -> (CashValue, CashValue) {
-        let can_deposit_year = *current_year_contribution_threshold - *current_year_contributions;
-        let can_deposit_life = *lifetime_contribution_threshold - *lifetime_contributions;
+        //Same logic as ISA but the allowance can vary by year
+        Self::isa_deposit_logic(amount, available_allowance, current_year_contributions)
+    }
 
-        if can_deposit_year <= 0.0 || can_deposit_life <= 0.0 {
-            //Over at least one threshold, can't deposit at all
-            (CashValue::from(0.0), CashValue::from(*amount))
-        } else if amount < &can_deposit_year && amount < &can_deposit_life {
-            //Under both thresholds, deposit full amount
-            (CashValue::from(*amount), CashValue::from(0.0))
+    //Returns contributions in excess of the allowance after using carry-forward, and updates
+    //carry-forward with this year's unused allowance
+    //Need to split this for testing
+    pub fn sipp_carry_forward_logic(
+        contributions: &f64,
+        annual_allowance: &f64,
+        carry_forward: &mut VecDeque<CashValue>,
+    ) -> CashValue {
+        let mut excess = *contributions - *annual_allowance;
+        if excess < 0.0 {
+            carry_forward.push_back(CashValue::from(-excess));
+            excess = 0.0;
         } else {
-            //Deposit would take us over at least one threshold
-            //Find the lowest threshold, deposit as much as possible
-            //under that threshold
-            if can_deposit_year > can_deposit_life {
-                (
-                    CashValue::from(can_deposit_life),
-                    CashValue::from(*amount - can_deposit_life),
-                )
+            carry_forward.push_back(CashValue::default());
+        }
+
+        //Carry-forward is only available from the previous years, not the year just added
+        let curr_year = carry_forward.len() - 1;
+        for unused in carry_forward.iter_mut().take(curr_year) {
+            if excess <= 0.0 {
+                break;
+            }
+            if **unused > excess {
+                *unused = CashValue::from(**unused - excess);
+                excess = 0.0;
             } else {
-                (
-                    CashValue::from(can_deposit_year),
-                    CashValue::from(*amount - can_deposit_year),
-                )
+                excess -= **unused;
+                *unused = CashValue::default();
             }
         }
+
+        while carry_forward.len() > SIPP_CARRY_FORWARD_YEARS {
+            carry_forward.pop_front();
+        }
+        CashValue::from(excess)
     }
 
+    //Allowance is reduced by £1 for every £2 of adjusted income over the limit, down to a minimum.
+    //Taper only applies if threshold income is also over the limit.
+    pub fn tapered_annual_allowance(threshold_income: &f64, adjusted_income: &f64) -> CashValue {
+        let allowance = *UKAccount::SippAnnualAllowance.val();
+        if *threshold_income <= *UKAccount::SippTaperThresholdIncome.val() {
+            return CashValue::from(allowance);
+        }
+
+        let income_over = *adjusted_income - *UKAccount::SippTaperAdjustedIncome.val();
+        if income_over <= 0.0 {
+            return CashValue::from(allowance);
+        }
 
+        let tapered = allowance - (income_over / 2.0);
+        let minimum = *UKAccount::SippMinimumTaperedAllowance.val();
+        if tapered < minimum {
+            CashValue::from(minimum)
+        } else {
+            CashValue::from(tapered)
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -240,9 +321,43 @@ impl<S: InvestmentStrategy> Isa<S> {
             &self.current_tax_year_deposits,
         );
         self.deposit(&deposit);
+        self.current_tax_year_deposits =
+            CashValue::from(*self.current_tax_year_deposits + *deposit);
         (deposit, returned)
     }
 
+    //Moves cash from another account into the ISA, liquidating the source if required. Used when
+    //we are moving assets rather than cash so counts against the deposit limit in the same way.
+    //Returns the amount transferred.
+    pub fn transfer_wrapper(&mut self, source: &mut impl CanTransfer, amount: &f64) -> CashValue {
+        let (deposit, _returned) = UKAccount::isa_deposit_logic(
+            amount,
+            &UKAccount::IsaAnnualDepositThreshold.val(),
+            &self.current_tax_year_deposits,
+        );
+        if *deposit <= 0.0 {
+            return CashValue::default();
+        }
+        if let TransferResult::Success = Transfer::force(source, self, &deposit) {
+            self.current_tax_year_deposits =
+                CashValue::from(*self.current_tax_year_deposits + *deposit);
+            return deposit;
+        }
+        CashValue::default()
+    }
+
+    //LISA deposits count against the ISA allowance but are made into a different account
+    pub fn remaining_allowance(&self) -> CashValue {
+        CashValue::from(
+            *UKAccount::IsaAnnualDepositThreshold.val() - *self.current_tax_year_deposits,
+        )
+    }
+
+    pub fn use_allowance(&mut self, amount: &f64) {
+        self.current_tax_year_deposits =
+            CashValue::from(*self.current_tax_year_deposits + *amount);
+    }
+
     pub fn new_with_cash(strat: S, start_cash: &f64) -> Self {
         let mut s = Self::new(strat);
         s.strat.init(start_cash);
@@ -285,20 +400,29 @@ impl<S: InvestmentStrategy> CanTransfer for Isa<S> {
     }
 }
 
+//Lifetime ISA. Deposits are limited to a sub-limit of the ISA allowance, and receive a government
+//bonus. Withdrawals are penalised unless the owner has reached the penalty-free age, only the
+//withdrawal for the first home purchase is exempt before then.
 #[derive(Clone, Debug)]
-pub struct Gia<S: InvestmentStrategy> {
+pub struct Lisa<S: InvestmentStrategy> {
     strat: S,
+    current_tax_year_deposits: CashValue,
+    date_of_birth: DateTime,
+    first_home_purchase: Option<DateTime>,
+    first_home_purchased: bool,
+    clock: Clock,
 }
 
-impl<S: InvestmentStrategy> Gia<S> {
+//Tax year state is controlled from within the simulation.
+impl<S: InvestmentStrategy> Lisa<S> {
     pub fn zero(&mut self) {
         self.strat.zero();
     }
 
-    pub fn get_capital_gains(&self, date: &i64, tax_year_start: &i64) -> CashValue {
-        //To calculate capital gains we need the whole trade history for that symbol
-        let all_trades: Vec<Trade> = self.strat.trades_between(&0, date);
-        calculate_capital_gains(&all_trades, tax_year_start)
+    pub fn rebalance(&mut self) {
+        if *self.strat.get_liquidation_value() > 0.0 {
+            self.strat.update();
+        }
     }
 
     pub fn check(&mut self) {
@@ -309,39 +433,124 @@ impl<S: InvestmentStrategy> Gia<S> {
         self.strat.finish();
     }
 
-    //broker
-    pub fn get_dividends(&self, date: &i64, tax_year_start: &i64) -> CashValue {
-        let all_dividends: Vec<DividendPayment> =
-            self.strat.dividends_between(tax_year_start, date);
-        let mut sum = 0.0;
-        for divi in all_dividends {
-            sum += *divi.value;
-        }
-        CashValue::from(sum)
+    pub fn tax_year_end(&mut self) {
+        self.current_tax_year_deposits = CashValue::default();
     }
 
     pub fn liquidation_value(&self) -> CashValue {
         self.strat.get_liquidation_value()
     }
 
-    pub fn rebalance(&mut self) {
-        if *self.strat.get_liquidation_value() > 0.0 {
-            self.strat.update();
+    fn age(&self) -> i32 {
+        let now = self.clock.borrow().now();
+        years_between(&self.date_of_birth, &now)
+    }
+
+    fn is_penalty_free(&self) -> bool {
+        self.age() >= LISA_PENALTY_FREE_AGE
+    }
+
+    //Amount that can be taken out after the penalty
+    pub fn withdrawable_value(&self) -> CashValue {
+        let value = *self.liquidation_value();
+        if self.is_penalty_free() {
+            return CashValue::from(value);
         }
+        CashValue::from(value * (1.0 - LISA_WITHDRAWAL_PENALTY_RATE))
     }
 
-    pub fn new_with_cash(strat: S, start_cash: &f64) -> Self {
-        let mut s = Self::new(strat);
+    //Returns true once, on the first call after the first home purchase date has passed
+    fn first_home_purchase_due(&mut self) -> bool {
+        if self.first_home_purchased {
+            return false;
+        }
+        if let Some(purchase_date) = &self.first_home_purchase {
+            let now = self.clock.borrow().now();
+            if now >= *purchase_date {
+                self.first_home_purchased = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    //Withdraws the balance penalty-free once the first home purchase date has passed, returns the
+    //amount withdrawn. Later withdrawals before the penalty-free age are penalised.
+    pub fn withdraw_for_first_home(&mut self) -> CashValue {
+        if !self.first_home_purchase_due() {
+            return CashValue::default();
+        }
+        let value = *self.liquidation_value();
+        if value <= 0.0 {
+            return CashValue::default();
+        }
+        if let StrategyEvent::WithdrawSuccess(_amount) =
+            self.strat.withdraw_cash_with_liquidation(&value)
+        {
+            return CashValue::from(value);
+        }
+        CashValue::default()
+    }
+
+    //Deposit is limited by both the LISA sub-limit and whatever is left of the ISA allowance, the
+    //bonus is paid on top and doesn't count against either. Returns (deposit amount, return to
+    //client).
+    pub fn deposit_wrapper(
+        &mut self,
+        amount: &f64,
+        isa_remaining_allowance: &f64,
+    ) -> (CashValue, CashValue) {
+        if self.age() >= LISA_LAST_CONTRIBUTION_AGE {
+            return (CashValue::from(0.0), CashValue::from(*amount));
+        }
+
+        let mut threshold = *UKAccount::LisaAnnualDepositThreshold.val();
+        let lisa_remaining = threshold - *self.current_tax_year_deposits;
+        if *isa_remaining_allowance < lisa_remaining {
+            threshold = *self.current_tax_year_deposits + *isa_remaining_allowance;
+        }
+
+        let (deposit, returned) =
+            UKAccount::isa_deposit_logic(amount, &threshold, &self.current_tax_year_deposits);
+        if *deposit > 0.0 {
+            let bonus = *deposit * LISA_BONUS_RATE;
+            self.deposit(&(*deposit + bonus));
+            self.current_tax_year_deposits =
+                CashValue::from(*self.current_tax_year_deposits + *deposit);
+        }
+        (deposit, returned)
+    }
+
+    pub fn new_with_cash(
+        strat: S,
+        start_cash: &f64,
+        date_of_birth: DateTime,
+        first_home_purchase: Option<DateTime>,
+        clock: Clock,
+    ) -> Self {
+        let mut s = Self::new(strat, date_of_birth, first_home_purchase, clock);
         s.strat.init(start_cash);
         s
     }
 
-    pub fn new(strat: S) -> Self {
-        Self { strat }
+    pub fn new(
+        strat: S,
+        date_of_birth: DateTime,
+        first_home_purchase: Option<DateTime>,
+        clock: Clock,
+    ) -> Self {
+        Self {
+            strat,
+            current_tax_year_deposits: CashValue::from(0.0),
+            date_of_birth,
+            first_home_purchase,
+            first_home_purchased: false,
+            clock,
+        }
     }
 }
 
-impl<S: InvestmentStrategy> CanTransfer for Gia<S> {
+impl<S: InvestmentStrategy> CanTransfer for Lisa<S> {
     fn deposit(&mut self, amount: &f64) -> TransferResult {
         if let StrategyEvent::DepositSuccess(_amount) = self.strat.deposit_cash(amount) {
             TransferResult::Success
@@ -350,8 +559,14 @@ impl<S: InvestmentStrategy> CanTransfer for Gia<S> {
         }
     }
 
+    //Penalty is a percentage of the amount withdrawn, so to get the requested amount out we have
+    //to take more from the account
     fn withdraw(&mut self, amount: &f64) -> TransferResult {
-        if let StrategyEvent::WithdrawSuccess(_amount) = self.strat.withdraw_cash(amount) {
+        let mut gross = *amount;
+        if !self.is_penalty_free() {
+            gross /= 1.0 - LISA_WITHDRAWAL_PENALTY_RATE;
+        }
+        if let StrategyEvent::WithdrawSuccess(_amount) = self.strat.withdraw_cash(&gross) {
             TransferResult::Success
         } else {
             TransferResult::Failure
@@ -359,8 +574,12 @@ impl<S: InvestmentStrategy> CanTransfer for Gia<S> {
     }
 
     fn liquidate(&mut self, amount: &f64) -> TransferResult {
+        let mut gross = *amount;
+        if !self.is_penalty_free() {
+            gross /= 1.0 - LISA_WITHDRAWAL_PENALTY_RATE;
+        }
         if let StrategyEvent::WithdrawSuccess(_amount) =
-            self.strat.withdraw_cash_with_liquidation(amount)
+            self.strat.withdraw_cash_with_liquidation(&gross)
         {
             TransferResult::Success
         } else {
@@ -369,24 +588,22 @@ impl<S: InvestmentStrategy> CanTransfer for Gia<S> {
     }
 }
 
-//Tax year state is controlled from within the simulation.
+//Junior ISA. Has a separate allowance from the parent's ISA, and becomes an adult ISA owned by the
+//child at the adult age after which no further deposits can be made by the parent.
 #[derive(Clone, Debug)]
-pub struct Sipp<S: InvestmentStrategy> {
+pub struct Jisa<S: InvestmentStrategy> {
     strat: S,
-    lifetime_contributions: CashValue,
-    current_tax_year_contributions: CashValue,
+    current_tax_year_deposits: CashValue,
+    date_of_birth: DateTime,
+    clock: Clock,
 }
 
-impl<S: InvestmentStrategy> Sipp<S> {
-
+//Tax year state is controlled from within the simulation.
+impl<S: InvestmentStrategy> Jisa<S> {
     pub fn zero(&mut self) {
         self.strat.zero();
     }
 
-    pub fn liquidation_value(&self) -> CashValue {
-        self.strat.get_liquidation_value()
-    }
-
     pub fn rebalance(&mut self) {
         if *self.strat.get_liquidation_value() > 0.0 {
             self.strat.update();
@@ -402,37 +619,62 @@ impl<S: InvestmentStrategy> Sipp<S> {
     }
 
     pub fn tax_year_end(&mut self) {
-        self.current_tax_year_contributions = CashValue::from(0.0);
+        self.current_tax_year_deposits = CashValue::default();
+    }
+
+    pub fn liquidation_value(&self) -> CashValue {
+        self.strat.get_liquidation_value()
     }
 
+    pub fn is_adult(&self) -> bool {
+        let now = self.clock.borrow().now();
+        years_between(&self.date_of_birth, &now) >= JISA_ADULT_AGE
+    }
+
+    //Investments are kept as they are and the child has the full adult ISA allowance
+    pub fn into_adult_isa(self) -> Isa<S> {
+        Isa::new(self.strat)
+    }
+
+    //Returns (deposit amount, return to client)
     pub fn deposit_wrapper(&mut self, amount: &f64) -> (CashValue, CashValue) {
-        let (deposit, returned) = UKAccount::sipp_deposit_logic(
+        if self.is_adult() {
+            return (CashValue::from(0.0), CashValue::from(*amount));
+        }
+
+        let (deposit, returned) = UKAccount::isa_deposit_logic(
             amount,
-            &UKAccount::SippAnnualContributionThreshold.val(),
-            &UKAccount::SippLifetimeContributionThreshold.val(),
-            &self.current_tax_year_contributions,
-            &self.lifetime_contributions,
+            &UKAccount::JisaAnnualDepositThreshold.val(),
+            &self.current_tax_year_deposits,
         );
         self.deposit(&deposit);
+        self.current_tax_year_deposits =
+            CashValue::from(*self.current_tax_year_deposits + *deposit);
         (deposit, returned)
     }
 
-    pub fn new_with_cash(strat: S, lifetime_contributions: &f64, start_cash: &f64) -> Self {
-        let mut s = Self::new(strat, CashValue::from(*lifetime_contributions));
+    pub fn new_with_cash(
+        strat: S,
+        start_cash: &f64,
+        date_of_birth: DateTime,
+        clock: Clock,
+    ) -> Self {
+        let mut s = Self::new(strat, date_of_birth, clock);
         s.strat.init(start_cash);
         s
     }
 
-    pub fn new(strat: S, lifetime_contributions: CashValue) -> Self {
+    pub fn new(strat: S, date_of_birth: DateTime, clock: Clock) -> Self {
         Self {
             strat,
-            lifetime_contributions,
-            current_tax_year_contributions: CashValue::default(),
+            current_tax_year_deposits: CashValue::from(0.0),
+            date_of_birth,
+            clock,
         }
     }
 }
 
-impl<S: InvestmentStrategy> CanTransfer for Sipp<S> {
+impl<S: InvestmentStrategy> CanTransfer for Jisa<S> {
     fn deposit(&mut self, amount: &f64) -> TransferResult {
         if let StrategyEvent::DepositSuccess(_amount) = self.strat.deposit_cash(amount) {
             TransferResult::Success
@@ -442,107 +684,460 @@ impl<S: InvestmentStrategy> CanTransfer for Sipp<S> {
     }
 
     fn withdraw(&mut self, _amount: &f64) -> TransferResult {
-        //Cannot withdraw directly from Sipp
+        //Money belongs to the child, parent cannot withdraw
         TransferResult::Failure
     }
 
     fn liquidate(&mut self, _amount: &f64) -> TransferResult {
-        //Cannot withdraw directly from Sipp
+        //Money belongs to the child, parent cannot withdraw
         TransferResult::Failure
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct BankAcc {
-    pub balance: CashValue,
+pub struct Gia<S: InvestmentStrategy> {
+    strat: S,
 }
 
-impl BankAcc {
+impl<S: InvestmentStrategy> Gia<S> {
     pub fn zero(&mut self) {
-        self.withdraw(&self.balance.clone());
+        self.strat.zero();
     }
-}
 
-impl CanTransfer for BankAcc {
-    fn withdraw(&mut self, amount: &f64) -> TransferResult {
-        if amount > &self.balance {
-            TransferResult::Failure
-        } else {
-            self.balance = CashValue::from(*self.balance - *amount);
-            TransferResult::Success
-        }
+    pub fn get_capital_gains(&self, date: &i64, tax_year_start: &i64) -> CashValue {
+        //To calculate capital gains we need the whole trade history for that symbol
+        let all_trades: Vec<Trade> = self.strat.trades_between(&0, date);
+        calculate_capital_gains(&all_trades, tax_year_start)
     }
 
-    fn liquidate(&mut self, amount: &f64) -> TransferResult {
-        self.withdraw(amount)
+    //Cash held in the account has no gain so only holdings are compared against the cost basis
+    pub fn get_unrealised_gain(&self, date: &i64) -> CashValue {
+        let all_trades: Vec<Trade> = self.strat.trades_between(&0, date);
+        let cost_basis = calculate_cost_basis(&all_trades);
+        CashValue::from(*self.holdings_value() - *cost_basis)
     }
 
-    fn deposit(&mut self, amount: &f64) -> TransferResult {
-        self.balance = CashValue::from(*self.balance + *amount);
-        TransferResult::Success
+    pub fn holdings_value(&self) -> CashValue {
+        CashValue::from(*self.strat.get_liquidation_value() - *self.strat.get_cash_balance())
     }
-}
 
-impl From<BankAcc> for CashValue {
-    fn from(val: BankAcc) -> Self {
-        val.balance
+    pub fn check(&mut self) {
+        self.strat.check();
     }
-}
 
-impl Default for BankAcc {
-    fn default() -> Self {
-        Self::new()
+    pub fn finish(&mut self) {
+        self.strat.finish();
     }
-}
 
-impl BankAcc {
-    pub fn new() -> Self {
-        Self {
-            balance: CashValue::default(),
+    //broker
+    pub fn get_dividends(&self, date: &i64, tax_year_start: &i64) -> CashValue {
+        let all_dividends: Vec<DividendPayment> =
+            self.strat.dividends_between(tax_year_start, date);
+        let mut sum = 0.0;
+        for divi in all_dividends {
+            sum += *divi.value;
         }
+        CashValue::from(sum)
     }
-}
 
-pub enum LoanEvent {
-    PaymentSuccess(CashValue),
-    PaymentFailure(CashValue),
-    Completed,
-}
+    pub fn liquidation_value(&self) -> CashValue {
+        self.strat.get_liquidation_value()
+    }
 
-struct AmortizingLoanLogic {
-    pub balance: CashValue,
-    rate: f64,
-    amortization_payment_min: CashValue,
-}
+    pub fn rebalance(&mut self) {
+        if *self.strat.get_liquidation_value() > 0.0 {
+            self.strat.update();
+        }
+    }
 
-//Logic implies monthly payments
-fn calculate_amortization_payment(balance: &f64, term_yrs: &u8) -> CashValue {
-    let term_months = (term_yrs * 12) as f64;
-    CashValue::from(*balance / term_months)
+    pub fn new_with_cash(strat: S, start_cash: &f64) -> Self {
+        let mut s = Self::new(strat);
+        s.strat.init(start_cash);
+        s
+    }
+
+    pub fn new(strat: S) -> Self {
+        Self { strat }
+    }
 }
 
-impl AmortizingLoanLogic {
-    //Non-mutating, does not decrement balance
-    fn payment_due(&self, amortization_payment: Option<CashValue>) -> CashValue {
-        let interest = *self.balance * self.rate;
-        //If amortization_payment is `Some` then the client is overpaying mortgage
-        if let Some(overpay) = amortization_payment {
-            //Check that overpay isn't less than minimum payment, if it is then we default to the
-            //main logic
-            if overpay > self.amortization_payment_min {
-                //If the loan balance is less than the overpay then we pay the balance + interest
-                if overpay < self.balance {
-                    return CashValue::from(*self.balance + interest);
-                } else {
-                    return CashValue::from(*overpay + interest);
-                }
-            }
-        }
-        //If the loan balance is less than the amortization than we pay the balance + interest
-        if self.balance < self.amortization_payment_min {
-            return CashValue::from(*self.balance + interest);
+impl<S: InvestmentStrategy> CanTransfer for Gia<S> {
+    fn deposit(&mut self, amount: &f64) -> TransferResult {
+        if let StrategyEvent::DepositSuccess(_amount) = self.strat.deposit_cash(amount) {
+            TransferResult::Success
+        } else {
+            TransferResult::Failure
         }
-        CashValue::from(*self.amortization_payment_min + interest)
+    }
+
+    fn withdraw(&mut self, amount: &f64) -> TransferResult {
+        if let StrategyEvent::WithdrawSuccess(_amount) = self.strat.withdraw_cash(amount) {
+            TransferResult::Success
+        } else {
+            TransferResult::Failure
+        }
+    }
+
+    fn liquidate(&mut self, amount: &f64) -> TransferResult {
+        if let StrategyEvent::WithdrawSuccess(_amount) =
+            self.strat.withdraw_cash_with_liquidation(amount)
+        {
+            TransferResult::Success
+        } else {
+            TransferResult::Failure
+        }
+    }
+}
+
+const SIPP_CARRY_FORWARD_YEARS: usize = 3;
+
+//Net pay contributions are deducted from pay before income tax. Relief at source contributions
+//are paid from net pay, the provider adds basic rate relief and higher rate relief is claimed by
+//extending the basic rate band.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum ReliefMethod {
+    NetPay,
+    ReliefAtSource,
+}
+
+//Tax year state is controlled from within the simulation.
+#[derive(Clone, Debug)]
+pub struct Sipp<S: InvestmentStrategy> {
+    strat: S,
+    lifetime_contributions: CashValue,
+    current_tax_year_contributions: CashValue,
+    //Defined benefit accrual counts against the annual allowance but not the MPAA
+    current_tax_year_defined_benefit_input: CashValue,
+    //Unused annual allowance from previous tax years, oldest first
+    carry_forward: VecDeque<CashValue>,
+    //Once pension is flexibly accessed, contributions are limited to the MPAA
+    drawdown_start: Option<DateTime>,
+    relief_method: ReliefMethod,
+    clock: Clock,
+}
+
+impl<S: InvestmentStrategy> Sipp<S> {
+
+    pub fn zero(&mut self) {
+        self.strat.zero();
+    }
+
+    pub fn liquidation_value(&self) -> CashValue {
+        self.strat.get_liquidation_value()
+    }
+
+    pub fn rebalance(&mut self) {
+        if *self.strat.get_liquidation_value() > 0.0 {
+            self.strat.update();
+        }
+    }
+
+    pub fn check(&mut self) {
+        self.strat.check();
+    }
+
+    pub fn finish(&mut self) {
+        self.strat.finish();
+    }
+
+    pub fn tax_year_end(&mut self) {
+        self.current_tax_year_contributions = CashValue::from(0.0);
+        self.current_tax_year_defined_benefit_input = CashValue::from(0.0);
+    }
+
+    pub fn relief_method(&self) -> ReliefMethod {
+        self.relief_method
+    }
+
+    //Money can't be withdrawn from the SIPP but can be used to buy an annuity. Returns the amount
+    //used, which is capped at the value of the SIPP.
+    pub fn purchase_annuity(&mut self, amount: &f64) -> CashValue {
+        let amount = amount.min(*self.liquidation_value());
+        if amount <= 0.0 {
+            return CashValue::default();
+        }
+        if let StrategyEvent::WithdrawSuccess(_amount) =
+            self.strat.withdraw_cash_with_liquidation(&amount)
+        {
+            return CashValue::from(amount);
+        }
+        CashValue::default()
+    }
+
+    pub fn is_flexibly_accessed(&self) -> bool {
+        if let Some(start) = &self.drawdown_start {
+            let now = self.clock.borrow().now();
+            return now >= *start;
+        }
+        false
+    }
+
+    pub fn carry_forward(&self) -> CashValue {
+        CashValue::from(self.carry_forward.iter().map(|v| **v).sum::<f64>())
+    }
+
+    //We don't know the tapered allowance until the end of the year so contributions are limited
+    //using the untapered allowance, any excess after taper is charged at the end of the year
+    fn available_allowance(&self) -> CashValue {
+        if self.is_flexibly_accessed() {
+            return UKAccount::SippMoneyPurchaseAnnualAllowance.val();
+        }
+        CashValue::from(*UKAccount::SippAnnualAllowance.val() + *self.carry_forward())
+    }
+
+    //Defined benefit input only uses up the headroom when the full allowance applies
+    fn used_allowance(&self) -> CashValue {
+        if self.is_flexibly_accessed() {
+            return self.current_tax_year_contributions.clone();
+        }
+        CashValue::from(
+            *self.current_tax_year_contributions + *self.current_tax_year_defined_benefit_input,
+        )
+    }
+
+    //Called once at the end of each tax year, before the contributions are reset, with the allowance
+    //after taper. The current year allowance is used first, then carry-forward from the oldest year.
+    //Any unused allowance is carried forward. Returns the contributions in excess of the
+    //available allowance, which are subject to the annual allowance charge.
+    pub fn annual_allowance_excess(&mut self, annual_allowance: &f64) -> CashValue {
+        let contributions = *self.current_tax_year_contributions;
+        let defined_benefit = *self.current_tax_year_defined_benefit_input;
+
+        //Carry-forward cannot be used against the MPAA. Money purchase contributions are tested
+        //against the MPAA and defined benefit input against the rest of the allowance, the
+        //excess is the higher of that and the excess of the total over the full allowance.
+        if self.is_flexibly_accessed() {
+            let mpaa = *UKAccount::SippMoneyPurchaseAnnualAllowance.val();
+            let alternative = (contributions - mpaa).max(0.0)
+                + (defined_benefit - (annual_allowance - mpaa).max(0.0)).max(0.0);
+            let total = (contributions + defined_benefit - annual_allowance).max(0.0);
+            return CashValue::from(alternative.max(total));
+        }
+
+        UKAccount::sipp_carry_forward_logic(
+            &(contributions + defined_benefit),
+            annual_allowance,
+            &mut self.carry_forward,
+        )
+    }
+
+    //Defined benefit accrual shares the annual allowance with the SIPP but isn't a contribution
+    pub fn add_defined_benefit_input(&mut self, amount: &f64) {
+        self.current_tax_year_defined_benefit_input =
+            CashValue::from(*self.current_tax_year_defined_benefit_input + *amount);
+    }
+
+    fn add_contribution(&mut self, amount: &f64) {
+        self.current_tax_year_contributions =
+            CashValue::from(*self.current_tax_year_contributions + *amount);
+        self.lifetime_contributions = CashValue::from(*self.lifetime_contributions + *amount);
+    }
+
+    pub fn deposit_wrapper(&mut self, amount: &f64) -> (CashValue, CashValue) {
+        let (deposit, returned) = UKAccount::sipp_deposit_logic(
+            amount,
+            &self.available_allowance(),
+            &self.used_allowance(),
+        );
+        self.deposit(&deposit);
+        self.add_contribution(&deposit);
+        (deposit, returned)
+    }
+
+    //Personal contribution from another account, liquidating the source if required. The provider
+    //adds basic rate relief on top of the net amount so the gross contribution is what counts
+    //against the thresholds. Returns (net amount taken from source, gross amount contributed).
+    pub fn transfer_with_relief(
+        &mut self,
+        source: &mut impl CanTransfer,
+        net_amount: &f64,
+        relief_rate: &f64,
+    ) -> (CashValue, CashValue) {
+        let gross_amount = *net_amount / (1.0 - *relief_rate);
+        let (gross_deposit, _returned) = UKAccount::sipp_deposit_logic(
+            &gross_amount,
+            &self.available_allowance(),
+            &self.used_allowance(),
+        );
+        if *gross_deposit <= 0.0 {
+            return (CashValue::default(), CashValue::default());
+        }
+
+        let net_deposit = *gross_deposit * (1.0 - *relief_rate);
+        if let TransferResult::Success = Transfer::force(source, self, &net_deposit) {
+            let relief = *gross_deposit - net_deposit;
+            self.deposit(&relief);
+            self.add_contribution(&gross_deposit);
+            return (CashValue::from(net_deposit), gross_deposit);
+        }
+        (CashValue::default(), CashValue::default())
+    }
+
+    pub fn new_with_cash(
+        strat: S,
+        lifetime_contributions: &f64,
+        start_cash: &f64,
+        carry_forward: Vec<f64>,
+        drawdown_start: Option<DateTime>,
+        relief_method: ReliefMethod,
+        clock: Clock,
+    ) -> Self {
+        let mut s = Self::new(
+            strat,
+            CashValue::from(*lifetime_contributions),
+            carry_forward,
+            drawdown_start,
+            relief_method,
+            clock,
+        );
+        s.strat.init(start_cash);
+        s
+    }
+
+    pub fn new(
+        strat: S,
+        lifetime_contributions: CashValue,
+        carry_forward: Vec<f64>,
+        drawdown_start: Option<DateTime>,
+        relief_method: ReliefMethod,
+        clock: Clock,
+    ) -> Self {
+        //Only the most recent years can be carried forward
+        let mut unused: VecDeque<CashValue> =
+            carry_forward.iter().map(|v| CashValue::from(*v)).collect();
+        while unused.len() > SIPP_CARRY_FORWARD_YEARS {
+            unused.pop_front();
+        }
+
+        Self {
+            strat,
+            lifetime_contributions,
+            current_tax_year_contributions: CashValue::default(),
+            current_tax_year_defined_benefit_input: CashValue::default(),
+            carry_forward: unused,
+            drawdown_start,
+            relief_method,
+            clock,
+        }
+    }
+}
+
+impl<S: InvestmentStrategy> CanTransfer for Sipp<S> {
+    fn deposit(&mut self, amount: &f64) -> TransferResult {
+        if let StrategyEvent::DepositSuccess(_amount) = self.strat.deposit_cash(amount) {
+            TransferResult::Success
+        } else {
+            TransferResult::Failure
+        }
+    }
+
+    fn withdraw(&mut self, _amount: &f64) -> TransferResult {
+        //Cannot withdraw directly from Sipp
+        TransferResult::Failure
+    }
+
+    fn liquidate(&mut self, _amount: &f64) -> TransferResult {
+        //Cannot withdraw directly from Sipp
+        TransferResult::Failure
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BankAcc {
+    pub balance: CashValue,
+}
+
+impl BankAcc {
+    pub fn zero(&mut self) {
+        self.withdraw(&self.balance.clone());
+    }
+}
+
+impl CanTransfer for BankAcc {
+    fn withdraw(&mut self, amount: &f64) -> TransferResult {
+        if amount > &self.balance {
+            TransferResult::Failure
+        } else {
+            self.balance = CashValue::from(*self.balance - *amount);
+            TransferResult::Success
+        }
+    }
+
+    fn liquidate(&mut self, amount: &f64) -> TransferResult {
+        self.withdraw(amount)
+    }
+
+    fn deposit(&mut self, amount: &f64) -> TransferResult {
+        self.balance = CashValue::from(*self.balance + *amount);
+        TransferResult::Success
+    }
+}
+
+impl From<BankAcc> for CashValue {
+    fn from(val: BankAcc) -> Self {
+        val.balance
+    }
+}
+
+impl Default for BankAcc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BankAcc {
+    pub fn new() -> Self {
+        Self {
+            balance: CashValue::default(),
+        }
+    }
+}
+
+pub enum LoanEvent {
+    PaymentSuccess(CashValue),
+    PaymentFailure(CashValue),
+    Completed,
+}
+
+struct AmortizingLoanLogic {
+    pub balance: CashValue,
+    rate: f64,
+    amortization_payment_min: CashValue,
+}
+
+//Logic implies monthly payments
+fn calculate_amortization_payment(balance: &f64, term_yrs: &u8) -> CashValue {
+    let term_months = *term_yrs as f64 * 12.0;
+    CashValue::from(*balance / term_months)
+}
+
+impl AmortizingLoanLogic {
+    //Rate is annual and payments are monthly
+    fn interest(&self) -> f64 {
+        *self.balance * (self.rate / 12.0)
+    }
+
+    //Non-mutating, does not decrement balance
+    fn payment_due(&self, amortization_payment: Option<CashValue>) -> CashValue {
+        let interest = self.interest();
+        //If amortization_payment is `Some` then the client is overpaying mortgage
+        if let Some(overpay) = amortization_payment {
+            //Check that overpay isn't less than minimum payment, if it is then we default to the
+            //main logic
+            if overpay > self.amortization_payment_min {
+                //If the loan balance is less than the overpay then we pay the balance + interest
+                if overpay < self.balance {
+                    return CashValue::from(*overpay + interest);
+                } else {
+                    return CashValue::from(*self.balance + interest);
+                }
+            }
+        }
+        //If the loan balance is less than the amortization than we pay the balance + interest
+        if self.balance < self.amortization_payment_min {
+            return CashValue::from(*self.balance + interest);
+        }
+        CashValue::from(*self.amortization_payment_min + interest)
     }
 
     pub fn payment(
@@ -554,112 +1149,464 @@ impl AmortizingLoanLogic {
             return LoanEvent::Completed;
         }
 
+        let interest = self.interest();
         let payment_due = self.payment_due(amortization_payment);
         if let TransferResult::Success = src.withdraw(&payment_due) {
+            self.balance = CashValue::from(*self.balance - (*payment_due - interest));
             return LoanEvent::PaymentSuccess(payment_due);
         }
         LoanEvent::PaymentFailure(payment_due)
     }
 
-    pub fn new(balance: &f64, rate: f64, term_yrs: u8) -> Self {
-        let amortization_payment_min = calculate_amortization_payment(balance, &term_yrs);
-        Self {
-            balance: CashValue::from(*balance),
-            rate,
-            amortization_payment_min,
+    //Payments still clear the balance over the original term
+    fn refix(&mut self, rate: f64) {
+        self.rate = rate;
+    }
+
+    pub fn new(balance: &f64, rate: f64, term_yrs: u8) -> Self {
+        let amortization_payment_min = calculate_amortization_payment(balance, &term_yrs);
+        Self {
+            balance: CashValue::from(*balance),
+            rate,
+            amortization_payment_min,
+        }
+    }
+}
+
+pub struct Mortgage<T: SimDataSource> {
+    loan: AmortizingLoanLogic,
+    payment_schedule: Schedule,
+    //We assume that the fix period rolls through term
+    fix_period: u8,
+    current_fix_end_date: DateTime,
+    clock: Clock,
+    source: T,
+}
+
+impl<T: SimDataSource> Mortgage<T> {
+    pub fn start(
+        balance: &f64,
+        rate: f64,
+        start_date: &i64,
+        term_yrs: u8,
+        initial_fix_in_yrs: u8,
+        clock: Clock,
+        source: T,
+    ) -> Self {
+        let start_offset: OffsetDateTime = DateTime::from(*start_date).into();
+        //This isn't totally accurate but it should be very close, the inaccuracy on the final fix
+        //should come out if we catch LoanEvent::Completed
+        let fix_duration = Duration::weeks(initial_fix_in_yrs as i64 * 52);
+        let current_fix_end_date: DateTime = start_offset
+            .checked_add(fix_duration)
+            .unwrap()
+            .unix_timestamp()
+            .into();
+
+        let loan = AmortizingLoanLogic::new(balance, rate, term_yrs);
+        let payment_schedule = Schedule::EveryMonth(25);
+        Self {
+            loan,
+            payment_schedule,
+            fix_period: initial_fix_in_yrs,
+            current_fix_end_date,
+            clock,
+            source,
+        }
+    }
+
+    pub fn with_step(mut self, step: &Step) -> Self {
+        self.payment_schedule = self.payment_schedule.for_step(step);
+        self
+    }
+
+    pub fn balance(&self) -> CashValue {
+        self.loan.balance.clone()
+    }
+
+    //This automatically decrements the minimum payment, if the user supplies a larger amortization value
+    //that is used instead. Interest is also paid automatically within the loan logic.
+    pub fn pay(
+        &mut self,
+        amortization_payment: Option<CashValue>,
+        src: &mut impl CanTransfer,
+    ) -> Option<LoanEvent> {
+        let curr_date = self.clock.borrow().now();
+        if self.payment_schedule.check(&curr_date) {
+            if curr_date > self.current_fix_end_date {
+                //Need to reissue using new data
+                if let Some(rate) = self.source.get_current_interest_rate() {
+                    let mortgage_margin = rate + 0.04;
+                    self.loan.refix(mortgage_margin);
+
+                    let curr_offset_date: OffsetDateTime = curr_date.into();
+                    let fix_duration = Duration::weeks(self.fix_period as i64 * 52);
+                    let current_fix_end_date: DateTime = curr_offset_date
+                        .checked_add(fix_duration)
+                        .unwrap()
+                        .unix_timestamp()
+                        .into();
+                    self.current_fix_end_date = current_fix_end_date;
+                } else {
+                    panic!("Missing data for rates, cannot move forward without data so panicking");
+                }
+            }
+            Some(self.loan.payment(amortization_payment, src))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum StudentLoanPlan {
+    Plan1,
+    Plan2,
+    Plan4,
+    Plan5,
+    Postgraduate,
+}
+
+impl StudentLoanPlan {
+    pub fn repayment_rate(&self) -> f64 {
+        match self {
+            StudentLoanPlan::Postgraduate => 0.06,
+            _ => 0.09,
+        }
+    }
+
+    //Annual thresholds for 2025/26, uprated by inflation at the start of each tax year
+    pub fn threshold(&self) -> f64 {
+        match self {
+            StudentLoanPlan::Plan1 => 26_065.0,
+            StudentLoanPlan::Plan2 => 28_470.0,
+            StudentLoanPlan::Plan4 => 32_745.0,
+            StudentLoanPlan::Plan5 => 25_000.0,
+            StudentLoanPlan::Postgraduate => 21_000.0,
+        }
+    }
+
+    //Income at which Plan 2 interest reaches RPI+3%
+    pub fn upper_threshold(&self) -> f64 {
+        match self {
+            StudentLoanPlan::Plan2 => 51_245.0,
+            _ => self.threshold(),
+        }
+    }
+
+    //Years from the first repayment date to write-off
+    pub fn write_off_years(&self) -> i64 {
+        match self {
+            StudentLoanPlan::Plan1 => 25,
+            StudentLoanPlan::Plan5 => 40,
+            _ => 30,
+        }
+    }
+
+    //Annual interest rate. RPI is approximated by inflation over the trailing year and the base rate
+    //by the interest rate in the source.
+    pub fn interest_rate_logic(
+        &self,
+        rpi: &f64,
+        base_rate: &f64,
+        income: &f64,
+        threshold: &f64,
+        upper_threshold: &f64,
+    ) -> f64 {
+        match self {
+            StudentLoanPlan::Plan1 | StudentLoanPlan::Plan4 => rpi.min(base_rate + 0.01),
+            StudentLoanPlan::Plan2 => {
+                let pct = ((income - threshold) / (upper_threshold - threshold)).clamp(0.0, 1.0);
+                rpi + (0.03 * pct)
+            }
+            StudentLoanPlan::Plan5 => *rpi,
+            StudentLoanPlan::Postgraduate => rpi + 0.03,
+        }
+    }
+
+    //Repayment on income over a period with the threshold for that period
+    pub fn repayment_logic(&self, income: &f64, threshold: &f64) -> f64 {
+        if income <= threshold {
+            return 0.0;
+        }
+        (income - threshold) * self.repayment_rate()
+    }
+}
+
+pub struct StudentLoan<T: SimDataSource> {
+    plan: StudentLoanPlan,
+    balance: CashValue,
+    //Annual thresholds
+    threshold: f64,
+    upper_threshold: f64,
+    first_repayment: DateTime,
+    write_off_date: DateTime,
+    interest_schedule: Schedule,
+    clock: Clock,
+    source: T,
+}
+
+impl<T: SimDataSource> StudentLoan<T> {
+    pub fn plan(&self) -> StudentLoanPlan {
+        self.plan
+    }
+
+    pub fn balance(&self) -> CashValue {
+        self.balance.clone()
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self.balance > 0.0
+    }
+
+    //Interest accrues before the first repayment date but nothing is repaid
+    pub fn is_repaying(&self) -> bool {
+        self.is_active() && self.clock.borrow().now() >= self.first_repayment
+    }
+
+    //PAYE deductions are taken against the monthly threshold, self-assessment against the annual
+    //threshold. Returns the amount due which may be greater than the balance.
+    pub fn repayment_due(&self, income: &f64, is_paye: bool) -> CashValue {
+        if !self.is_repaying() {
+            return CashValue::from(0.0);
+        }
+        let threshold = if is_paye {
+            self.threshold / 12.0
+        } else {
+            self.threshold
+        };
+        CashValue::from(self.plan.repayment_logic(income, &threshold))
+    }
+
+    //Returns the amount repaid, which is capped at the balance
+    pub fn repay(&mut self, amount: &f64) -> CashValue {
+        let repaid = amount.min(*self.balance).max(0.0);
+        self.balance = CashValue::from(*self.balance - repaid);
+        CashValue::from(repaid)
+    }
+
+    //Called daily, accrues a month of interest at the start of each month and writes off the
+    //balance once the write-off date is reached. Income should be the annual income used for
+    //the Plan 2 interest taper.
+    pub fn check(&mut self, income: &f64) {
+        if !self.is_active() {
+            return;
+        }
+
+        let curr_date = self.clock.borrow().now();
+        if curr_date >= self.write_off_date {
+            self.balance = CashValue::from(0.0);
+            return;
+        }
+
+        if self.interest_schedule.check(&curr_date) {
+            let rpi = self.source.get_trailing_year_inflation();
+            let base_rate = self.source.get_current_interest_rate().unwrap_or(0.0);
+            let rate = self.plan.interest_rate_logic(
+                &rpi,
+                &base_rate,
+                income,
+                &self.threshold,
+                &self.upper_threshold,
+            );
+            self.balance = CashValue::from(*self.balance * (1.0 + (rate / 12.0)));
+        }
+    }
+
+    pub fn apply_inflation(&mut self, inflation: &f64) {
+        self.threshold *= 1.0 + inflation;
+        self.upper_threshold *= 1.0 + inflation;
+    }
+
+    //First repayment date is the April after leaving the course, write-off is counted from this date
+    pub fn new(
+        plan: StudentLoanPlan,
+        balance: &f64,
+        first_repayment: &i64,
+        clock: Clock,
+        source: T,
+    ) -> Self {
+        let write_off_date = years_after(first_repayment, plan.write_off_years() as i32).into();
+
+        Self {
+            plan,
+            balance: CashValue::from(*balance),
+            threshold: plan.threshold(),
+            upper_threshold: plan.upper_threshold(),
+            first_repayment: DateTime::from(*first_repayment),
+            write_off_date,
+            interest_schedule: Schedule::StartOfMonth,
+            clock,
+            source,
+        }
+    }
+
+    pub fn with_step(mut self, step: &Step) -> Self {
+        self.interest_schedule = self.interest_schedule.for_step(step);
+        self
+    }
+}
+
+//Undergraduate and postgraduate loans are deducted separately. Where there is more than one
+//undergraduate plan, the deduction is taken once above the lowest threshold.
+pub fn student_loan_due<T: SimDataSource>(
+    loans: &[StudentLoan<T>],
+    income: &f64,
+    is_paye: bool,
+) -> CashValue {
+    let mut undergraduate = 0.0_f64;
+    let mut postgraduate = 0.0_f64;
+    for loan in loans {
+        let due = *loan.repayment_due(income, is_paye);
+        match loan.plan() {
+            StudentLoanPlan::Postgraduate => postgraduate = postgraduate.max(due),
+            _ => undergraduate = undergraduate.max(due),
+        }
+    }
+    CashValue::from(undergraduate + postgraduate)
+}
+
+//Repays loans with the lowest threshold first, returns the total repaid
+pub fn repay_student_loans<T: SimDataSource>(
+    loans: &mut [StudentLoan<T>],
+    amount: &f64,
+) -> CashValue {
+    let mut order: Vec<usize> = (0..loans.len()).collect();
+    order.sort_by(|a, b| loans[*a].threshold().total_cmp(&loans[*b].threshold()));
+
+    let mut remaining = *amount;
+    for pos in order {
+        if remaining <= 0.0 {
+            break;
+        }
+        if !loans[pos].is_repaying() {
+            continue;
+        }
+        remaining -= *loans[pos].repay(&remaining);
+    }
+    CashValue::from(amount - remaining)
+}
+
+//Valued daily from the house price returns in the source. The gain on sale is exempt if this is
+//the main residence, otherwise it is charged at the residential rate.
+pub struct Property<T: SimDataSource> {
+    value: CashValue,
+    //Value at the start of the simulation, rent linked to the property moves with the value
+    initial_value: CashValue,
+    //Purchase price plus costs of buying and improving the property
+    base_cost: CashValue,
+    main_residence: bool,
+    sale_date: Option<DateTime>,
+    sold: bool,
+    clock: Clock,
+    source: T,
+}
+
+impl<T: SimDataSource> Property<T> {
+    pub fn value(&self) -> CashValue {
+        if self.sold {
+            return CashValue::from(0.0);
+        }
+        self.value.clone()
+    }
+
+    pub fn is_main_residence(&self) -> bool {
+        self.main_residence
+    }
+
+    pub fn is_sold(&self) -> bool {
+        self.sold
+    }
+
+    //Value relative to the start of the simulation
+    pub fn growth(&self) -> f64 {
+        if *self.initial_value <= 0.0 {
+            return 1.0;
+        }
+        *self.value / *self.initial_value
+    }
+
+    pub fn sale_due(&self) -> bool {
+        if self.sold {
+            return false;
+        }
+        match &self.sale_date {
+            Some(date) => self.clock.borrow().now() >= *date,
+            None => false,
+        }
+    }
+
+    //Called daily
+    pub fn check(&mut self) {
+        if self.sold {
+            return;
+        }
+        if let Some(ret) = self.source.get_current_house_price_return() {
+            self.value = CashValue::from(*self.value * (1.0 + ret));
+        }
+    }
+
+    //Returns the proceeds and the taxable gain, which is zero for a main residence. Losses aren't
+    //carried forward.
+    pub fn sell(&mut self) -> (CashValue, CashValue) {
+        if self.sold {
+            return (CashValue::from(0.0), CashValue::from(0.0));
         }
+        self.sold = true;
+        let gain = if self.main_residence {
+            0.0
+        } else {
+            (*self.value - *self.base_cost).max(0.0)
+        };
+        (self.value.clone(), CashValue::from(gain))
     }
-}
-
-pub struct Mortgage<T: SimDataSource> {
-    loan: AmortizingLoanLogic,
-    payment_schedule: Schedule,
-    //We assume that the fix period rolls through term
-    fix_period: u8,
-    current_fix_end_date: DateTime,
-    clock: Clock,
-    source: T,
-}
 
-impl<T: SimDataSource> Mortgage<T> {
-    pub fn start(
-        balance: &f64,
-        rate: f64,
-        start_date: &i64,
-        initial_fix_in_yrs: u8,
+    pub fn new(
+        value: &f64,
+        base_cost: &f64,
+        main_residence: bool,
+        sale_date: Option<DateTime>,
         clock: Clock,
         source: T,
     ) -> Self {
-        let start_offset: OffsetDateTime = DateTime::from(*start_date).into();
-        //This isn't totally accurate but it should be very close, the inaccuracy on the final fix
-        //should come out if we catch LoanEvent::Completed
-        let fix_duration = Duration::weeks(initial_fix_in_yrs as i64 * 52);
-        let current_fix_end_date: DateTime = start_offset
-            .checked_add(fix_duration)
-            .unwrap()
-            .unix_timestamp()
-            .into();
-
-        let loan = AmortizingLoanLogic::new(balance, rate, initial_fix_in_yrs);
-        let payment_schedule = Schedule::EveryMonth(25);
         Self {
-            loan,
-            payment_schedule,
-            fix_period: initial_fix_in_yrs,
-            current_fix_end_date,
+            value: CashValue::from(*value),
+            initial_value: CashValue::from(*value),
+            base_cost: CashValue::from(*base_cost),
+            main_residence,
+            sale_date,
+            sold: false,
             clock,
             source,
         }
     }
-
-    //This automatically decrements the minimum payment, if the user supplies a larger amortization value
-    //that is used instead. Interest is also paid automatically within the loan logic.
-    pub fn pay(
-        &mut self,
-        amortization_payment: Option<CashValue>,
-        src: &mut impl CanTransfer,
-    ) -> Option<LoanEvent> {
-        let curr_date = self.clock.borrow().now();
-        if self.payment_schedule.check(&curr_date) {
-            if curr_date > self.current_fix_end_date {
-                //Need to reissue using new data
-                if let Some(rate) = self.source.get_current_interest_rate() {
-                    let mortgage_margin = rate + 0.04;
-                    let remaining = &self.loan.balance;
-                    self.loan =
-                        AmortizingLoanLogic::new(remaining, mortgage_margin, self.fix_period);
-
-                    let curr_offset_date: OffsetDateTime = curr_date.into();
-                    let fix_duration = Duration::weeks(self.fix_period as i64 * 52);
-                    let current_fix_end_date: DateTime = curr_offset_date
-                        .checked_add(fix_duration)
-                        .unwrap()
-                        .unix_timestamp()
-                        .into();
-                    self.current_fix_end_date = current_fix_end_date;
-                } else {
-                    panic!("Missing data for rates, cannot move forward without data so panicking");
-                }
-            }
-            Some(self.loan.payment(amortization_payment, src))
-        } else {
-            None
-        }
-    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use alator::broker::{Trade, TradeType};
-    use alator::clock::ClockBuilder;
-    use alator::types::Frequency;
+    use alator::clock::{Clock, ClockBuilder};
+    use alator::exchange::DefaultExchangeBuilder;
+    use alator::sim::SimulatedBrokerBuilder;
+    use alator::types::{Frequency, PortfolioAllocation};
     use std::rc::Rc;
 
     use crate::input::{daily_data_generator_static, HashMapSourceSimBuilder};
+    use crate::schedule::Schedule;
+    use crate::strat::StaticInvestmentStrategy;
 
-    use super::{calculate_capital_gains, UKAccount};
+    use super::{calculate_capital_gains, calculate_cost_basis, CanTransfer, UKAccount};
+    use super::{Jisa, Lisa, ReliefMethod, Sipp};
+    use alator::types::CashValue;
+    use std::collections::VecDeque;
     use super::{BankAcc, LoanEvent, Mortgage};
+    use super::{repay_student_loans, student_loan_due, StudentLoan, StudentLoanPlan};
+    use super::Property;
 
     #[test]
     fn test_that_isa_threshold() {
@@ -680,23 +1627,55 @@ mod tests {
     #[test]
     fn test_that_sipp_threshold() {
         //TODO: this should be tested with mocks
-        let res = UKAccount::sipp_deposit_logic(&100.0, &50.0, &50.0, &0.0, &0.0);
+        let res = UKAccount::sipp_deposit_logic(&100.0, &50.0, &0.0);
         assert!(*res.0 == 50.0 && *res.1 == 50.0);
 
-        let res1 = UKAccount::sipp_deposit_logic(&10.0, &50.0, &50.0, &0.0, &0.0);
+        let res1 = UKAccount::sipp_deposit_logic(&10.0, &50.0, &0.0);
         assert!(*res1.0 == 10.0 && *res1.1 == 0.0);
 
-        let res2 = UKAccount::sipp_deposit_logic(&10.0, &50.0, &50.0, &40.0, &40.0);
+        let res2 = UKAccount::sipp_deposit_logic(&10.0, &50.0, &40.0);
         assert!(*res2.0 == 10.0 && *res2.1 == 0.0);
 
-        let res3 = UKAccount::sipp_deposit_logic(&10.0, &50.0, &50.0, &45.0, &45.0);
+        let res3 = UKAccount::sipp_deposit_logic(&10.0, &50.0, &45.0);
         assert!(*res3.0 == 5.0 && *res3.1 == 5.0);
+    }
+
+    #[test]
+    fn test_that_sipp_allowance_tapers() {
+        //Threshold income under limit means no taper even with high adjusted income
+        let res = UKAccount::tapered_annual_allowance(&150_000.0, &300_000.0);
+        assert!(*res == 60_000.0);
+
+        let res1 = UKAccount::tapered_annual_allowance(&250_000.0, &280_000.0);
+        assert!(*res1 == 50_000.0);
 
-        let res4 = UKAccount::sipp_deposit_logic(&10.0, &50.0, &100.0, &45.0, &45.0);
-        assert!(*res4.0 == 5.0 && *res4.1 == 5.0);
+        //Taper cannot go below the minimum
+        let res2 = UKAccount::tapered_annual_allowance(&400_000.0, &400_000.0);
+        assert!(*res2 == 10_000.0);
+    }
 
-        let res5 = UKAccount::sipp_deposit_logic(&10.0, &100.0, &50.0, &45.0, &45.0);
-        assert!(*res5.0 == 5.0 && *res5.1 == 5.0);
+    #[test]
+    fn test_that_sipp_carry_forward_is_used_oldest_first() {
+        let mut carry_forward = VecDeque::from(vec![
+            CashValue::from(10.0),
+            CashValue::from(20.0),
+            CashValue::from(30.0),
+        ]);
+
+        //Under allowance, unused is added and the oldest year drops off
+        let res = UKAccount::sipp_carry_forward_logic(&40.0, &50.0, &mut carry_forward);
+        assert!(*res == 0.0);
+        assert!(carry_forward.iter().map(|v| **v).collect::<Vec<f64>>() == vec![20.0, 30.0, 10.0]);
+
+        //Over allowance uses the oldest year first
+        let res1 = UKAccount::sipp_carry_forward_logic(&75.0, &50.0, &mut carry_forward);
+        assert!(*res1 == 0.0);
+        assert!(carry_forward.iter().map(|v| **v).collect::<Vec<f64>>() == vec![25.0, 10.0, 0.0]);
+
+        //Over allowance and carry-forward returns the excess
+        let res2 = UKAccount::sipp_carry_forward_logic(&100.0, &50.0, &mut carry_forward);
+        assert!(*res2 == 15.0);
+        assert!(carry_forward.iter().map(|v| **v).collect::<Vec<f64>>() == vec![0.0, 0.0, 0.0]);
     }
 
     #[test]
@@ -738,6 +1717,19 @@ mod tests {
         assert!(*capital_gains > -1991.0 && *capital_gains < -1990.0);
     }
 
+    #[test]
+    fn test_cost_basis_calculation_logic() {
+        let t1 = Trade::new("ABC", 1000.0, 100.0, 1, TradeType::Buy);
+        let t2 = Trade::new("ABC", 2000.0, 100.0, 5, TradeType::Buy);
+        let t3 = Trade::new("ABC", 3000.0, 100.0, 10, TradeType::Sell);
+        let t4 = Trade::new("BCD", 500.0, 10.0, 10, TradeType::Buy);
+
+        let trades = vec![t1, t2, t3, t4];
+        let cost_basis = calculate_cost_basis(&trades);
+        //Half of the ABC pool is sold at average cost of 15, leaving 1500 + 500 of BCD
+        assert!(*cost_basis == 2000.0);
+    }
+
     #[test]
     fn test_that_mortgage_payment_reduces_balance() {
         let clock = ClockBuilder::with_length_in_days(1, 60)
@@ -751,7 +1743,7 @@ mod tests {
             .build();
 
         let rate = 0.05;
-        let mut mortgage = Mortgage::start(&100_000.0, rate, &1, 1, Rc::clone(&clock), source);
+        let mut mortgage = Mortgage::start(&100_000.0, rate, &1, 25, 1, Rc::clone(&clock), source);
         let mut test_acc = BankAcc {
             balance: 10_000.0.into(),
         };
@@ -761,8 +1753,11 @@ mod tests {
             mortgage.pay(None, &mut test_acc);
         }
 
-        println!("{:?}", test_acc.balance);
-        assert!(*test_acc.balance != 20_000.0);
+        //Two monthly payments of a 25 year term with monthly interest at 5%
+        let principal = 100_000.0 / 300.0;
+        let interest = (100_000.0 + (100_000.0 - principal)) * (0.05 / 12.0);
+        assert!((*mortgage.balance() - (100_000.0 - 2.0 * principal)).abs() < 0.01);
+        assert!((*test_acc.balance - (10_000.0 - 2.0 * principal - interest)).abs() < 0.01);
     }
 
     #[test]
@@ -778,7 +1773,8 @@ mod tests {
             .build();
 
         let rate = 0.05;
-        let mut mortgage = Mortgage::start(&1_000_000.0, rate, &1, 1, Rc::clone(&clock), source);
+        let mut mortgage =
+            Mortgage::start(&1_000_000.0, rate, &1, 25, 1, Rc::clone(&clock), source);
         let mut test_acc = BankAcc {
             balance: 1_000.0.into(),
         };
@@ -790,4 +1786,488 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_that_mortgage_overpayment_reduces_balance() {
+        let clock = ClockBuilder::with_length_in_days(1, 60)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_rates(daily_data_generator_static(0.02, Rc::clone(&clock)))
+            .build();
+
+        let mut mortgage = Mortgage::start(&500.0, 0.05, &1, 25, 1, Rc::clone(&clock), source);
+        let mut test_acc = BankAcc {
+            balance: 10_000.0.into(),
+        };
+
+        //Overpayment less than the balance is paid with the interest
+        let mut payments = Vec::new();
+        while clock.borrow().has_next() {
+            clock.borrow_mut().tick();
+            if let Some(LoanEvent::PaymentSuccess(paid)) =
+                mortgage.pay(Some(300.0.into()), &mut test_acc)
+            {
+                payments.push(*paid);
+            }
+        }
+        assert!((payments[0] - (300.0 + 500.0 * (0.05 / 12.0))).abs() < 0.01);
+
+        //Overpayment more than the balance clears the balance
+        assert!((payments[1] - (200.0 + 200.0 * (0.05 / 12.0))).abs() < 0.01);
+        assert!(*mortgage.balance() == 0.0);
+    }
+
+    #[test]
+    fn test_that_mortgage_refix_keeps_the_term() {
+        let clock = ClockBuilder::with_length_in_days(1, 450)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_rates(daily_data_generator_static(0.02, Rc::clone(&clock)))
+            .build();
+
+        let mut mortgage =
+            Mortgage::start(&100_000.0, 0.05, &1, 25, 1, Rc::clone(&clock), source);
+        let mut test_acc = BankAcc {
+            balance: 100_000.0.into(),
+        };
+
+        //Fix ends after a year, the new rate is lower but the principal is still repaid over
+        //the remaining term
+        let principal = 100_000.0 / 300.0;
+        let mut payments = 0;
+        let mut last_payment = 0.0;
+        let mut last_balance = 0.0;
+        while clock.borrow().has_next() {
+            clock.borrow_mut().tick();
+            let balance = *mortgage.balance();
+            if let Some(LoanEvent::PaymentSuccess(paid)) = mortgage.pay(None, &mut test_acc) {
+                payments += 1;
+                last_payment = *paid;
+                last_balance = balance;
+            }
+        }
+        assert!(payments > 12);
+        let balance = 100_000.0 - (payments as f64 * principal);
+        assert!((*mortgage.balance() - balance).abs() < 0.01);
+        let interest = last_payment - principal;
+        assert!(interest > last_balance * (0.04 / 12.0));
+        assert!(interest < last_balance * (0.05 / 12.0));
+    }
+
+    #[test]
+    fn test_that_student_loan_repayment_is_above_threshold() {
+        let plan = StudentLoanPlan::Plan2;
+        assert!(plan.repayment_logic(&20_000.0, &28_470.0) == 0.0);
+        assert!((plan.repayment_logic(&38_470.0, &28_470.0) - 900.0).abs() < 0.01);
+
+        let pg = StudentLoanPlan::Postgraduate;
+        assert!((pg.repayment_logic(&31_000.0, &21_000.0) - 600.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_that_plan_2_interest_tapers_with_income() {
+        let plan = StudentLoanPlan::Plan2;
+        let low = plan.interest_rate_logic(&0.03, &0.05, &20_000.0, &28_470.0, &51_245.0);
+        let high = plan.interest_rate_logic(&0.03, &0.05, &60_000.0, &28_470.0, &51_245.0);
+        assert!((low - 0.03).abs() < 0.0001);
+        assert!((high - 0.06).abs() < 0.0001);
+
+        //Plan 1 is capped at base rate plus one
+        let plan1 = StudentLoanPlan::Plan1;
+        let rate = plan1.interest_rate_logic(&0.05, &0.02, &60_000.0, &26_065.0, &26_065.0);
+        assert!((rate - 0.03).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_that_student_loans_share_undergraduate_deduction() {
+        let clock = ClockBuilder::with_length_in_days(1, 60)
+            .with_frequency(&Frequency::Daily)
+            .build();
+
+        let rates = daily_data_generator_static(0.02, Rc::clone(&clock));
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_rates(rates)
+            .build();
+
+        let mut loans = vec![
+            StudentLoan::new(
+                StudentLoanPlan::Plan2,
+                &1_000.0,
+                &1,
+                Rc::clone(&clock),
+                source.clone(),
+            ),
+            StudentLoan::new(
+                StudentLoanPlan::Plan1,
+                &100.0,
+                &1,
+                Rc::clone(&clock),
+                source.clone(),
+            ),
+        ];
+
+        //Only one deduction above the lowest threshold
+        let due = student_loan_due(&loans, &46_065.0, false);
+        assert!((*due - 1_800.0).abs() < 0.01);
+
+        //Lowest threshold is repaid first and repayments are capped at the balance
+        let repaid = repay_student_loans(&mut loans, &due);
+        assert!((*repaid - 1_100.0).abs() < 0.01);
+        assert!(!loans[0].is_active() && !loans[1].is_active());
+    }
+
+    #[test]
+    fn test_that_student_loan_interest_is_annual_rpi_plus_margin() {
+        let clock = ClockBuilder::with_length_in_days(1, 800)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_rates(daily_data_generator_static(0.0, Rc::clone(&clock)))
+            .with_inflation(daily_data_generator_static(0.05, Rc::clone(&clock)))
+            .build();
+
+        let mut loan = StudentLoan::new(
+            StudentLoanPlan::Postgraduate,
+            &10_000.0,
+            &1,
+            Rc::clone(&clock),
+            source,
+        );
+        //Skip the first year so that there is a full year of inflation
+        for _i in 0..400 {
+            clock.borrow_mut().tick();
+            loan.check(&0.0);
+        }
+        let start = *loan.balance();
+        for _i in 0..365 {
+            clock.borrow_mut().tick();
+            loan.check(&0.0);
+        }
+        //Twelve months of RPI + 3%
+        let expected = (1.0 + 0.08 / 12.0_f64).powi(12);
+        assert!((*loan.balance() / start - expected).abs() < 0.002);
+    }
+
+    #[test]
+    fn test_that_student_loan_is_written_off() {
+        let clock = ClockBuilder::with_length_in_days(1, 60)
+            .with_frequency(&Frequency::Daily)
+            .build();
+
+        let rates = daily_data_generator_static(0.02, Rc::clone(&clock));
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_rates(rates)
+            .build();
+
+        //First repayment far enough in the past that the write-off date has passed
+        let first_repayment = -(40 * 365 * 86_400);
+        let mut loan = StudentLoan::new(
+            StudentLoanPlan::Plan2,
+            &10_000.0,
+            &first_repayment,
+            Rc::clone(&clock),
+            source,
+        );
+
+        clock.borrow_mut().tick();
+        loan.check(&0.0);
+        assert!(*loan.balance() == 0.0);
+    }
+
+    #[test]
+    fn test_that_student_loan_is_written_off_on_the_anniversary() {
+        let clock = ClockBuilder::with_length_in_days(1, 60)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_rates(daily_data_generator_static(0.0, Rc::clone(&clock)))
+            .build();
+
+        //1940-01-11, thirty calendar years is ten days into the simulation, thirty years of 52
+        //weeks was 38 days earlier
+        let mut loan = StudentLoan::new(
+            StudentLoanPlan::Plan2,
+            &10_000.0,
+            &-945_907_199,
+            Rc::clone(&clock),
+            source,
+        );
+        for _i in 0..9 {
+            clock.borrow_mut().tick();
+            loan.check(&0.0);
+        }
+        assert!(loan.is_active());
+        clock.borrow_mut().tick();
+        loan.check(&0.0);
+        assert!(!loan.is_active());
+    }
+
+    #[test]
+    fn test_that_student_loan_isnt_repaid_before_first_repayment() {
+        let clock = ClockBuilder::with_length_in_days(1, 60)
+            .with_frequency(&Frequency::Daily)
+            .build();
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_rates(daily_data_generator_static(0.0, Rc::clone(&clock)))
+            .build();
+
+        let first_repayment = 1 + (30 * 86_400);
+        let mut loans = vec![StudentLoan::new(
+            StudentLoanPlan::Plan2,
+            &10_000.0,
+            &first_repayment,
+            Rc::clone(&clock),
+            source,
+        )];
+        clock.borrow_mut().tick();
+        assert!(*student_loan_due(&loans, &50_000.0, false) == 0.0);
+        assert!(*repay_student_loans(&mut loans, &1_000.0) == 0.0);
+
+        for _i in 0..30 {
+            clock.borrow_mut().tick();
+        }
+        assert!(*student_loan_due(&loans, &50_000.0, false) > 0.0);
+        assert!(*repay_student_loans(&mut loans, &1_000.0) == 1_000.0);
+    }
+
+    #[test]
+    fn test_that_property_grows_and_sale_realises_gain() {
+        let clock = ClockBuilder::with_length_in_days(1, 400)
+            .with_frequency(&Frequency::Daily)
+            .build();
+
+        let house_prices = daily_data_generator_static(0.1, Rc::clone(&clock));
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(&clock))
+            .with_house_prices(house_prices)
+            .build();
+
+        let sale_date = 1 + (365 * 86_400);
+        let mut let_property = Property::new(
+            &100_000.0,
+            &80_000.0,
+            false,
+            Some(sale_date.into()),
+            Rc::clone(&clock),
+            source.clone(),
+        );
+        let mut residence =
+            Property::new(&100_000.0, &80_000.0, true, None, Rc::clone(&clock), source);
+
+        while !let_property.sale_due() {
+            let_property.check();
+            residence.check();
+            clock.borrow_mut().tick();
+        }
+        assert!((*let_property.value() - 110_000.0).abs() < 1.0);
+        assert!((let_property.growth() - 1.1).abs() < 0.0001);
+
+        let (proceeds, gain) = let_property.sell();
+        assert!((*proceeds - 110_000.0).abs() < 1.0);
+        assert!((*gain - 30_000.0).abs() < 1.0);
+        assert!(*let_property.value() == 0.0 && !let_property.sale_due());
+
+        //Main residence is exempt
+        let (_proceeds, gain) = residence.sell();
+        assert!(*gain == 0.0);
+    }
+
+    //Nothing is invested so the value of the account is the cash held
+    fn cash_strat(clock: &Clock) -> StaticInvestmentStrategy {
+        let source = HashMapSourceSimBuilder::start()
+            .with_clock(Rc::clone(clock))
+            .build();
+        let exchange = DefaultExchangeBuilder::new()
+            .with_clock(Rc::clone(clock))
+            .with_data_source(source.clone())
+            .build();
+        let brkr = SimulatedBrokerBuilder::new()
+            .with_exchange(exchange)
+            .with_data(source)
+            .build();
+        StaticInvestmentStrategy::new(
+            brkr,
+            Schedule::EveryFriday,
+            PortfolioAllocation::new(),
+            Rc::clone(clock),
+        )
+    }
+
+    //2020-01-01
+    const START_DATE: i64 = 1_577_836_800;
+    const YEAR: i64 = 365 * 86_400;
+
+    fn clock() -> Clock {
+        ClockBuilder::with_length_in_days(START_DATE, 10)
+            .with_frequency(&Frequency::Daily)
+            .build()
+    }
+
+    fn lisa_aged(age: i64, start_cash: &f64) -> Lisa<StaticInvestmentStrategy> {
+        let clock = clock();
+        let date_of_birth = START_DATE - (age * YEAR) - (30 * 86_400);
+        Lisa::new_with_cash(cash_strat(&clock), start_cash, date_of_birth.into(), None, clock)
+    }
+
+    #[test]
+    fn test_that_lisa_deposit_receives_bonus() {
+        let mut lisa = lisa_aged(30, &0.0);
+        let (deposited, returned) = lisa.deposit_wrapper(&1_000.0, &20_000.0);
+        assert!(*deposited == 1_000.0 && *returned == 0.0);
+        assert!(*lisa.liquidation_value() == 1_250.0);
+    }
+
+    #[test]
+    fn test_that_lisa_deposits_are_capped() {
+        let mut lisa = lisa_aged(30, &0.0);
+        let (deposited, returned) = lisa.deposit_wrapper(&5_000.0, &20_000.0);
+        assert!(*deposited == 4_000.0 && *returned == 1_000.0);
+        assert!(*lisa.liquidation_value() == 5_000.0);
+
+        //Sub-limit is used up for the tax year
+        let (deposited, returned) = lisa.deposit_wrapper(&1_000.0, &20_000.0);
+        assert!(*deposited == 0.0 && *returned == 1_000.0);
+
+        //ISA allowance limits the deposit if there is less left than the sub-limit
+        let mut lisa = lisa_aged(30, &0.0);
+        let (deposited, returned) = lisa.deposit_wrapper(&4_000.0, &1_500.0);
+        assert!(*deposited == 1_500.0 && *returned == 2_500.0);
+    }
+
+    #[test]
+    fn test_that_lisa_deposits_stop_at_fifty() {
+        let mut lisa = lisa_aged(49, &0.0);
+        let (deposited, _returned) = lisa.deposit_wrapper(&1_000.0, &20_000.0);
+        assert!(*deposited == 1_000.0);
+
+        let mut lisa = lisa_aged(50, &0.0);
+        let (deposited, returned) = lisa.deposit_wrapper(&1_000.0, &20_000.0);
+        assert!(*deposited == 0.0 && *returned == 1_000.0);
+        assert!(*lisa.liquidation_value() == 0.0);
+    }
+
+    #[test]
+    fn test_that_lisa_withdrawal_is_penalised_before_sixty() {
+        //Penalty is a quarter of the gross amount so withdrawing 750 costs 1000
+        let mut lisa = lisa_aged(40, &10_000.0);
+        assert!(*lisa.withdrawable_value() == 7_500.0);
+        lisa.withdraw(&750.0);
+        assert!((*lisa.liquidation_value() - 9_000.0).abs() < 1e-6);
+
+        let mut lisa = lisa_aged(60, &10_000.0);
+        assert!(*lisa.withdrawable_value() == 10_000.0);
+        lisa.withdraw(&750.0);
+        assert!((*lisa.liquidation_value() - 9_250.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_that_lisa_withdrawal_for_first_home_is_not_penalised() {
+        let clock = clock();
+        let date_of_birth = START_DATE - (30 * YEAR);
+        let purchase = START_DATE + (2 * 86_400);
+        let mut lisa = Lisa::new_with_cash(
+            cash_strat(&clock),
+            &10_000.0,
+            date_of_birth.into(),
+            Some(purchase.into()),
+            Rc::clone(&clock),
+        );
+        assert!(*lisa.withdraw_for_first_home() == 0.0);
+        clock.borrow_mut().tick();
+        clock.borrow_mut().tick();
+        assert!(*lisa.withdraw_for_first_home() == 10_000.0);
+        assert!(*lisa.liquidation_value() == 0.0);
+
+        //Only the purchase is penalty-free, the bonus on later deposits is still clawed back
+        lisa.deposit_wrapper(&1_000.0, &20_000.0);
+        assert!(*lisa.withdraw_for_first_home() == 0.0);
+        assert!(*lisa.withdrawable_value() == 1_250.0 * 0.75);
+    }
+
+    fn sipp_with(drawdown_start: Option<i64>) -> Sipp<StaticInvestmentStrategy> {
+        let clock = clock();
+        Sipp::new_with_cash(
+            cash_strat(&clock),
+            &0.0,
+            &0.0,
+            Vec::new(),
+            drawdown_start.map(|d| d.into()),
+            ReliefMethod::NetPay,
+            clock,
+        )
+    }
+
+    #[test]
+    fn test_that_defined_benefit_input_shares_the_annual_allowance() {
+        let mut sipp = sipp_with(None);
+        sipp.add_defined_benefit_input(&55_000.0);
+        let (deposited, returned) = sipp.deposit_wrapper(&10_000.0);
+        assert!(*deposited == 5_000.0 && *returned == 5_000.0);
+        assert!(*sipp.annual_allowance_excess(&60_000.0) == 0.0);
+
+        //Taper lowers the allowance below the total input
+        let mut sipp = sipp_with(None);
+        sipp.add_defined_benefit_input(&55_000.0);
+        sipp.deposit_wrapper(&5_000.0);
+        assert!(*sipp.annual_allowance_excess(&50_000.0) == 10_000.0);
+    }
+
+    #[test]
+    fn test_that_defined_benefit_input_is_not_tested_against_the_mpaa() {
+        let mut sipp = sipp_with(Some(START_DATE));
+        sipp.add_defined_benefit_input(&30_000.0);
+        let (deposited, returned) = sipp.deposit_wrapper(&20_000.0);
+        assert!(*deposited == 10_000.0 && *returned == 10_000.0);
+        assert!(*sipp.annual_allowance_excess(&60_000.0) == 0.0);
+
+        //Defined benefit input over the allowance left after the MPAA is still charged
+        let mut sipp = sipp_with(Some(START_DATE));
+        sipp.add_defined_benefit_input(&55_000.0);
+        sipp.deposit_wrapper(&10_000.0);
+        assert!(*sipp.annual_allowance_excess(&60_000.0) == 5_000.0);
+    }
+
+    #[test]
+    fn test_that_jisa_deposits_are_capped() {
+        let clock = clock();
+        let date_of_birth = START_DATE - (5 * YEAR);
+        let mut jisa = Jisa::new(cash_strat(&clock), date_of_birth.into(), Rc::clone(&clock));
+        let (deposited, returned) = jisa.deposit_wrapper(&10_000.0);
+        assert!(*deposited == 9_000.0 && *returned == 1_000.0);
+        assert!(*jisa.liquidation_value() == 9_000.0);
+
+        jisa.tax_year_end();
+        let (deposited, _returned) = jisa.deposit_wrapper(&9_000.0);
+        assert!(*deposited == 9_000.0);
+    }
+
+    #[test]
+    fn test_that_jisa_becomes_adult_isa_at_eighteen() {
+        let clock = clock();
+        let date_of_birth = START_DATE - (18 * YEAR) - (30 * 86_400);
+        let mut jisa = Jisa::new_with_cash(
+            cash_strat(&clock),
+            &5_000.0,
+            date_of_birth.into(),
+            Rc::clone(&clock),
+        );
+        assert!(jisa.is_adult());
+        let (deposited, returned) = jisa.deposit_wrapper(&1_000.0);
+        assert!(*deposited == 0.0 && *returned == 1_000.0);
+
+        //Balance is kept and deposits are limited by the adult ISA allowance
+        let mut isa = jisa.into_adult_isa();
+        assert!(*isa.liquidation_value() == 5_000.0);
+        let (deposited, _returned) = isa.deposit_wrapper(&15_000.0);
+        assert!(*deposited == 15_000.0);
+    }
 }
@@ -7,7 +7,7 @@ use alator::sim::SimulatedBroker;
 use alator::strategy::{Strategy, StrategyEvent, TransferFrom, TransferTo};
 use alator::types::{CashValue, PortfolioAllocation};
 
-use crate::input::HashMapSourceSim;
+use crate::input::{HashMapSourceSim, SimDataSource};
 use crate::schedule::Schedule;
 
 //[InvestmentStrategy] supplements the [Strategy] provided by alator with methods that are relevant
@@ -18,6 +18,7 @@ use crate::schedule::Schedule;
 //to add the lifecycle methods to the strategy, so they can be called through accounts.
 pub trait InvestmentStrategy: Clone + Strategy + TransferFrom + TransferTo {
     fn get_liquidation_value(&self) -> CashValue;
+    fn get_cash_balance(&self) -> CashValue;
     fn trades_between(&self, start: &i64, end: &i64) -> Vec<Trade>;
     fn dividends_between(&self, start: &i64, end: &i64) -> Vec<DividendPayment>;
     fn check(&mut self);
@@ -29,14 +30,14 @@ pub trait InvestmentStrategy: Clone + Strategy + TransferFrom + TransferTo {
 }
 
 #[derive(Clone)]
-pub struct StaticInvestmentStrategy {
-    brkr: SimulatedBroker<HashMapSourceSim>,
+pub struct StaticInvestmentStrategy<D: SimDataSource = HashMapSourceSim> {
+    brkr: SimulatedBroker<D>,
     rebalance_schedule: Schedule,
     target_weights: PortfolioAllocation,
     clock: Clock,
 }
 
-impl Strategy for StaticInvestmentStrategy {
+impl<D: SimDataSource> Strategy for StaticInvestmentStrategy<D> {
     fn update(&mut self) -> CashValue {
         let date = self.clock.borrow().now();
         if self.rebalance_schedule.check(&date) {
@@ -57,14 +58,14 @@ impl Strategy for StaticInvestmentStrategy {
     }
 }
 
-impl TransferTo for StaticInvestmentStrategy {
+impl<D: SimDataSource> TransferTo for StaticInvestmentStrategy<D> {
     fn deposit_cash(&mut self, cash: &f64) -> StrategyEvent {
         self.brkr.deposit_cash(cash);
         StrategyEvent::DepositSuccess(CashValue::from(*cash))
     }
 }
 
-impl TransferFrom for StaticInvestmentStrategy {
+impl<D: SimDataSource> TransferFrom for StaticInvestmentStrategy<D> {
     fn withdraw_cash(&mut self, cash: &f64) -> StrategyEvent {
         if let BrokerCashEvent::WithdrawSuccess(amount) = self.brkr.withdraw_cash(cash) {
             return StrategyEvent::WithdrawSuccess(amount);
@@ -82,11 +83,15 @@ impl TransferFrom for StaticInvestmentStrategy {
     }
 }
 
-impl InvestmentStrategy for StaticInvestmentStrategy {
+impl<D: SimDataSource> InvestmentStrategy for StaticInvestmentStrategy<D> {
     fn get_liquidation_value(&self) -> CashValue {
         self.brkr.get_liquidation_value()
     }
 
+    fn get_cash_balance(&self) -> CashValue {
+        self.brkr.get_cash_balance()
+    }
+
     fn trades_between(&self, start: &i64, end: &i64) -> Vec<Trade> {
         self.brkr.trades_between(start, end)
     }
@@ -114,9 +119,9 @@ impl InvestmentStrategy for StaticInvestmentStrategy {
     }
 }
 
-impl StaticInvestmentStrategy {
+impl<D: SimDataSource> StaticInvestmentStrategy<D> {
     pub fn new(
-        brkr: SimulatedBroker<HashMapSourceSim>,
+        brkr: SimulatedBroker<D>,
         rebalance_schedule: Schedule,
         target_weights: PortfolioAllocation,
         clock: Clock,
@@ -25,6 +25,20 @@ impl From<TaxRate> for f64 {
     }
 }
 
+//CashValue is serialised as the underlying f64
+pub(crate) mod cash_value {
+    use alator::types::CashValue;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &CashValue, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(**value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CashValue, D::Error> {
+        f64::deserialize(deserializer).map(CashValue::from)
+    }
+}
+
 pub trait Threshold {
     const MIN: CashValue;
     const MAX: CashValue;
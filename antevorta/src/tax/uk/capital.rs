@@ -19,22 +19,38 @@ impl CapitalGainsTaxOutput {
 pub struct CapitalGainsTax;
 impl CapitalGainsTax {
     pub fn calc(period: &UKTaxInput, config: &UKTaxConfig) -> CapitalGainsTaxOutput {
-        let capital_gains = period.capital_gains.clone();
+        //Gains from the GIA are taxed at the other rate and gains on property at the residential
+        //rate. The annual exempt amount and the basic rate band are used against residential gains
+        //first as this rate is never lower.
+        let mut exempt = *config.capital_gains_allowance_band;
+        let residential_gain = (*period.residential_capital_gains).max(0.0);
+        let taxable_residential_gain = (residential_gain - exempt).max(0.0);
+        exempt = (exempt - residential_gain).max(0.0);
+        let taxable_other_gain = (*period.capital_gains - exempt).max(0.0);
+        if taxable_residential_gain + taxable_other_gain <= 0.0 {
+            return CapitalGainsTaxOutput::zero();
+        }
 
-        let taxable_income = IncomeTax::taxable_income(period);
+        let config = &config.extend_bands(&period.relief_at_source_contributions);
         //TODO: Probably need to be reduced for income over 100k
         //Need some separate calculation for the personal allowance?
-        let income_over_basic = *taxable_income - *config.personal_allowance_band;
-
-        let taxable_gain = *capital_gains - *config.capital_gains_allowance_band;
-        if income_over_basic + taxable_gain < *config.basic_income_top_band {
-            let resi_tax = *capital_gains * *config.basic_residential_capital_rate;
-            let other_tax = *capital_gains * *config.basic_other_capital_rate;
-            CapitalGainsTaxOutput(CashValue::from(resi_tax + other_tax))
-        } else {
-            let resi_tax = *capital_gains * *config.higher_residential_capital_rate;
-            let other_tax = *capital_gains * *config.higher_other_capital_rate;
-            CapitalGainsTaxOutput(CashValue::from(resi_tax + other_tax))
+        let taxable_income = IncomeTax::taxable_income(period, config);
+        //Gains are added on top of income, any part that falls within the basic rate band is
+        //taxed at the basic rate
+        let mut remaining_basic_band = *config.basic_income_top_band - *taxable_income;
+        if remaining_basic_band < 0.0 {
+            remaining_basic_band = 0.0;
         }
+
+        let residential_basic = taxable_residential_gain.min(remaining_basic_band);
+        let residential_tax = (residential_basic * *config.basic_residential_capital_rate)
+            + ((taxable_residential_gain - residential_basic)
+                * *config.higher_residential_capital_rate);
+        remaining_basic_band -= residential_basic;
+
+        let other_basic = taxable_other_gain.min(remaining_basic_band);
+        let other_tax = (other_basic * *config.basic_other_capital_rate)
+            + ((taxable_other_gain - other_basic) * *config.higher_other_capital_rate);
+        CapitalGainsTaxOutput(CashValue::from(residential_tax + other_tax))
     }
 }
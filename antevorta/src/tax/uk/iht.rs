@@ -0,0 +1,222 @@
+use alator::types::CashValue;
+use serde::{Deserialize, Serialize};
+
+//Pensions are brought into the estate for deaths from 6 April 2027
+const PENSIONS_IN_ESTATE_FROM: i64 = 1806969600;
+
+//Bands have been frozen since 2009 and are frozen until at least April 2030, the simulation holds
+//them at these values
+#[derive(Clone, Debug)]
+pub struct InheritanceTaxConfig {
+    rate: f64,
+    nil_rate_band: f64,
+    residence_nil_rate_band: f64,
+    //Residence nil-rate band is withdrawn by one pound for every two pounds over this
+    residence_nil_rate_band_taper_threshold: f64,
+}
+
+impl Default for InheritanceTaxConfig {
+    fn default() -> Self {
+        Self {
+            rate: 0.4,
+            nil_rate_band: 325_000.0,
+            residence_nil_rate_band: 175_000.0,
+            residence_nil_rate_band_taper_threshold: 2_000_000.0,
+        }
+    }
+}
+
+//User-supplied facts about the estate that the simulation doesn't model
+#[derive(Clone, Debug, Default)]
+pub struct EstateConfig {
+    //Value of the main residence passed to direct descendants
+    pub residence: f64,
+    //Defaults to the rules in force at the date of death
+    pub include_pensions: Option<bool>,
+    //Proportion of each band left unused by a spouse who died first, capped at one
+    pub transferred_nil_rate_band: f64,
+    pub transferred_residence_nil_rate_band: f64,
+}
+
+impl EstateConfig {
+    pub fn input(
+        &self,
+        assets: &f64,
+        pensions: &f64,
+        date_of_death: &i64,
+    ) -> InheritanceTaxInput {
+        let include_pensions = self
+            .include_pensions
+            .unwrap_or(*date_of_death >= PENSIONS_IN_ESTATE_FROM);
+        InheritanceTaxInput {
+            assets: CashValue::from(*assets),
+            pensions: CashValue::from(*pensions),
+            residence: CashValue::from(self.residence),
+            include_pensions,
+            transferred_nil_rate_band: self.transferred_nil_rate_band.clamp(0.0, 1.0),
+            transferred_residence_nil_rate_band: self
+                .transferred_residence_nil_rate_band
+                .clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InheritanceTaxInput {
+    pub assets: CashValue,
+    pub pensions: CashValue,
+    pub residence: CashValue,
+    pub include_pensions: bool,
+    pub transferred_nil_rate_band: f64,
+    pub transferred_residence_nil_rate_band: f64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UKEstateBreakdown {
+    pub date: i64,
+    pub assets: f64,
+    pub pensions: f64,
+    pub residence: f64,
+    pub pensions_included: bool,
+    pub estate: f64,
+    pub nil_rate_band: f64,
+    pub residence_nil_rate_band: f64,
+    pub taxable_estate: f64,
+    pub inheritance_tax: f64,
+    //Pensions outside of the estate pass to heirs without IHT
+    pub net_to_heirs: f64,
+}
+
+pub struct InheritanceTax;
+
+impl InheritanceTax {
+    //Residence nil-rate band can't be more than the residence passed on, and is tapered away on
+    //estates over the threshold. The estate value used for the taper includes pensions when they
+    //are in the estate.
+    fn residence_nil_rate_band(
+        input: &InheritanceTaxInput,
+        estate: &f64,
+        config: &InheritanceTaxConfig,
+    ) -> f64 {
+        let band = config.residence_nil_rate_band * (1.0 + input.transferred_residence_nil_rate_band);
+        let taper = ((estate - config.residence_nil_rate_band_taper_threshold) / 2.0).max(0.0);
+        (band - taper).max(0.0).min(*input.residence)
+    }
+
+    pub fn calc(
+        input: &InheritanceTaxInput,
+        config: &InheritanceTaxConfig,
+        date_of_death: &i64,
+    ) -> UKEstateBreakdown {
+        let mut estate = *input.assets + *input.residence;
+        if input.include_pensions {
+            estate += *input.pensions;
+        }
+
+        let nil_rate_band = config.nil_rate_band * (1.0 + input.transferred_nil_rate_band);
+        let residence_nil_rate_band = Self::residence_nil_rate_band(input, &estate, config);
+        let taxable_estate = (estate - nil_rate_band - residence_nil_rate_band).max(0.0);
+        let inheritance_tax = taxable_estate * config.rate;
+
+        let mut net_to_heirs = estate - inheritance_tax;
+        if !input.include_pensions {
+            net_to_heirs += *input.pensions;
+        }
+
+        UKEstateBreakdown {
+            date: *date_of_death,
+            assets: *input.assets,
+            pensions: *input.pensions,
+            residence: *input.residence,
+            pensions_included: input.include_pensions,
+            estate,
+            nil_rate_band,
+            residence_nil_rate_band,
+            taxable_estate,
+            inheritance_tax,
+            net_to_heirs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EstateConfig, InheritanceTax, InheritanceTaxConfig};
+
+    #[test]
+    fn test_that_estate_under_bands_pays_no_iht() {
+        let config = InheritanceTaxConfig::default();
+        let estate = EstateConfig {
+            residence: 175_000.0,
+            ..Default::default()
+        };
+        let input = estate.input(&325_000.0, &0.0, &0);
+        let res = InheritanceTax::calc(&input, &config, &0);
+        assert!(res.inheritance_tax == 0.0);
+        assert!(res.net_to_heirs == 500_000.0);
+    }
+
+    #[test]
+    fn test_that_spousal_transfer_doubles_bands() {
+        let config = InheritanceTaxConfig::default();
+        let estate = EstateConfig {
+            residence: 500_000.0,
+            transferred_nil_rate_band: 1.0,
+            transferred_residence_nil_rate_band: 1.0,
+            ..Default::default()
+        };
+        let input = estate.input(&500_000.0, &0.0, &0);
+        let res = InheritanceTax::calc(&input, &config, &0);
+        assert!(res.nil_rate_band == 650_000.0);
+        assert!(res.residence_nil_rate_band == 350_000.0);
+        assert!(res.inheritance_tax == 0.0);
+    }
+
+    #[test]
+    fn test_that_residence_nil_rate_band_tapers() {
+        let config = InheritanceTaxConfig::default();
+        let estate = EstateConfig {
+            residence: 500_000.0,
+            ..Default::default()
+        };
+        //Estate of 2.1m loses 50k of the band
+        let input = estate.input(&1_600_000.0, &0.0, &0);
+        let res = InheritanceTax::calc(&input, &config, &0);
+        assert!(res.residence_nil_rate_band == 125_000.0);
+
+        //Fully withdrawn above 2.35m
+        let input = estate.input(&2_000_000.0, &0.0, &0);
+        let res = InheritanceTax::calc(&input, &config, &0);
+        assert!(res.residence_nil_rate_band == 0.0);
+    }
+
+    #[test]
+    fn test_that_pensions_enter_estate_from_2027() {
+        let config = InheritanceTaxConfig::default();
+        let estate = EstateConfig::default();
+
+        //Death in 2026
+        let before = 1767225600;
+        let input = estate.input(&325_000.0, &100_000.0, &before);
+        let res = InheritanceTax::calc(&input, &config, &before);
+        assert!(!res.pensions_included);
+        assert!(res.inheritance_tax == 0.0);
+        assert!(res.net_to_heirs == 425_000.0);
+
+        //Death in 2028
+        let after = 1830297600;
+        let input = estate.input(&325_000.0, &100_000.0, &after);
+        let res = InheritanceTax::calc(&input, &config, &after);
+        assert!(res.pensions_included);
+        assert!((res.inheritance_tax - 40_000.0).abs() < 0.01);
+
+        //Can be overridden
+        let excluded = EstateConfig {
+            include_pensions: Some(false),
+            ..Default::default()
+        };
+        let input = excluded.input(&325_000.0, &100_000.0, &after);
+        let res = InheritanceTax::calc(&input, &config, &after);
+        assert!(res.inheritance_tax == 0.0);
+    }
+}
@@ -2,21 +2,19 @@ use alator::types::CashValue;
 
 use crate::tax::ThresholdCalculator;
 
-use super::{UKTaxConfig, UKTaxInput};
+use super::{ScottishIncomeBands, UKTaxConfig, UKTaxInput};
 
-fn basic(total_income: &f64, is_paye: bool, config: &UKTaxConfig) -> CashValue {
+//Personal allowance is tapered away for income over the taper threshold
+fn personal_allowance(total_income: &f64, is_paye: bool, config: &UKTaxConfig) -> f64 {
     let mut allowance_taper_threshold = *config.personal_allowance_taper_threshold_band;
     let mut min = *config.personal_allowance_band;
-    let mut max = *config.basic_income_top_band;
     if is_paye {
         allowance_taper_threshold /= 12.0;
         min /= 12.0;
-        max /= 12.0;
     }
 
-    let rate = config.basic_income_rate;
     if total_income < &allowance_taper_threshold {
-        return ThresholdCalculator::calc(&min, &max, rate)(total_income);
+        min
     } else {
         let income_over = *total_income - allowance_taper_threshold;
 
@@ -25,10 +23,21 @@ fn basic(total_income: &f64, is_paye: bool, config: &UKTaxConfig) -> CashValue {
         if min_basic_threshold < 0.0 {
             min_basic_threshold = 0.0;
         }
-        return ThresholdCalculator::calc(&min_basic_threshold, &max, rate)(total_income);
+        min_basic_threshold
     }
 }
 
+fn basic(total_income: &f64, is_paye: bool, config: &UKTaxConfig) -> CashValue {
+    let min = personal_allowance(total_income, is_paye, config);
+    let mut max = *config.basic_income_top_band;
+    if is_paye {
+        max /= 12.0;
+    }
+
+    let rate = config.basic_income_rate;
+    ThresholdCalculator::calc(&min, &max, rate)(total_income)
+}
+
 fn higher(total_income: &f64, is_paye: bool, config: &UKTaxConfig) -> CashValue {
     let rate = config.higher_income_rate;
     let min = *config.basic_income_top_band + 1.0;
@@ -51,6 +60,50 @@ fn additional(total_income: &f64, is_paye: bool, config: &UKTaxConfig) -> CashVa
     }
 }
 
+//Starter, basic and intermediate are returned as basic, advanced and top are returned as additional
+fn scottish(
+    total_income: &f64,
+    is_paye: bool,
+    bands: &ScottishIncomeBands,
+    config: &UKTaxConfig,
+) -> (CashValue, CashValue, CashValue) {
+    let mut min = personal_allowance(total_income, is_paye, config);
+    let mut tax = Vec::new();
+    for (top, rate) in bands.bands() {
+        let mut max = top;
+        if is_paye && top != f64::MAX {
+            max /= 12.0;
+        }
+        if max > min {
+            tax.push(*ThresholdCalculator::calc(&min, &max, rate)(total_income));
+            min = max;
+        } else {
+            tax.push(0.0);
+        }
+    }
+    (
+        CashValue::from(tax[0] + tax[1] + tax[2]),
+        CashValue::from(tax[3]),
+        CashValue::from(tax[4] + tax[5]),
+    )
+}
+
+//Tax on non-savings, non-dividend income, this is the only income that is taxed at Scottish rates
+fn non_savings(
+    total_income: &f64,
+    is_paye: bool,
+    config: &UKTaxConfig,
+) -> (CashValue, CashValue, CashValue) {
+    match &config.scottish_bands {
+        Some(bands) => scottish(total_income, is_paye, bands, config),
+        None => (
+            basic(total_income, is_paye, config),
+            higher(total_income, is_paye, config),
+            additional(total_income, is_paye, config),
+        ),
+    }
+}
+
 //Savings allowance is calculated using taxable income including savings.
 //If savings income puts you into the additional bracket then there is no allowance.
 //We can safely calculate after income_tax has been calculated on all income.
@@ -87,23 +140,53 @@ fn personal_savings_allowance(
     }
 }
 
+//Starting rate band is reduced by non-savings income over the personal allowance
 fn starting_savings_allowance(
     savings_income: &f64,
-    total_income: &f64,
+    non_savings_income: &f64,
     config: &UKTaxConfig,
 ) -> CashValue {
     let allowance = *config.starting_savings_allowance_band;
     let threshold = *config.personal_allowance_band + allowance;
+    let available = (threshold - *non_savings_income).clamp(0.0, allowance);
+    CashValue::from(savings_income.min(available))
+}
 
-    if *total_income < threshold {
-        if *savings_income > allowance {
-            CashValue::from(allowance)
-        } else {
-            CashValue::from(*savings_income)
-        }
-    } else {
-        CashValue::from(0.0)
+//Savings are taxed on top of non-savings income using rUK bands for all taxpayers. The starting
+//rate and the personal savings allowance are taxed at 0% but still use up the bands, so they are
+//taken from the bottom of the savings slice. Returns (basic, higher, additional, allowance).
+fn savings(
+    non_savings_income: &f64,
+    savings_income: &f64,
+    config: &UKTaxConfig,
+) -> (CashValue, CashValue, CashValue, CashValue) {
+    if *savings_income <= 0.0 {
+        return (
+            CashValue::default(),
+            CashValue::default(),
+            CashValue::default(),
+            CashValue::default(),
+        );
     }
+
+    let total_income = *non_savings_income + *savings_income;
+    let personal_savings_allowance = personal_savings_allowance(
+        savings_income,
+        &basic(&total_income, false, config),
+        &higher(&total_income, false, config),
+        &additional(&total_income, false, config),
+        config,
+    );
+    let starting_savings_allowance =
+        starting_savings_allowance(savings_income, non_savings_income, config);
+    let allowance =
+        (*personal_savings_allowance + *starting_savings_allowance).min(*savings_income);
+
+    let taxed_from = *non_savings_income + allowance;
+    let slice = |band: fn(&f64, bool, &UKTaxConfig) -> CashValue| {
+        CashValue::from(*band(&total_income, false, config) - *band(&taxed_from, false, config))
+    };
+    (slice(basic), slice(higher), slice(additional), CashValue::from(allowance))
 }
 
 fn self_employment_allowance(self_employment_income: &f64, config: &UKTaxConfig) -> CashValue {
@@ -131,72 +214,134 @@ fn rental_allowance(rental_income: &f64, config: &UKTaxConfig) -> CashValue {
     CashValue::from(0.0)
 }
 
+//Allowances are the income taxed at 0% by the savings, rental and trading allowances, this is
+//already taken out of the bands so isn't deducted from the total
 #[derive(Debug)]
 pub struct IncomeTaxOutput(CashValue, CashValue, CashValue, CashValue, CashValue);
 impl IncomeTaxOutput {
+    pub fn basic(&self) -> CashValue {
+        self.0.clone()
+    }
+
+    pub fn higher(&self) -> CashValue {
+        self.1.clone()
+    }
+
     pub fn additional(&self) -> CashValue {
         self.2.clone()
     }
 
+    pub fn allowances(&self) -> CashValue {
+        self.3.clone()
+    }
+
     pub fn taxable_income(&self) -> CashValue {
         self.4.clone()
     }
 
     pub fn total(&self) -> CashValue {
-        CashValue::from(*self.0 + *self.1 + *self.2 - *self.3)
+        CashValue::from(*self.0 + *self.1 + *self.2)
     }
 }
 
 pub struct IncomeTax;
 impl IncomeTax {
     pub fn calc(input: &UKTaxInput, config: &UKTaxConfig) -> IncomeTaxOutput {
-        let taxable_income_net_pension = Self::taxable_income(input);
-        let basic = basic(&taxable_income_net_pension, false, config);
-        let higher = higher(&taxable_income_net_pension, false, config);
-        let additional = additional(&taxable_income_net_pension, false, config);
-
-        let allowances = Self::allowances(input, &basic, &higher, &additional, config);
+        let config = &config.extend_bands(&input.relief_at_source_contributions);
+        let non_savings_income = Self::non_savings_income(input, config);
+        let (basic, higher, additional) = non_savings(&non_savings_income, false, config);
+        let (savings_basic, savings_higher, savings_additional, savings_allowance) =
+            self::savings(&non_savings_income, &input.savings, config);
+
+        let allowances = *savings_allowance
+            + *self_employment_allowance(&input.self_employment, config)
+            + *rental_allowance(&input.rental, config);
         IncomeTaxOutput(
-            basic,
-            higher,
-            additional,
-            allowances,
-            taxable_income_net_pension,
-        )
-    }
-
-    fn allowances(
-        period: &UKTaxInput,
-        basic: &CashValue,
-        higher: &CashValue,
-        additional: &CashValue,
-        config: &UKTaxConfig,
-    ) -> CashValue {
-        let total_income = period.non_paye_employment.clone();
-        let savings_income = period.savings.clone();
-        let rental_income = period.rental.clone();
-        let self_employment_income = period.self_employment.clone();
-
-        let saving_allowance =
-            personal_savings_allowance(&savings_income, basic, higher, additional, config);
-        let self_employment_allowance = self_employment_allowance(&self_employment_income, config);
-        let rental_allowance = rental_allowance(&rental_income, config);
-        let starting_savings_allowance =
-            starting_savings_allowance(&savings_income, &total_income, config);
-        CashValue::from(
-            *saving_allowance
-                + *self_employment_allowance
-                + *rental_allowance
-                + *starting_savings_allowance,
+            CashValue::from(*basic + *savings_basic),
+            CashValue::from(*higher + *savings_higher),
+            CashValue::from(*additional + *savings_additional),
+            CashValue::from(allowances),
+            Self::taxable_income(input, config),
         )
     }
 
-    pub fn taxable_income(input: &UKTaxInput) -> CashValue {
-        let total_income = input.non_paye_employment.clone();
+    fn non_savings_income(input: &UKTaxInput, config: &UKTaxConfig) -> CashValue {
+        //PAYE income is included so that the annual calculation can find any balance over the
+        //tax already paid through PAYE. Rental and trading profit are taxed after the property
+        //and trading allowances.
+        let rental = *input.rental - *rental_allowance(&input.rental, config);
+        let self_employment = *input.self_employment
+            - *self_employment_allowance(&input.self_employment, config);
+        let total_income = *input.non_paye_employment
+            + *input.paye_employment
+            + *input.pension
+            + rental
+            + self_employment;
         //in SIPP account, and we only pass contribution after we are
         //sure that it can be deposited into account with breaking limits
         let total_contributions = input.contributions.clone();
-        CashValue::from(*total_income - *total_contributions)
+        CashValue::from(total_income - *total_contributions)
+    }
+
+    //Non-savings income with savings on top, dividends and gains are taxed above this
+    pub fn taxable_income(input: &UKTaxInput, config: &UKTaxConfig) -> CashValue {
+        CashValue::from(*Self::non_savings_income(input, config) + *input.savings)
+    }
+}
+
+//Contributions over the annual allowance are added to income and taxed at the marginal rate
+pub struct AnnualAllowanceCharge;
+
+impl AnnualAllowanceCharge {
+    pub fn calc(input: &UKTaxInput, config: &UKTaxConfig) -> CashValue {
+        let excess = *input.annual_allowance_excess;
+        if excess <= 0.0 {
+            return CashValue::default();
+        }
+
+        let config = &config.extend_bands(&input.relief_at_source_contributions);
+        let tax = |income: &f64| -> f64 {
+            let (basic, higher, additional) = non_savings(income, false, config);
+            *basic + *higher + *additional
+        };
+        let taxable_income = *IncomeTax::taxable_income(input, config);
+        let with_excess = taxable_income + excess;
+        CashValue::from(tax(&with_excess) - tax(&taxable_income))
+    }
+}
+
+//Adjusted net income is total taxable income less gross pension contributions
+pub fn adjusted_net_income(input: &UKTaxInput, config: &UKTaxConfig) -> CashValue {
+    let income = *IncomeTax::taxable_income(input, config)
+        + *input.dividend
+        - *input.relief_at_source_contributions;
+    CashValue::from(income)
+}
+
+//Child benefit is charged back at one percent for each equal step between the charge band and the
+//top band, so all child benefit is charged back at the top band
+pub struct HighIncomeChildBenefitCharge;
+
+impl HighIncomeChildBenefitCharge {
+    pub fn calc(input: &UKTaxInput, config: &UKTaxConfig) -> CashValue {
+        let child_benefit = *input.child_benefit;
+        if child_benefit <= 0.0 {
+            return CashValue::default();
+        }
+
+        let income = *adjusted_net_income(input, config);
+        let min = *config.child_benefit_charge_band;
+        let max = *config.child_benefit_charge_top_band;
+        if income <= min {
+            return CashValue::default();
+        }
+
+        let step = (max - min) / 100.0;
+        let mut pct = ((income - min) / step).floor() / 100.0;
+        if pct > 1.0 {
+            pct = 1.0;
+        }
+        CashValue::from(child_benefit * pct)
     }
 }
 
@@ -209,9 +354,7 @@ impl PAYEIncomeTax {
         //sure that it can be deposited into account with breaking limits
         let taxable_income_net_pension = *pay - *contribution;
 
-        let basic = basic(&taxable_income_net_pension, true, config);
-        let higher = higher(&taxable_income_net_pension, true, config);
-        let additional = additional(&taxable_income_net_pension, true, config);
+        let (basic, higher, additional) = non_savings(&taxable_income_net_pension, true, config);
         //PAYE has no allowances
         IncomeTaxOutput(
             basic,
@@ -223,6 +366,32 @@ impl PAYEIncomeTax {
     }
 }
 
+//Basic, higher and additional rate bands
+#[derive(Debug)]
+pub struct DividendTaxOutput(CashValue, CashValue, CashValue);
+
+impl DividendTaxOutput {
+    pub fn basic(&self) -> CashValue {
+        self.0.clone()
+    }
+
+    pub fn higher(&self) -> CashValue {
+        self.1.clone()
+    }
+
+    pub fn additional(&self) -> CashValue {
+        self.2.clone()
+    }
+
+    pub fn total(&self) -> CashValue {
+        CashValue::from(*self.0 + *self.1 + *self.2)
+    }
+
+    pub fn zero() -> Self {
+        DividendTaxOutput(CashValue::default(), CashValue::default(), CashValue::default())
+    }
+}
+
 pub struct DividendTax;
 
 impl DividendTax {
@@ -230,7 +399,8 @@ impl DividendTax {
         period: &UKTaxInput,
         income_tax: &IncomeTaxOutput,
         config: &UKTaxConfig,
-    ) -> CashValue {
+    ) -> DividendTaxOutput {
+        let config = &config.extend_bands(&period.relief_at_source_contributions);
         let dividend = period.dividend.clone();
 
         let allowance = *config.dividend_allowance_band;
@@ -239,51 +409,102 @@ impl DividendTax {
         let additional = config.additional_dividend_rate;
 
         if *dividend <= allowance {
-            return CashValue::default();
+            return DividendTaxOutput::zero();
         }
 
         let taxable_amount = *dividend - allowance;
-        if *income_tax.additional() > 0.0 {
-            CashValue::from(taxable_amount * *additional)
+        //Dividends use rUK bands for all taxpayers
+        let taxable_income = *income_tax.taxable_income();
+        if taxable_income > *config.higher_rate_top_band {
+            DividendTaxOutput(
+                CashValue::default(),
+                CashValue::default(),
+                CashValue::from(taxable_amount * *additional),
+            )
         } else {
             let mut income_left = taxable_amount;
-            let mut tax_paid = 0.0;
+            let mut basic_paid = 0.0;
+            let mut higher_paid = 0.0;
+            let mut additional_paid = 0.0;
 
-            let remaining_basic_allowance =
-                *config.basic_income_top_band - *income_tax.taxable_income();
+            let remaining_basic_allowance = *config.basic_income_top_band - taxable_income;
             if remaining_basic_allowance > 0.0 {
                 if remaining_basic_allowance < income_left {
-                    tax_paid += remaining_basic_allowance * *basic;
+                    basic_paid += remaining_basic_allowance * *basic;
                     income_left -= remaining_basic_allowance;
                 } else {
-                    tax_paid += income_left * *basic;
+                    basic_paid += income_left * *basic;
                     income_left = 0.0;
                 }
             }
-            let remaining_higher_allowance =
-                *config.higher_rate_top_band - *income_tax.taxable_income();
+            //Higher rate band starts wherever income or the basic rate band ends
+            let higher_start = if taxable_income > *config.basic_income_top_band {
+                taxable_income
+            } else {
+                *config.basic_income_top_band
+            };
+            let remaining_higher_allowance = *config.higher_rate_top_band - higher_start;
             if remaining_higher_allowance > 0.0 && income_left > 0.0 {
                 if remaining_higher_allowance < income_left {
-                    tax_paid += remaining_higher_allowance * *higher;
+                    higher_paid += remaining_higher_allowance * *higher;
                     income_left -= remaining_higher_allowance;
                 } else {
-                    tax_paid += income_left * *higher;
+                    higher_paid += income_left * *higher;
                     income_left = 0.0;
                 }
             }
             if income_left > 0.0 {
-                tax_paid += income_left * *additional;
+                additional_paid += income_left * *additional;
             }
-            CashValue::from(tax_paid)
+            DividendTaxOutput(
+                CashValue::from(basic_paid),
+                CashValue::from(higher_paid),
+                CashValue::from(additional_paid),
+            )
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::personal_savings_allowance;
+    use super::{personal_savings_allowance, AnnualAllowanceCharge, HighIncomeChildBenefitCharge};
+
+    use crate::tax::uk::{UKTaxConfig, UKTaxInput};
+
+    #[test]
+    fn test_annual_allowance_charge_at_marginal_rate() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 100_000.0.into();
+        let no_charge = AnnualAllowanceCharge::calc(&input, &config);
+        assert!(*no_charge == 0.0);
+
+        //Excess doesn't push income over the next band so should all be at the higher rate
+        input.annual_allowance_excess = 10_000.0.into();
+        let charge = AnnualAllowanceCharge::calc(&input, &config);
+        assert!(*charge > 3_999.0);
+    }
 
-    use crate::tax::uk::UKTaxConfig;
+    #[test]
+    fn test_that_child_benefit_charge_is_reduced_by_contributions() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.child_benefit = 2_000.0.into();
+
+        input.non_paye_employment = (*config.child_benefit_charge_band + 10_000.0).into();
+        let charge = HighIncomeChildBenefitCharge::calc(&input, &config);
+        assert!(*charge > 0.0 && *charge < 2_000.0);
+
+        //Contributions take adjusted net income back under the band
+        input.contributions = 10_000.0.into();
+        let no_charge = HighIncomeChildBenefitCharge::calc(&input, &config);
+        assert!(*no_charge == 0.0);
+
+        input.contributions = 0.0.into();
+        input.non_paye_employment = (*config.child_benefit_charge_top_band + 1.0).into();
+        let full_charge = HighIncomeChildBenefitCharge::calc(&input, &config);
+        assert!(*full_charge == 2_000.0);
+    }
 
     #[test]
     fn test_savings_allowance_output() {
@@ -1,60 +1,175 @@
 mod capital;
+mod iht;
 mod income;
 mod ni;
+mod tables;
 
 use alator::types::CashValue;
+use serde::{Deserialize, Serialize};
 
-use crate::tax::TaxRate;
+use crate::tax::{cash_value, TaxRate};
 
 use self::capital::{CapitalGainsTax, CapitalGainsTaxOutput};
-use self::income::{DividendTax, IncomeTax, IncomeTaxOutput, PAYEIncomeTax};
+use self::income::{
+    AnnualAllowanceCharge, DividendTax, DividendTaxOutput, HighIncomeChildBenefitCharge,
+    IncomeTax, IncomeTaxOutput, PAYEIncomeTax,
+};
 use self::ni::NITaxOutput;
+pub use self::iht::{EstateConfig, InheritanceTax, InheritanceTaxConfig, UKEstateBreakdown};
 pub use self::ni::NIC;
+pub use self::tables::{FiscalDrag, UKTaxTables};
 
 #[derive(Debug)]
 pub struct UKTaxOutput {
     income: IncomeTaxOutput,
     capital_gains: CapitalGainsTaxOutput,
     ni: NITaxOutput,
-    dividend: CashValue,
+    nic: NIC,
+    dividend: DividendTaxOutput,
+    annual_allowance_charge: CashValue,
+    child_benefit_charge: CashValue,
     paye_tax_paid: CashValue,
+    paye_ni_paid: CashValue,
 }
 
 impl UKTaxOutput {
     pub fn total(&self) -> CashValue {
-        let sum =
-            *self.income.total() + *self.capital_gains.total() + *self.ni.total() + *self.dividend
-                - *self.paye_tax_paid;
+        let sum = *self.income.total()
+            + *self.capital_gains.total()
+            + *self.ni.total()
+            + *self.dividend.total()
+            + *self.annual_allowance_charge
+            + *self.child_benefit_charge
+            - *self.paye_tax_paid;
         CashValue::from(sum)
     }
+
+    pub fn income_tax(&self) -> CashValue {
+        self.income.total()
+    }
+
+    pub fn ni(&self) -> CashValue {
+        self.ni.total()
+    }
+
+    pub fn breakdown(&self, tax_year: i32) -> UKTaxBreakdown {
+        UKTaxBreakdown {
+            tax_year,
+            taxable_income: *self.income.taxable_income(),
+            income_tax_basic: *self.income.basic(),
+            income_tax_higher: *self.income.higher(),
+            income_tax_additional: *self.income.additional(),
+            allowances: *self.income.allowances(),
+            ni_category: self.nic,
+            ni_main: *self.ni.main(),
+            ni_upper: *self.ni.upper(),
+            paye_ni_paid: *self.paye_ni_paid,
+            dividend_tax_basic: *self.dividend.basic(),
+            dividend_tax_higher: *self.dividend.higher(),
+            dividend_tax_additional: *self.dividend.additional(),
+            capital_gains_tax: *self.capital_gains.total(),
+            annual_allowance_charge: *self.annual_allowance_charge,
+            child_benefit_charge: *self.child_benefit_charge,
+            paye_tax_paid: *self.paye_tax_paid,
+            balancing_payment: *self.total(),
+        }
+    }
 }
 
+//Itemised tax for one tax year. NI is only calculated here for income that hasn't been through
+//PAYE, PAYE NI is final when paid. Scottish starter to intermediate rates are reported in basic,
+//advanced and top rates are reported in additional.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UKTaxBreakdown {
+    pub tax_year: i32,
+    pub taxable_income: f64,
+    pub income_tax_basic: f64,
+    pub income_tax_higher: f64,
+    pub income_tax_additional: f64,
+    //Income taxed at 0% by the savings, rental and trading allowances, not deducted from the tax
+    pub allowances: f64,
+    pub ni_category: NIC,
+    pub ni_main: f64,
+    pub ni_upper: f64,
+    pub paye_ni_paid: f64,
+    pub dividend_tax_basic: f64,
+    pub dividend_tax_higher: f64,
+    pub dividend_tax_additional: f64,
+    pub capital_gains_tax: f64,
+    pub annual_allowance_charge: f64,
+    pub child_benefit_charge: f64,
+    pub paye_tax_paid: f64,
+    //Due after PAYE has been deducted, negative if too much was paid through PAYE
+    pub balancing_payment: f64,
+}
+
+//Fields that aren't given are zero
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct UKTaxInput {
+    #[serde(with = "cash_value")]
     pub non_paye_employment: CashValue,
+    #[serde(with = "cash_value")]
     pub paye_employment: CashValue,
+    //Pension and annuity income, taxed as non-savings income without NI
+    #[serde(with = "cash_value")]
+    pub pension: CashValue,
+    //Interest, taxed above non-savings income after the starting rate and savings allowance
+    #[serde(with = "cash_value")]
     pub savings: CashValue,
+    //Rental profit, taxed as non-savings income after the property allowance
+    #[serde(with = "cash_value")]
     pub rental: CashValue,
+    //Trading profit, taxed as non-savings income after the trading allowance. Class 4 NI isn't
+    //calculated.
+    #[serde(with = "cash_value")]
     pub self_employment: CashValue,
+    #[serde(with = "cash_value")]
     pub contributions: CashValue,
+    //Income tax paid through PAYE, deducted from the annual calculation
+    #[serde(with = "cash_value")]
     pub paye_tax_paid: CashValue,
+    //NI paid through PAYE, this is final so is only used for reporting
+    #[serde(with = "cash_value")]
+    pub paye_ni_paid: CashValue,
     pub ni: NIC,
+    #[serde(with = "cash_value")]
     pub dividend: CashValue,
+    #[serde(with = "cash_value")]
     pub capital_gains: CashValue,
+    //Gains on property that isn't exempt as a main residence
+    #[serde(with = "cash_value")]
+    pub residential_capital_gains: CashValue,
+    //Pension contributions over the annual allowance after taper and carry-forward
+    #[serde(with = "cash_value")]
+    pub annual_allowance_excess: CashValue,
+    //Gross pension contributions made with relief at source, these aren't deducted from income
+    #[serde(with = "cash_value")]
+    pub relief_at_source_contributions: CashValue,
+    //Received by the taxpayer or their partner, charged back above the adjusted net income band
+    #[serde(with = "cash_value")]
+    pub child_benefit: CashValue,
 }
 
-impl UKTaxInput {
-    pub fn default() -> Self {
+impl Default for UKTaxInput {
+    fn default() -> Self {
         Self {
             non_paye_employment: CashValue::from(0.0),
             paye_employment: CashValue::from(0.0),
+            pension: CashValue::from(0.0),
             savings: CashValue::from(0.0),
             rental: CashValue::from(0.0),
             self_employment: CashValue::from(0.0),
             contributions: CashValue::from(0.0),
             paye_tax_paid: CashValue::from(0.0),
+            paye_ni_paid: CashValue::from(0.0),
             ni: NIC::A,
             dividend: CashValue::from(0.0),
             capital_gains: CashValue::from(0.0),
+            residential_capital_gains: CashValue::from(0.0),
+            annual_allowance_excess: CashValue::from(0.0),
+            relief_at_source_contributions: CashValue::from(0.0),
+            child_benefit: CashValue::from(0.0),
         }
     }
 }
@@ -68,33 +183,108 @@ impl TaxPeriod {
         let cg = CapitalGainsTax::calc(input, config);
         let ni = input.ni.calc(input, config);
         let divi = DividendTax::calc(input, &it, config);
+        let aa_charge = AnnualAllowanceCharge::calc(input, config);
+        let cb_charge = HighIncomeChildBenefitCharge::calc(input, config);
 
         UKTaxOutput {
             income: it,
             capital_gains: cg,
             ni,
+            nic: input.ni,
             dividend: divi,
+            annual_allowance_charge: aa_charge,
+            child_benefit_charge: cb_charge,
             paye_tax_paid: input.paye_tax_paid.clone(),
+            paye_ni_paid: input.paye_ni_paid.clone(),
         }
     }
 
+    //Standalone calculation outside of the simulation, uses the table for the tax year given
+    pub fn calc_for_year(input: &UKTaxInput, tax_year: i32, residency: Residency) -> UKTaxBreakdown {
+        let tables = UKTaxTables::new(residency, FiscalDrag::default());
+        TaxPeriod::calc(input, &tables.config(&tax_year)).breakdown(tax_year)
+    }
+
     //PAYE calculations are totally stateless
     pub fn paye(pay: &f64, contribution: &f64, ni: NIC, config: &UKTaxConfig) -> UKTaxOutput {
         //Filters income passed for PAYE only so can't be called with
         //wrong input
         let it = PAYEIncomeTax::calc(pay, contribution, config);
-        let ni = ni.paye_calc(pay, config);
+        let ni_paid = ni.paye_calc(pay, config);
 
         UKTaxOutput {
             income: it,
             capital_gains: CapitalGainsTaxOutput::zero(),
-            ni,
-            dividend: CashValue::default(),
+            ni: ni_paid,
+            nic: ni,
+            dividend: DividendTaxOutput::zero(),
+            annual_allowance_charge: CashValue::default(),
+            child_benefit_charge: CashValue::default(),
             paye_tax_paid: CashValue::default(),
+            paye_ni_paid: CashValue::default(),
         }
     }
 }
 
+//Scottish taxpayers pay Scottish rates on non-savings, non-dividend income, savings and dividends
+//are still taxed using rUK bands
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum Residency {
+    RestOfUK,
+    Scotland,
+}
+
+//Top of each band is measured on total income, including the personal allowance, like the rUK
+//bands
+#[derive(Clone, Debug)]
+pub struct ScottishIncomeBands {
+    starter_rate: TaxRate,
+    basic_rate: TaxRate,
+    intermediate_rate: TaxRate,
+    higher_rate: TaxRate,
+    advanced_rate: TaxRate,
+    top_rate: TaxRate,
+    starter_top_band: CashValue,
+    basic_top_band: CashValue,
+    intermediate_top_band: CashValue,
+    higher_top_band: CashValue,
+    advanced_top_band: CashValue,
+}
+
+impl ScottishIncomeBands {
+    //Ordered from lowest band, the last band has no top
+    fn bands(&self) -> Vec<(f64, TaxRate)> {
+        vec![
+            (*self.starter_top_band, self.starter_rate),
+            (*self.basic_top_band, self.basic_rate),
+            (*self.intermediate_top_band, self.intermediate_rate),
+            (*self.higher_top_band, self.higher_rate),
+            (*self.advanced_top_band, self.advanced_rate),
+            (f64::MAX, self.top_rate),
+        ]
+    }
+
+    fn extend_bands(&self, value: &f64) -> ScottishIncomeBands {
+        let mut bands = self.clone();
+        bands.starter_top_band = CashValue::from(*self.starter_top_band + value);
+        bands.basic_top_band = CashValue::from(*self.basic_top_band + value);
+        bands.intermediate_top_band = CashValue::from(*self.intermediate_top_band + value);
+        bands.higher_top_band = CashValue::from(*self.higher_top_band + value);
+        bands.advanced_top_band = CashValue::from(*self.advanced_top_band + value);
+        bands
+    }
+
+    fn apply_inflation(&self, value: &f64) -> ScottishIncomeBands {
+        let mut bands = self.clone();
+        bands.starter_top_band = CashValue::from(*self.starter_top_band * (1.0 + value));
+        bands.basic_top_band = CashValue::from(*self.basic_top_band * (1.0 + value));
+        bands.intermediate_top_band = CashValue::from(*self.intermediate_top_band * (1.0 + value));
+        bands.higher_top_band = CashValue::from(*self.higher_top_band * (1.0 + value));
+        bands.advanced_top_band = CashValue::from(*self.advanced_top_band * (1.0 + value));
+        bands
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct UKTaxConfig {
     basic_income_rate: TaxRate,
@@ -110,6 +300,7 @@ pub struct UKTaxConfig {
     ni_band_1_rate: TaxRate,
     ni_band_2_rate: TaxRate,
     ni_band_3_rate: TaxRate,
+    ni_employer_rate: TaxRate,
     personal_allowance_band: CashValue,
     personal_allowance_taper_threshold_band: CashValue,
     personal_allowance_taper_value: CashValue,
@@ -124,43 +315,86 @@ pub struct UKTaxConfig {
     ni_band_2_band: CashValue,
     basic_rate_savings_allowance_band: CashValue,
     higher_rate_savings_allowance_band: CashValue,
+    child_benefit_eldest_weekly: CashValue,
+    child_benefit_additional_weekly: CashValue,
+    //Charge starts at this adjusted net income and reaches all child benefit at the top band
+    child_benefit_charge_band: CashValue,
+    child_benefit_charge_top_band: CashValue,
+    //Only for Scottish taxpayers, replaces rUK bands for non-savings, non-dividend income
+    scottish_bands: Option<ScottishIncomeBands>,
 }
 
+//Latest tax year with rUK bands
 impl Default for UKTaxConfig {
     fn default() -> Self {
-        Self {
-            basic_income_rate: TaxRate::from(0.22),
-            higher_income_rate: TaxRate::from(0.4),
-            additional_income_rate: TaxRate::from(0.45),
-            basic_dividend_rate: TaxRate::from(0.0875),
-            higher_dividend_rate: TaxRate::from(0.3375),
-            additional_dividend_rate: TaxRate::from(0.3935),
-            basic_residential_capital_rate: TaxRate::from(0.18),
-            basic_other_capital_rate: TaxRate::from(0.1),
-            higher_residential_capital_rate: TaxRate::from(0.28),
-            higher_other_capital_rate: TaxRate::from(0.28),
-            ni_band_1_rate: TaxRate::from(0.0325),
-            ni_band_2_rate: TaxRate::from(0.071),
-            ni_band_3_rate: TaxRate::from(0.1325),
-            personal_allowance_band: CashValue::from(12_571.0),
-            personal_allowance_taper_threshold_band: CashValue::from(100_000.0),
-            personal_allowance_taper_value: CashValue::from(2.0),
-            basic_income_top_band: CashValue::from(50_270.0),
-            higher_rate_top_band: CashValue::from(150_000.0),
-            starting_savings_allowance_band: CashValue::from(5_000.0),
-            self_employment_allowance_band: CashValue::from(1_000.0),
-            rental_allowance_band: CashValue::from(1_000.0),
-            dividend_allowance_band: CashValue::from(2_000.0),
-            capital_gains_allowance_band: CashValue::from(12_300.0),
-            ni_band_1_band: CashValue::from(823.01),
-            ni_band_2_band: CashValue::from(4_189.0),
-            basic_rate_savings_allowance_band: CashValue::from(1000.0),
-            higher_rate_savings_allowance_band: CashValue::from(500.0),
-        }
+        let tables = UKTaxTables::new(Residency::RestOfUK, FiscalDrag::default());
+        tables.config(&tables.latest_year())
     }
 }
 
 impl UKTaxConfig {
+    //Latest tax year with Scottish bands
+    pub fn scottish() -> Self {
+        let tables = UKTaxTables::new(Residency::Scotland, FiscalDrag::default());
+        tables.config(&tables.latest_year())
+    }
+
+    pub fn capital_gains_allowance(&self) -> CashValue {
+        self.capital_gains_allowance_band.clone()
+    }
+
+    pub fn personal_allowance(&self) -> CashValue {
+        self.personal_allowance_band.clone()
+    }
+
+    pub fn basic_income_rate(&self) -> TaxRate {
+        self.basic_income_rate
+    }
+
+    pub fn employer_ni_rate(&self) -> TaxRate {
+        self.ni_employer_rate
+    }
+
+    //Weekly child benefit for the number of children that are eligible
+    pub fn child_benefit_weekly(&self, children: usize) -> CashValue {
+        if children == 0 {
+            return CashValue::default();
+        }
+        let additional = (children - 1) as f64 * *self.child_benefit_additional_weekly;
+        CashValue::from(*self.child_benefit_eldest_weekly + additional)
+    }
+
+    //Benefit rates are uprated from the previous year's config, used when the thresholds are
+    //frozen. Inflation is the annual rate over the tax year that has ended.
+    fn with_benefits_from(&self, prev: &UKTaxConfig, inflation: &f64) -> UKTaxConfig {
+        let mut config = self.clone();
+        config.child_benefit_eldest_weekly =
+            CashValue::from(*prev.child_benefit_eldest_weekly * (1.0 + inflation));
+        config.child_benefit_additional_weekly =
+            CashValue::from(*prev.child_benefit_additional_weekly * (1.0 + inflation));
+        config
+    }
+
+    //Higher rate relief on relief at source contributions is given by extending the basic and
+    //higher rate bands by the gross contribution. Adjusted net income is also reduced so the
+    //personal allowance taper threshold moves by the same amount.
+    fn extend_bands(&self, contributions: &f64) -> UKTaxConfig {
+        let mut config = self.clone();
+        if *contributions > 0.0 {
+            config.basic_income_top_band =
+                CashValue::from(*self.basic_income_top_band + *contributions);
+            config.higher_rate_top_band =
+                CashValue::from(*self.higher_rate_top_band + *contributions);
+            config.personal_allowance_taper_threshold_band =
+                CashValue::from(*self.personal_allowance_taper_threshold_band + *contributions);
+            config.scottish_bands = self
+                .scottish_bands
+                .as_ref()
+                .map(|bands| bands.extend_bands(contributions));
+        }
+        config
+    }
+
     pub fn apply_inflation(&self, value: &f64) -> UKTaxConfig {
         UKTaxConfig {
             basic_income_rate: self.basic_income_rate,
@@ -176,13 +410,13 @@ impl UKTaxConfig {
             ni_band_1_rate: self.ni_band_1_rate,
             ni_band_2_rate: self.ni_band_2_rate,
             ni_band_3_rate: self.ni_band_3_rate,
+            ni_employer_rate: self.ni_employer_rate,
             personal_allowance_band: CashValue::from(*self.personal_allowance_band * (1.0 + value)),
             personal_allowance_taper_threshold_band: CashValue::from(
                 *self.personal_allowance_taper_threshold_band * (1.0 + value),
             ),
-            personal_allowance_taper_value: CashValue::from(
-                *self.personal_allowance_taper_value * (1.0 + value),
-            ),
+            //Ratio rather than a threshold so isn't uprated
+            personal_allowance_taper_value: self.personal_allowance_taper_value.clone(),
             basic_income_top_band: CashValue::from(*self.basic_income_top_band * (1.0 + value)),
             higher_rate_top_band: CashValue::from(*self.higher_rate_top_band * (1.0 + value)),
             starting_savings_allowance_band: CashValue::from(
@@ -204,13 +438,29 @@ impl UKTaxConfig {
             higher_rate_savings_allowance_band: CashValue::from(
                 *self.higher_rate_savings_allowance_band * (1.0 + value),
             ),
+            child_benefit_eldest_weekly: CashValue::from(
+                *self.child_benefit_eldest_weekly * (1.0 + value),
+            ),
+            child_benefit_additional_weekly: CashValue::from(
+                *self.child_benefit_additional_weekly * (1.0 + value),
+            ),
+            child_benefit_charge_band: CashValue::from(
+                *self.child_benefit_charge_band * (1.0 + value),
+            ),
+            child_benefit_charge_top_band: CashValue::from(
+                *self.child_benefit_charge_top_band * (1.0 + value),
+            ),
+            scottish_bands: self
+                .scottish_bands
+                .as_ref()
+                .map(|bands| bands.apply_inflation(value)),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{TaxPeriod, UKTaxConfig, UKTaxInput, NIC};
+    use super::{Residency, TaxPeriod, UKTaxConfig, UKTaxInput, NIC};
 
     #[test]
     fn test_that_uk_tax_flow_works() {
@@ -253,6 +503,260 @@ mod tests {
         assert!(*tax_paid / *tax_paid1 > 0.9);
     }
 
+    #[test]
+    fn test_that_capital_gains_under_allowance_are_not_taxed() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 30_000.0.into();
+        input.capital_gains = config.capital_gains_allowance();
+        let tax_paid = TaxPeriod::calc(&input, &config).total();
+
+        let mut input1 = UKTaxInput::default();
+        input1.non_paye_employment = 30_000.0.into();
+        let tax_paid1 = TaxPeriod::calc(&input1, &config).total();
+        assert!(*tax_paid == *tax_paid1);
+
+        let mut input2 = UKTaxInput::default();
+        input2.non_paye_employment = 30_000.0.into();
+        input2.capital_gains = (*config.capital_gains_allowance() + 1_000.0).into();
+        let tax_paid2 = TaxPeriod::calc(&input2, &config).total();
+        assert!(*tax_paid2 > *tax_paid1);
+    }
+
+    #[test]
+    fn test_that_residential_gains_use_residential_rate() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 100_000.0.into();
+        input.residential_capital_gains = 50_000.0.into();
+        let breakdown = TaxPeriod::calc(&input, &config).breakdown(2025);
+        let expected = (50_000.0 - *config.capital_gains_allowance())
+            * *config.higher_residential_capital_rate;
+        assert!((breakdown.capital_gains_tax - expected).abs() < 0.01);
+
+        //Exempt amount is used against residential gains first
+        input.capital_gains = 10_000.0.into();
+        let breakdown = TaxPeriod::calc(&input, &config).breakdown(2025);
+        let expected = expected + (10_000.0 * *config.higher_other_capital_rate);
+        assert!((breakdown.capital_gains_tax - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_that_relief_at_source_extends_basic_rate_band() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 60_000.0.into();
+        let no_contribution = TaxPeriod::calc(&input, &config).total();
+
+        //Gross contribution takes all income above the basic rate band out of higher rate
+        let mut input1 = UKTaxInput::default();
+        input1.non_paye_employment = 60_000.0.into();
+        input1.relief_at_source_contributions = 10_000.0.into();
+        let ras = TaxPeriod::calc(&input1, &config).total();
+
+        //Relief at source should give the same relief as net pay, less the basic rate relief
+        //that the provider adds to the pension
+        let mut input2 = UKTaxInput::default();
+        input2.non_paye_employment = 60_000.0.into();
+        input2.contributions = 10_000.0.into();
+        let net_pay = TaxPeriod::calc(&input2, &config).total();
+
+        assert!(*ras < *no_contribution);
+        let basic_relief = 10_000.0 * *config.basic_income_rate();
+        let diff = (*net_pay + basic_relief) - *ras;
+        assert!(diff > -10.0 && diff < 10.0);
+    }
+
+    #[test]
+    fn test_that_savings_income_is_taxed_above_the_savings_allowance() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 60_000.0.into();
+        let no_savings = TaxPeriod::calc(&input, &config).total();
+
+        //Higher rate taxpayers have a 500 allowance and pay 40% on the rest
+        input.savings = 10_000.0.into();
+        let breakdown = TaxPeriod::calc(&input, &config).breakdown(2025);
+        let expected = (10_000.0 - 500.0) * 0.4;
+        assert!((breakdown.balancing_payment - *no_savings - expected).abs() < 0.01);
+        assert!(breakdown.taxable_income == 70_000.0);
+        assert!(breakdown.allowances == 500.0);
+    }
+
+    #[test]
+    fn test_that_starting_rate_for_savings_is_reduced_by_non_savings_income() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        //2,000 of the 5,000 starting rate band is left, then 1,000 of personal savings allowance
+        input.pension = (12_570.0 + 3_000.0).into();
+        let no_savings = TaxPeriod::calc(&input, &config).total();
+
+        input.savings = 4_000.0.into();
+        let with_savings = TaxPeriod::calc(&input, &config).total();
+        let expected = (4_000.0 - 2_000.0 - 1_000.0) * 0.2;
+        assert!((*with_savings - *no_savings - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_that_rental_profit_is_taxed_above_the_property_allowance() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 60_000.0.into();
+        let no_rent = TaxPeriod::calc(&input, &config).total();
+
+        input.rental = 20_000.0.into();
+        let breakdown = TaxPeriod::calc(&input, &config).breakdown(2025);
+        let expected = (20_000.0 - 1_000.0) * 0.4;
+        assert!((breakdown.balancing_payment - *no_rent - expected).abs() < 0.01);
+        assert!(breakdown.taxable_income == 79_000.0);
+
+        //Rent under the allowance isn't taxed
+        input.rental = 800.0.into();
+        let with_rent = TaxPeriod::calc(&input, &config).total();
+        assert!(*with_rent == *no_rent);
+    }
+
+    #[test]
+    fn test_that_self_employment_profit_is_taxed_above_the_trading_allowance() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 60_000.0.into();
+        let no_profit = TaxPeriod::calc(&input, &config);
+
+        input.self_employment = 20_000.0.into();
+        let with_profit = TaxPeriod::calc(&input, &config);
+        let expected = (20_000.0 - 1_000.0) * 0.4;
+        assert!((*with_profit.income_tax() - *no_profit.income_tax() - expected).abs() < 0.01);
+        assert!(*with_profit.ni() == *no_profit.ni());
+    }
+
+    #[test]
+    fn test_that_scottish_bands_only_apply_to_non_savings_income() {
+        let config = UKTaxConfig::default();
+        let scottish_config = UKTaxConfig::scottish();
+
+        //Scottish higher rate starts below the rUK higher rate
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 60_000.0.into();
+        let ruk = TaxPeriod::calc(&input, &config).total();
+        let scottish = TaxPeriod::calc(&input, &scottish_config).total();
+        assert!(*scottish > *ruk);
+
+        //Dividends are taxed at the same rUK rate for both
+        let mut input1 = UKTaxInput::default();
+        input1.non_paye_employment = 60_000.0.into();
+        input1.dividend = 10_000.0.into();
+        let ruk1 = TaxPeriod::calc(&input1, &config).total();
+        let scottish1 = TaxPeriod::calc(&input1, &scottish_config).total();
+        let diff = (*scottish1 - *scottish) - (*ruk1 - *ruk);
+        assert!(diff > -1.0 && diff < 1.0);
+    }
+
+    #[test]
+    fn test_that_scottish_paye_matches_annual() {
+        let annual_wage = 60_000.0;
+        let config = UKTaxConfig::scottish();
+
+        let paye = TaxPeriod::paye(&(annual_wage / 12.0), &0.0, NIC::A, &config);
+        let annualised_paye_total = *paye.total() * 12.0;
+
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = annual_wage.into();
+        let tax_paid = TaxPeriod::calc(&input, &config).total();
+
+        let diff = *tax_paid - annualised_paye_total;
+        assert!(diff > -10.0 && diff < 10.0);
+    }
+
+    #[test]
+    fn test_that_breakdown_sums_to_balancing_payment() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 60_000.0.into();
+        input.paye_employment = 30_000.0.into();
+        input.paye_tax_paid = 3_500.0.into();
+        input.dividend = 10_000.0.into();
+        input.capital_gains = 20_000.0.into();
+
+        let breakdown = TaxPeriod::calc(&input, &config).breakdown(2030);
+        let sum = breakdown.income_tax_basic
+            + breakdown.income_tax_higher
+            + breakdown.income_tax_additional
+            + breakdown.ni_main
+            + breakdown.ni_upper
+            + breakdown.dividend_tax_basic
+            + breakdown.dividend_tax_higher
+            + breakdown.dividend_tax_additional
+            + breakdown.capital_gains_tax
+            + breakdown.annual_allowance_charge
+            + breakdown.child_benefit_charge
+            - breakdown.paye_tax_paid;
+        assert!((sum - breakdown.balancing_payment).abs() < 0.01);
+        assert!(breakdown.taxable_income == 90_000.0);
+        assert!(breakdown.dividend_tax_higher > 0.0);
+    }
+
+    #[test]
+    fn test_that_paye_income_tax_is_not_refunded_at_year_end() {
+        let config = UKTaxConfig::default();
+        let annual_wage = 50_000.0;
+        let paye = TaxPeriod::paye(&(annual_wage / 12.0), &0.0, NIC::A, &config);
+
+        //PAYE has collected the right income tax so nothing is due, excluding PAYE income from
+        //the annual calculation refunded all of the tax paid through PAYE
+        let mut input = UKTaxInput::default();
+        input.paye_employment = annual_wage.into();
+        input.paye_tax_paid = (*paye.income_tax() * 12.0).into();
+        input.paye_ni_paid = (*paye.ni() * 12.0).into();
+        let balance = TaxPeriod::calc(&input, &config).total();
+        assert!(*balance > -10.0 && *balance < 10.0);
+    }
+
+    #[test]
+    fn test_that_paye_income_moves_dividends_into_higher_rate() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.paye_employment = 60_000.0.into();
+        input.dividend = 10_000.0.into();
+
+        let breakdown = TaxPeriod::calc(&input, &config).breakdown(2030);
+        assert!(breakdown.dividend_tax_basic == 0.0);
+        assert!(breakdown.dividend_tax_higher > 0.0);
+    }
+
+    #[test]
+    fn test_that_dividend_higher_rate_band_starts_at_basic_rate_top() {
+        let config = UKTaxConfig::default();
+        let mut input = UKTaxInput::default();
+        input.non_paye_employment = 20_000.0.into();
+        input.dividend = 200_000.0.into();
+
+        //Income is below the basic rate top so the higher rate band is the full width, taking
+        //the higher rate band from income overlapped the basic rate band used by dividends
+        let breakdown = TaxPeriod::calc(&input, &config).breakdown(2030);
+        let width = *config.higher_rate_top_band - *config.basic_income_top_band;
+        let expected = width * *config.higher_dividend_rate;
+        assert!((breakdown.dividend_tax_higher - expected).abs() < 0.01);
+        assert!(breakdown.dividend_tax_additional > 0.0);
+    }
+
+    #[test]
+    fn test_that_tax_input_deserializes_with_defaults() {
+        let data = r#"
+          {
+              "paye_employment": 60000.0,
+              "contributions": 3000.0,
+              "ni": "A"
+          }"#;
+        let input: UKTaxInput = serde_json::from_str(data).unwrap();
+        assert!(*input.paye_employment == 60_000.0);
+        assert!(*input.dividend == 0.0);
+
+        let breakdown = TaxPeriod::calc_for_year(&input, 2024, Residency::RestOfUK);
+        assert!(breakdown.taxable_income == 57_000.0);
+        assert!(breakdown.income_tax_higher > 0.0);
+    }
+
     #[test]
     fn test_that_paye_income_calculates_correctly() {
         //This is very rough until we build out everything fully
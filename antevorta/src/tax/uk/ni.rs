@@ -59,13 +59,22 @@ impl NIB4 {
     }
 }
 
+//Main rate band and upper rate band
 #[derive(Debug)]
-pub struct NITaxOutput(CashValue);
+pub struct NITaxOutput(CashValue, CashValue);
 
 impl NITaxOutput {
-    pub fn total(&self) -> CashValue {
+    pub fn main(&self) -> CashValue {
         self.0.clone()
     }
+
+    pub fn upper(&self) -> CashValue {
+        self.1.clone()
+    }
+
+    pub fn total(&self) -> CashValue {
+        CashValue::from(*self.0 + *self.1)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
@@ -84,21 +93,21 @@ pub enum NIC {
     Z,
 }
 
-fn ni_calc(nic: &NIC, inc: &f64, is_paye: bool, config: &UKTaxConfig) -> CashValue {
+fn ni_calc(nic: &NIC, inc: &f64, is_paye: bool, config: &UKTaxConfig) -> NITaxOutput {
     match nic {
-        NIC::A | NIC::F | NIC::H | NIC::M | NIC::V => {
-            let sum = *NIB3::calc(inc, is_paye, config) + *NIB1::calc(inc, is_paye, config);
-            CashValue::from(sum)
-        }
-        NIC::B | NIC::I => {
-            let sum = *NIB2::calc(inc, is_paye, config) + *NIB1::calc(inc, is_paye, config);
-            CashValue::from(sum)
-        }
-        NIC::J | NIC::L | NIC::Z => {
-            let sum = *NIB4::calc(inc, is_paye, config) + *NIB1::calc(inc, is_paye, config);
-            CashValue::from(sum)
-        }
-        NIC::C | NIC::S => CashValue::from(0.0),
+        NIC::A | NIC::F | NIC::H | NIC::M | NIC::V => NITaxOutput(
+            NIB3::calc(inc, is_paye, config),
+            NIB1::calc(inc, is_paye, config),
+        ),
+        NIC::B | NIC::I => NITaxOutput(
+            NIB2::calc(inc, is_paye, config),
+            NIB1::calc(inc, is_paye, config),
+        ),
+        NIC::J | NIC::L | NIC::Z => NITaxOutput(
+            NIB4::calc(inc, is_paye, config),
+            NIB1::calc(inc, is_paye, config),
+        ),
+        NIC::C | NIC::S => NITaxOutput(CashValue::from(0.0), CashValue::from(0.0)),
     }
 }
 
@@ -106,12 +115,10 @@ impl NIC {
     pub fn calc(&self, period: &UKTaxInput, config: &UKTaxConfig) -> NITaxOutput {
         //We only take non_paye_employment income here because paye_employment has already paid NI
         let employment_income = &period.non_paye_employment;
-        let ni = ni_calc(self, &employment_income, false, config);
-        NITaxOutput(ni)
+        ni_calc(self, employment_income, false, config)
     }
 
     pub fn paye_calc(&self, pay: &f64, config: &UKTaxConfig) -> NITaxOutput {
-        let ni = ni_calc(self, pay, true, config);
-        NITaxOutput(ni)
+        ni_calc(self, pay, true, config)
     }
 }
@@ -0,0 +1,261 @@
+use alator::types::CashValue;
+use serde::Deserialize;
+
+use crate::tax::TaxRate;
+
+use super::{Residency, ScottishIncomeBands, UKTaxConfig};
+
+//Tax year is identified by the calendar year in which it starts, 2024 is 2024/25
+const UK_TAX_TABLES: &str = include_str!("tables.json");
+
+#[derive(Clone, Debug, Deserialize)]
+struct ScottishIncomeBandsTable {
+    starter_rate: f64,
+    basic_rate: f64,
+    intermediate_rate: f64,
+    higher_rate: f64,
+    advanced_rate: f64,
+    top_rate: f64,
+    starter_top_band: f64,
+    basic_top_band: f64,
+    intermediate_top_band: f64,
+    higher_top_band: f64,
+    advanced_top_band: f64,
+}
+
+impl From<&ScottishIncomeBandsTable> for ScottishIncomeBands {
+    fn from(table: &ScottishIncomeBandsTable) -> Self {
+        Self {
+            starter_rate: TaxRate::from(table.starter_rate),
+            basic_rate: TaxRate::from(table.basic_rate),
+            intermediate_rate: TaxRate::from(table.intermediate_rate),
+            higher_rate: TaxRate::from(table.higher_rate),
+            advanced_rate: TaxRate::from(table.advanced_rate),
+            top_rate: TaxRate::from(table.top_rate),
+            starter_top_band: CashValue::from(table.starter_top_band),
+            basic_top_band: CashValue::from(table.basic_top_band),
+            intermediate_top_band: CashValue::from(table.intermediate_top_band),
+            higher_top_band: CashValue::from(table.higher_top_band),
+            advanced_top_band: CashValue::from(table.advanced_top_band),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct UKTaxTable {
+    tax_year: i32,
+    basic_income_rate: f64,
+    higher_income_rate: f64,
+    additional_income_rate: f64,
+    basic_dividend_rate: f64,
+    higher_dividend_rate: f64,
+    additional_dividend_rate: f64,
+    basic_residential_capital_rate: f64,
+    higher_residential_capital_rate: f64,
+    basic_other_capital_rate: f64,
+    higher_other_capital_rate: f64,
+    ni_band_1_rate: f64,
+    ni_band_2_rate: f64,
+    ni_band_3_rate: f64,
+    ni_employer_rate: f64,
+    personal_allowance_band: f64,
+    personal_allowance_taper_threshold_band: f64,
+    personal_allowance_taper_value: f64,
+    basic_income_top_band: f64,
+    higher_rate_top_band: f64,
+    starting_savings_allowance_band: f64,
+    self_employment_allowance_band: f64,
+    rental_allowance_band: f64,
+    dividend_allowance_band: f64,
+    capital_gains_allowance_band: f64,
+    ni_band_1_band: f64,
+    ni_band_2_band: f64,
+    basic_rate_savings_allowance_band: f64,
+    higher_rate_savings_allowance_band: f64,
+    child_benefit_eldest_weekly: f64,
+    child_benefit_additional_weekly: f64,
+    child_benefit_charge_band: f64,
+    child_benefit_charge_top_band: f64,
+    scottish_bands: ScottishIncomeBandsTable,
+}
+
+impl UKTaxTable {
+    fn config(&self, residency: &Residency) -> UKTaxConfig {
+        let scottish_bands = match residency {
+            Residency::RestOfUK => None,
+            Residency::Scotland => Some(ScottishIncomeBands::from(&self.scottish_bands)),
+        };
+
+        UKTaxConfig {
+            basic_income_rate: TaxRate::from(self.basic_income_rate),
+            higher_income_rate: TaxRate::from(self.higher_income_rate),
+            additional_income_rate: TaxRate::from(self.additional_income_rate),
+            basic_dividend_rate: TaxRate::from(self.basic_dividend_rate),
+            higher_dividend_rate: TaxRate::from(self.higher_dividend_rate),
+            additional_dividend_rate: TaxRate::from(self.additional_dividend_rate),
+            basic_residential_capital_rate: TaxRate::from(self.basic_residential_capital_rate),
+            higher_residential_capital_rate: TaxRate::from(self.higher_residential_capital_rate),
+            basic_other_capital_rate: TaxRate::from(self.basic_other_capital_rate),
+            higher_other_capital_rate: TaxRate::from(self.higher_other_capital_rate),
+            ni_band_1_rate: TaxRate::from(self.ni_band_1_rate),
+            ni_band_2_rate: TaxRate::from(self.ni_band_2_rate),
+            ni_band_3_rate: TaxRate::from(self.ni_band_3_rate),
+            ni_employer_rate: TaxRate::from(self.ni_employer_rate),
+            personal_allowance_band: CashValue::from(self.personal_allowance_band),
+            personal_allowance_taper_threshold_band: CashValue::from(
+                self.personal_allowance_taper_threshold_band,
+            ),
+            personal_allowance_taper_value: CashValue::from(self.personal_allowance_taper_value),
+            basic_income_top_band: CashValue::from(self.basic_income_top_band),
+            higher_rate_top_band: CashValue::from(self.higher_rate_top_band),
+            starting_savings_allowance_band: CashValue::from(self.starting_savings_allowance_band),
+            self_employment_allowance_band: CashValue::from(self.self_employment_allowance_band),
+            rental_allowance_band: CashValue::from(self.rental_allowance_band),
+            dividend_allowance_band: CashValue::from(self.dividend_allowance_band),
+            capital_gains_allowance_band: CashValue::from(self.capital_gains_allowance_band),
+            ni_band_1_band: CashValue::from(self.ni_band_1_band),
+            ni_band_2_band: CashValue::from(self.ni_band_2_band),
+            basic_rate_savings_allowance_band: CashValue::from(
+                self.basic_rate_savings_allowance_band,
+            ),
+            higher_rate_savings_allowance_band: CashValue::from(
+                self.higher_rate_savings_allowance_band,
+            ),
+            child_benefit_eldest_weekly: CashValue::from(self.child_benefit_eldest_weekly),
+            child_benefit_additional_weekly: CashValue::from(self.child_benefit_additional_weekly),
+            child_benefit_charge_band: CashValue::from(self.child_benefit_charge_band),
+            child_benefit_charge_top_band: CashValue::from(self.child_benefit_charge_top_band),
+            scottish_bands,
+        }
+    }
+}
+
+//How thresholds move in tax years after the last table
+#[derive(Clone, Debug)]
+pub enum FiscalDrag {
+    //Thresholds are frozen until the tax year given, and then indexed to inflation
+    FreezeUntil(i32),
+    //Thresholds are indexed to inflation every year
+    Index,
+    //Thresholds are uprated by the given rates in each year after the last table, and frozen when
+    //the rates run out
+    Custom(Vec<f64>),
+}
+
+impl Default for FiscalDrag {
+    //Income tax thresholds are frozen until April 2028
+    fn default() -> Self {
+        FiscalDrag::FreezeUntil(2028)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UKTaxTables {
+    tables: Vec<UKTaxTable>,
+    residency: Residency,
+    fiscal_drag: FiscalDrag,
+}
+
+impl UKTaxTables {
+    pub fn latest_year(&self) -> i32 {
+        self.tables.last().unwrap().tax_year
+    }
+
+    //Years before the first table use the first table, years after the last table use the last
+    //table without uprating as there is no inflation path to uprate with
+    pub fn config(&self, tax_year: &i32) -> UKTaxConfig {
+        let mut table = self.tables.first().unwrap();
+        for t in &self.tables {
+            if t.tax_year <= *tax_year {
+                table = t;
+            }
+        }
+        table.config(&self.residency)
+    }
+
+    //Called at the start of each tax year with the config from the previous year, tables are used
+    //whilst they exist and the fiscal drag policy applies after
+    pub fn next(&self, curr: &UKTaxConfig, tax_year: &i32, inflation: &f64) -> UKTaxConfig {
+        if let Some(table) = self.tables.iter().find(|t| t.tax_year == *tax_year) {
+            return table.config(&self.residency);
+        }
+
+        let latest_year = self.latest_year();
+        if *tax_year < latest_year {
+            return self.config(tax_year);
+        }
+
+        let config = match &self.fiscal_drag {
+            FiscalDrag::FreezeUntil(year) => {
+                if tax_year < year {
+                    curr.clone()
+                } else {
+                    curr.apply_inflation(inflation)
+                }
+            }
+            FiscalDrag::Index => curr.apply_inflation(inflation),
+            FiscalDrag::Custom(rates) => {
+                let pos = (*tax_year - latest_year - 1) as usize;
+                match rates.get(pos) {
+                    Some(rate) => curr.apply_inflation(rate),
+                    None => curr.clone(),
+                }
+            }
+        };
+        //Benefit rates are uprated by inflation whatever happens to thresholds
+        config.with_benefits_from(curr, inflation)
+    }
+
+    pub fn new(residency: Residency, fiscal_drag: FiscalDrag) -> Self {
+        let mut tables: Vec<UKTaxTable> = serde_json::from_str(UK_TAX_TABLES).unwrap();
+        tables.sort_by_key(|t| t.tax_year);
+        Self {
+            tables,
+            residency,
+            fiscal_drag,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FiscalDrag, UKTaxTables};
+    use crate::tax::uk::Residency;
+
+    #[test]
+    fn test_that_tables_are_selected_by_year() {
+        let tables = UKTaxTables::new(Residency::RestOfUK, FiscalDrag::default());
+        assert!(*tables.config(&2022).capital_gains_allowance() == 12_300.0);
+        assert!(*tables.config(&2024).capital_gains_allowance() == 3_000.0);
+        //Years outside of the tables use the nearest table
+        assert!(*tables.config(&1970).capital_gains_allowance() == 12_300.0);
+        assert!(*tables.config(&2100).capital_gains_allowance() == 3_000.0);
+    }
+
+    #[test]
+    fn test_that_fiscal_drag_freezes_then_indexes() {
+        let tables = UKTaxTables::new(Residency::RestOfUK, FiscalDrag::FreezeUntil(2028));
+        let latest = tables.latest_year();
+        let config = tables.config(&latest);
+
+        let frozen = tables.next(&config, &2027, &0.1);
+        assert!(*frozen.capital_gains_allowance() == *config.capital_gains_allowance());
+
+        let indexed = tables.next(&frozen, &2028, &0.1);
+        assert!(*indexed.capital_gains_allowance() > *config.capital_gains_allowance());
+    }
+
+    #[test]
+    fn test_that_custom_fiscal_drag_freezes_when_rates_run_out() {
+        let tables = UKTaxTables::new(Residency::RestOfUK, FiscalDrag::Custom(vec![0.05]));
+        let latest = tables.latest_year();
+        let config = tables.config(&latest);
+
+        let uprated = tables.next(&config, &(latest + 1), &0.1);
+        let expected = *config.capital_gains_allowance() * 1.05;
+        assert!((*uprated.capital_gains_allowance() - expected).abs() < 0.01);
+
+        let frozen = tables.next(&uprated, &(latest + 2), &0.1);
+        assert!(*frozen.capital_gains_allowance() == *uprated.capital_gains_allowance());
+    }
+}
@@ -348,3 +348,107 @@ fn test_that_mortgage_can_build() {
     }
     assert!(*UKSimulationOutput::get_final_value(&sim) > 0.0);
 }
+
+#[test]
+fn test_that_bed_and_isa_can_build() {
+    let data = r#"
+        {
+            "starting_cash": 1000.0,
+            "nic": "A",
+            "lifetime_pension_contributions": 10.0,
+            "contribution_pct": 0.1,
+            "emergency_cash_min": 4000.0,
+            "bed_and_isa": true,
+            "bed_and_sipp": 1000.0,
+            "flows": [
+                {
+                    "flow_type": "Employment",
+                    "value": 4000.0,
+                    "schedule": {
+                        "schedule_type": "EndOfMonth"
+                    }
+                }
+            ],
+            "stacks": [
+                {
+                    "stack_type": "Isa",
+                    "value": 4000.0
+                },
+                {
+                    "stack_type": "Gia",
+                    "value": 40000.0
+                },
+                {
+                    "stack_type": "Sipp",
+                    "value": 4000.0
+                }
+            ]
+        }"#;
+    let (clock, strat, sim_data) = setup();
+    let mut sim = UKSimConfig::parse(data)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, sim_data);
+
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+    }
+    assert!(*UKSimulationOutput::get_final_value(&sim) > 0.0);
+}
+
+#[test]
+fn test_that_lisa_and_jisa_can_build() {
+    let data = r#"
+        {
+            "starting_cash": 1000.0,
+            "nic": "A",
+            "lifetime_pension_contributions": 10.0,
+            "contribution_pct": 0.1,
+            "emergency_cash_min": 4000.0,
+            "flows": [
+                {
+                    "flow_type": "Employment",
+                    "value": 4000.0,
+                    "schedule": {
+                        "schedule_type": "EndOfMonth"
+                    }
+                }
+            ],
+            "stacks": [
+                {
+                    "stack_type": "Isa",
+                    "value": 4000.0
+                },
+                {
+                    "stack_type": "Lisa",
+                    "value": 4000.0,
+                    "date_of_birth": -315619200,
+                    "first_home_purchase": 5000000
+                },
+                {
+                    "stack_type": "Jisa",
+                    "value": 0.0,
+                    "date_of_birth": 0,
+                    "contribution": 1000.0
+                },
+                {
+                    "stack_type": "Gia",
+                    "value": 4000.0
+                },
+                {
+                    "stack_type": "Sipp",
+                    "value": 4000.0
+                }
+            ]
+        }"#;
+    let (clock, strat, sim_data) = setup();
+    let mut sim = UKSimConfig::parse(data)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, sim_data);
+
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+    }
+    assert!(*UKSimulationOutput::get_final_value(&sim) > 0.0);
+}
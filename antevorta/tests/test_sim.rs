@@ -1,14 +1,22 @@
+use alator::broker::Quote;
 use alator::clock::ClockBuilder;
 use alator::exchange::DefaultExchangeBuilder;
+use alator::input::QuotesHashMap;
 use alator::sim::SimulatedBrokerBuilder;
+use alator::clock::Clock;
 use alator::types::PortfolioAllocation;
-use antevorta::input::build_hashmapsource_random;
+use antevorta::input::{
+    build_hashmapsource_random, daily_data_generator_static, HashMapSourceSim,
+    HashMapSourceSimBuilder, SimDataSource, VecSourceSim,
+};
 use antevorta::output::UKSimulationOutput;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use antevorta::config::uk::UKSimConfig;
 use antevorta::schedule::Schedule;
 use antevorta::strat::StaticInvestmentStrategy;
+use antevorta::tax::uk::{FiscalDrag, Residency, UKTaxTables};
 
 #[test]
 fn sim_test() {
@@ -81,3 +89,791 @@ fn sim_test() {
 
     let _perf = UKSimulationOutput::get_output(&sim);
 }
+
+fn strat<D: SimDataSource>(clock: Clock, src: D) -> StaticInvestmentStrategy<D> {
+    let mut target_weights = PortfolioAllocation::new();
+    target_weights.insert("ABC", 0.5);
+    target_weights.insert("BCD", 0.5);
+
+    let exchange = DefaultExchangeBuilder::new()
+        .with_clock(Rc::clone(&clock))
+        .with_data_source(src.clone())
+        .build();
+
+    let brkr = SimulatedBrokerBuilder::new()
+        .with_exchange(exchange)
+        .with_data(src)
+        .build();
+
+    StaticInvestmentStrategy::new(brkr, Schedule::EveryFriday, target_weights, clock)
+}
+
+#[test]
+fn test_that_vec_source_sim_matches_hashmap_source() {
+    let clock = ClockBuilder::with_length_in_days(1, 1000)
+        .with_frequency(&alator::types::Frequency::Daily)
+        .build();
+    let hashmap_src = build_hashmapsource_random(Rc::clone(&clock));
+    let vec_src = VecSourceSim::from(&hashmap_src);
+
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.05,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "flows": [
+            {
+                "flow_type": "Employment",
+                "value": 4000.0,
+                "schedule": {
+                    "schedule_type": "StartOfMonth"
+                }
+            },
+            {
+                "flow_type": "InflationLinkedExpense",
+                "value": 1500.0,
+                "schedule": {
+                    "schedule_type": "EndOfMonth"
+                }
+            }
+        ],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 10000.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 10000.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 10000.0
+            }
+        ]
+    }"#;
+    let config = UKSimConfig::parse(config).unwrap();
+    let hashmap_strat = strat(Rc::clone(&clock), hashmap_src.clone());
+    let vec_strat = strat(Rc::clone(&clock), vec_src.clone());
+    let mut hashmap_sim = config.create(Rc::clone(&clock), hashmap_strat, hashmap_src);
+    let mut vec_sim = config.create(Rc::clone(&clock), vec_strat, vec_src);
+
+    //Both simulations share the clock so run together
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        hashmap_sim.update();
+        vec_sim.update();
+    }
+
+    let hashmap_perf = UKSimulationOutput::get_output(&hashmap_sim);
+    let vec_perf = UKSimulationOutput::get_output(&vec_sim);
+    assert!(!vec_perf.values.is_empty());
+    for (a, b) in hashmap_perf.values.iter().zip(vec_perf.values.iter()) {
+        assert!((a - b).abs() < 1e-6);
+    }
+    assert!((*hashmap_sim.get_total_value() - *vec_sim.get_total_value()).abs() < 1e-6);
+}
+
+fn flat_src(clock: &Clock) -> HashMapSourceSim {
+    flat_src_with_inflation(clock, 0.0)
+}
+
+fn flat_src_with_inflation(clock: &Clock, inflation: f64) -> HashMapSourceSim {
+    let mut quotes: QuotesHashMap = HashMap::new();
+    for date in clock.borrow().peek() {
+        let abc = Quote::new(100.0, 100.0, date.clone(), "ABC");
+        let bcd = Quote::new(100.0, 100.0, date.clone(), "BCD");
+        quotes.insert(date, vec![abc, bcd]);
+    }
+    HashMapSourceSimBuilder::start()
+        .with_clock(Rc::clone(clock))
+        .with_rates(daily_data_generator_static(0.0, Rc::clone(clock)))
+        .with_inflation(daily_data_generator_static(inflation, Rc::clone(clock)))
+        .with_house_prices(daily_data_generator_static(0.0, Rc::clone(clock)))
+        .with_quotes(quotes)
+        .build()
+}
+
+#[test]
+fn test_that_expenses_sell_investments_and_ruin_when_they_run_out() {
+    let config = |expense: f64| {
+        format!(
+            r#"{{
+                "starting_cash": 1000.0,
+                "nic": "A",
+                "contribution_pct": 0.0,
+                "emergency_cash_min": 1000.0,
+                "lifetime_pension_contributions": 0.0,
+                "flows": [
+                    {{
+                        "flow_type": "Expense",
+                        "value": {expense},
+                        "schedule": {{
+                            "schedule_type": "EndOfMonth"
+                        }}
+                    }}
+                ],
+                "stacks": [
+                    {{
+                        "stack_type": "Gia",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Isa",
+                        "value": 10000.0
+                    }},
+                    {{
+                        "stack_type": "Sipp",
+                        "value": 0.0
+                    }}
+                ]
+            }}"#
+        )
+    };
+
+    //Prices are flat so the only change in value comes from the expense
+    let run = |expense: f64| {
+        let clock = ClockBuilder::with_length_in_days(1, 1000)
+            .with_frequency(&alator::types::Frequency::Daily)
+            .build();
+        let src = flat_src(&clock);
+        let strat = strat(Rc::clone(&clock), src.clone());
+        let mut sim = UKSimConfig::parse(&config(expense))
+            .unwrap()
+            .create(Rc::clone(&clock), strat, src);
+        while clock.borrow().has_next() {
+            clock.borrow_mut().tick();
+            sim.update();
+        }
+        (*sim.get_total_value(), UKSimulationOutput::get_output(&sim))
+    };
+
+    //Bank only holds the emergency fund, so after five months every payment comes out of the ISA
+    let (value, affordable) = run(200.0);
+    assert!(affordable.ruin_date.is_none());
+    assert!(value < 11000.0 - (30.0 * 200.0));
+
+    let (value, unaffordable) = run(2000.0);
+    assert!(unaffordable.ruin_date.is_some());
+    assert!(value == 0.0);
+}
+
+#[test]
+fn test_that_lisa_is_sold_after_isa_and_gia_before_ruin() {
+    //Owner is 30 so the LISA pays out 7500 after the penalty
+    let config = |expense: f64| {
+        format!(
+            r#"{{
+                "starting_cash": 1000.0,
+                "nic": "A",
+                "contribution_pct": 0.0,
+                "emergency_cash_min": 1000.0,
+                "lifetime_pension_contributions": 0.0,
+                "flows": [
+                    {{
+                        "flow_type": "Expense",
+                        "value": {expense},
+                        "schedule": {{
+                            "schedule_type": "EndOfMonth"
+                        }}
+                    }}
+                ],
+                "stacks": [
+                    {{
+                        "stack_type": "Gia",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Isa",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Sipp",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Lisa",
+                        "value": 10000.0,
+                        "date_of_birth": -946771200
+                    }}
+                ]
+            }}"#
+        )
+    };
+
+    let run = |expense: f64| {
+        let clock = ClockBuilder::with_length_in_days(1, 1000)
+            .with_frequency(&alator::types::Frequency::Daily)
+            .build();
+        let src = flat_src(&clock);
+        let strat = strat(Rc::clone(&clock), src.clone());
+        let mut sim = UKSimConfig::parse(&config(expense))
+            .unwrap()
+            .create(Rc::clone(&clock), strat, src);
+        while clock.borrow().has_next() {
+            clock.borrow_mut().tick();
+            sim.update();
+        }
+        UKSimulationOutput::get_output(&sim)
+    };
+
+    //About 5600 is paid from the LISA after the bank runs out
+    assert!(run(200.0).ruin_date.is_none());
+    //Would need about 8900 from the LISA
+    assert!(run(300.0).ruin_date.is_some());
+}
+
+
+#[test]
+fn test_that_lisa_is_spent_on_the_first_home() {
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.0,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "flows": [],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Lisa",
+                "value": 10000.0,
+                "date_of_birth": -946771200,
+                "first_home_purchase": 864000
+            }
+        ]
+    }"#;
+
+    let clock = ClockBuilder::with_length_in_days(1, 100)
+        .with_frequency(&alator::types::Frequency::Daily)
+        .build();
+    let src = flat_src(&clock);
+    let strat = strat(Rc::clone(&clock), src.clone());
+    let mut sim = UKSimConfig::parse(config)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, src);
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+    }
+    let output = UKSimulationOutput::get_output(&sim);
+
+    //Whole balance is withdrawn without the penalty and none of it is left to invest
+    assert!((output.expense.iter().sum::<f64>() - 10_000.0).abs() < 0.01);
+    assert!((*sim.get_total_value() - 1_000.0).abs() < 0.01);
+}
+#[test]
+fn test_that_salary_sacrifice_lowers_paye_and_passes_on_employer_ni() {
+    let config = |sacrifice: bool, pass_on: bool, relief: &str| {
+        format!(
+            r#"{{
+                "starting_cash": 1000.0,
+                "nic": "A",
+                "contribution_pct": 0.1,
+                "emergency_cash_min": 1000.0,
+                "lifetime_pension_contributions": 0.0,
+                "flows": [
+                    {{
+                        "flow_type": "EmploymentPAYE",
+                        "value": 4000.0,
+                        "schedule": {{
+                            "schedule_type": "StartOfMonth"
+                        }},
+                        "salary_sacrifice": {sacrifice},
+                        "pass_on_ni_saving": {pass_on}
+                    }}
+                ],
+                "stacks": [
+                    {{
+                        "stack_type": "Gia",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Isa",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Sipp",
+                        "value": 0.0,
+                        "relief_method": "{relief}"
+                    }}
+                ]
+            }}"#
+        )
+    };
+
+    let run = |sacrifice: bool, pass_on: bool, relief: &str| {
+        let clock = ClockBuilder::with_length_in_days(1, 500)
+            .with_frequency(&alator::types::Frequency::Daily)
+            .build();
+        let src = flat_src(&clock);
+        let strat = strat(Rc::clone(&clock), src.clone());
+        let mut sim = UKSimConfig::parse(&config(sacrifice, pass_on, relief))
+            .unwrap()
+            .create(Rc::clone(&clock), strat, src);
+        while clock.borrow().has_next() {
+            clock.borrow_mut().tick();
+            sim.update();
+        }
+        UKSimulationOutput::get_output(&sim)
+    };
+
+    //Second tax year is the first full year of pay
+    let net_pay = run(false, false, "NetPay");
+    let relief_at_source = run(false, false, "ReliefAtSource");
+    let sacrifice = run(true, false, "NetPay");
+    let passed_on = run(true, true, "NetPay");
+
+    //Relief at source is taken from pay after tax, net pay only removes the contribution from
+    //income tax, sacrifice removes it from both income tax and NI
+    let (ras, net, sac) = (
+        &relief_at_source.tax_breakdown[1],
+        &net_pay.tax_breakdown[1],
+        &sacrifice.tax_breakdown[1],
+    );
+    assert!(sac.paye_tax_paid < ras.paye_tax_paid);
+    assert!((sac.paye_tax_paid - net.paye_tax_paid).abs() < 0.01);
+    assert!(sac.paye_ni_paid < net.paye_ni_paid);
+    assert!(sac.paye_ni_paid < ras.paye_ni_paid);
+
+    //Employer adds their NI saving on the twelve sacrificed contributions, 1970 uses the earliest
+    //tax table
+    let tables = UKTaxTables::new(Residency::RestOfUK, FiscalDrag::default());
+    let saving = 12.0 * 400.0 * *tables.config(&1970).employer_ni_rate();
+    let diff = passed_on.employer_contributions[1] - sacrifice.employer_contributions[1];
+    assert!((diff - saving).abs() < 0.01);
+    assert!(passed_on.sipp_contributions[1] == sacrifice.sipp_contributions[1]);
+}
+
+#[test]
+fn test_that_rpi_annuity_is_priced_with_annual_inflation() {
+    //Purchased at 65 on 1971-06-01
+    let config = |indexation: &str| {
+        format!(
+            r#"{{
+                "starting_cash": 1000.0,
+                "nic": "A",
+                "contribution_pct": 0.0,
+                "emergency_cash_min": 1000.0,
+                "lifetime_pension_contributions": 0.0,
+                "flows": [],
+                "stacks": [
+                    {{
+                        "stack_type": "Gia",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Isa",
+                        "value": 0.0
+                    }},
+                    {{
+                        "stack_type": "Sipp",
+                        "value": 100000.0
+                    }}
+                ],
+                "mortality": {{
+                    "date_of_birth": -2006661600,
+                    "sex": "Male"
+                }},
+                "annuity": {{
+                    "age": 65,
+                    "pct": 1.0,
+                    "indexation": "{indexation}"
+                }}
+            }}"#
+        )
+    };
+
+    let run = |indexation: &str| {
+        let clock = ClockBuilder::with_length_in_days(1, 1000)
+            .with_frequency(&alator::types::Frequency::Daily)
+            .build();
+        let src = flat_src_with_inflation(&clock, 0.05);
+        let strat = strat(Rc::clone(&clock), src.clone());
+        let mut sim = UKSimConfig::parse(&config(indexation))
+            .unwrap()
+            .create(Rc::clone(&clock), strat, src);
+        while clock.borrow().has_next() {
+            clock.borrow_mut().tick();
+            sim.update();
+        }
+        UKSimulationOutput::get_output(&sim)
+    };
+
+    //Rates are zero so the RPI annuity discounts at about -5%, the daily inflation rate would
+    //price it almost the same as the level annuity
+    let level = run("Level");
+    let rpi = run("RPI");
+    let level_income = level.gross_income[2];
+    let rpi_income = rpi.gross_income[2];
+    assert!(level_income > 0.0);
+    assert!(rpi_income < level_income * 0.8);
+}
+
+#[test]
+fn test_that_rpi_annuity_is_uprated_by_annual_inflation() {
+    //Purchased at 65 on 1971-03-25 so each tax year holds a full year of payments at one rate
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.0,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "flows": [],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 100000.0
+            }
+        ],
+        "mortality": {
+            "date_of_birth": -2012536800,
+            "sex": "Male"
+        },
+        "annuity": {
+            "age": 65,
+            "pct": 1.0,
+            "indexation": "RPI"
+        }
+    }"#;
+
+    let clock = ClockBuilder::with_length_in_days(1, 1300)
+        .with_frequency(&alator::types::Frequency::Daily)
+        .build();
+    let src = flat_src_with_inflation(&clock, 0.05);
+    let strat = strat(Rc::clone(&clock), src.clone());
+    let mut sim = UKSimConfig::parse(config)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, src);
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+    }
+    let output = UKSimulationOutput::get_output(&sim);
+
+    //Payments after the first anniversary are 5% higher, the daily rate would leave them level
+    let first_year = output.gross_income[2];
+    let second_year = output.gross_income[3];
+    assert!(first_year > 0.0);
+    assert!((second_year / first_year - 1.05).abs() < 0.005);
+}
+
+#[test]
+fn test_that_indexed_personal_allowance_rises_by_annual_inflation() {
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.0,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "fiscal_drag": {
+            "policy_type": "Index"
+        },
+        "flows": [],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 0.0
+            }
+        ]
+    }"#;
+
+    //Starts on 2026-01-01 after the last tax table
+    let clock = ClockBuilder::with_length_in_days(1_767_225_600, 600)
+        .with_frequency(&alator::types::Frequency::Daily)
+        .build();
+    let src = flat_src_with_inflation(&clock, 0.05);
+    let strat = strat(Rc::clone(&clock), src.clone());
+    let mut sim = UKSimConfig::parse(config)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, src);
+    let mut allowances = vec![*sim.tax_config.personal_allowance()];
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+        let allowance = *sim.tax_config.personal_allowance();
+        if allowance != *allowances.last().unwrap() {
+            allowances.push(allowance);
+        }
+    }
+
+    //The first uprate only has a few months of inflation, the second has a full year
+    assert!(allowances.len() == 3);
+    assert!((allowances[2] / allowances[1] - 1.05).abs() < 0.001);
+}
+
+#[test]
+fn test_that_frozen_thresholds_still_uprate_child_benefit_by_annual_inflation() {
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.0,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "flows": [],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 0.0
+            }
+        ]
+    }"#;
+
+    //Starts on 2026-01-01 after the last tax table, thresholds are frozen until 2028
+    let clock = ClockBuilder::with_length_in_days(1_767_225_600, 600)
+        .with_frequency(&alator::types::Frequency::Daily)
+        .build();
+    let src = flat_src_with_inflation(&clock, 0.05);
+    let strat = strat(Rc::clone(&clock), src.clone());
+    let mut sim = UKSimConfig::parse(config)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, src);
+    let allowance = *sim.tax_config.personal_allowance();
+    let mut rates = vec![*sim.tax_config.child_benefit_weekly(1)];
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+        let rate = *sim.tax_config.child_benefit_weekly(1);
+        if rate != *rates.last().unwrap() {
+            rates.push(rate);
+        }
+    }
+
+    //The first uprate only has a few months of inflation, the second has a full year
+    assert!(*sim.tax_config.personal_allowance() == allowance);
+    assert!(rates.len() == 3);
+    assert!((rates[2] / rates[1] - 1.05).abs() < 0.001);
+}
+
+#[test]
+fn test_that_defined_benefit_is_revalued_by_annual_inflation_up_to_the_cap() {
+    //Retired at the start, paying 1000 a month before revaluation
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.0,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "flows": [
+            {
+                "flow_type": "DefinedBenefit",
+                "value": 0.0,
+                "schedule": {
+                    "schedule_type": "StartOfMonth"
+                },
+                "accrued": 12000.0,
+                "normal_pension_date": 1,
+                "revaluation_cap": 0.05
+            }
+        ],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 0.0
+            }
+        ]
+    }"#;
+
+    let run = |inflation: f64| {
+        let clock = ClockBuilder::with_length_in_days(1, 1300)
+            .with_frequency(&alator::types::Frequency::Daily)
+            .build();
+        let src = flat_src_with_inflation(&clock, inflation);
+        let strat = strat(Rc::clone(&clock), src.clone());
+        let mut sim = UKSimConfig::parse(config)
+            .unwrap()
+            .create(Rc::clone(&clock), strat, src);
+        while clock.borrow().has_next() {
+            clock.borrow_mut().tick();
+            sim.update();
+        }
+        let output = UKSimulationOutput::get_output(&sim);
+        output.gross_income[3] / output.gross_income[2]
+    };
+
+    assert!((run(0.03) - 1.03).abs() < 0.003);
+    assert!((run(0.08) - 1.05).abs() < 0.003);
+}
+
+#[test]
+fn test_that_mortgage_is_taken_from_property_value_and_estate() {
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.0,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "flows": [],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 50000.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Property",
+                "value": 300000.0,
+                "main_residence": true
+            },
+            {
+                "stack_type": "Mortgage",
+                "value": 200000.0,
+                "rate": 0.0,
+                "term": 25,
+                "fix_length": 5
+            }
+        ]
+    }"#;
+
+    let clock = ClockBuilder::with_length_in_days(1, 1000)
+        .with_frequency(&alator::types::Frequency::Daily)
+        .build();
+    let src = flat_src(&clock);
+    let strat = strat(Rc::clone(&clock), src.clone());
+    let mut sim = UKSimConfig::parse(config)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, src);
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+    }
+
+    //Prices and rates are flat so payments move cash into equity without changing the total
+    let balance = *sim.mortgages[0].balance();
+    assert!(balance < 200000.0 && balance > 150000.0);
+    assert!((*sim.get_total_value() - 151000.0).abs() < 0.01);
+
+    let output = UKSimulationOutput::get_output(&sim);
+    assert!(output.property_value.iter().all(|v| *v < 300000.0 - 150000.0));
+
+    let estate = sim.estate();
+    assert!((estate.residence - (300000.0 - balance)).abs() < 0.01);
+}
+
+#[test]
+fn test_that_bed_and_isa_crystallises_gains_up_to_the_exempt_amount() {
+    let config = r#"{
+        "starting_cash": 1000.0,
+        "nic": "A",
+        "contribution_pct": 0.0,
+        "emergency_cash_min": 1000.0,
+        "lifetime_pension_contributions": 0.0,
+        "bed_and_isa": true,
+        "flows": [],
+        "stacks": [
+            {
+                "stack_type": "Gia",
+                "value": 10000.0
+            },
+            {
+                "stack_type": "Isa",
+                "value": 0.0
+            },
+            {
+                "stack_type": "Sipp",
+                "value": 0.0
+            }
+        ]
+    }"#;
+
+    //2024-03-28 to 2024-04-04, prices double on 2024-04-01
+    let clock = ClockBuilder::with_length_in_days(1_711_584_000, 7)
+        .with_frequency(&alator::types::Frequency::Daily)
+        .build();
+    let mut quotes: QuotesHashMap = HashMap::new();
+    for date in clock.borrow().peek() {
+        let price = if *date < 1_711_929_600 { 100.0 } else { 200.0 };
+        let abc = Quote::new(price, price, date.clone(), "ABC");
+        let bcd = Quote::new(price, price, date.clone(), "BCD");
+        quotes.insert(date, vec![abc, bcd]);
+    }
+    let src = HashMapSourceSimBuilder::start()
+        .with_clock(Rc::clone(&clock))
+        .with_rates(daily_data_generator_static(0.0, Rc::clone(&clock)))
+        .with_inflation(daily_data_generator_static(0.0, Rc::clone(&clock)))
+        .with_house_prices(daily_data_generator_static(0.0, Rc::clone(&clock)))
+        .with_quotes(quotes)
+        .build();
+
+    //A fifth of each account is left in cash
+    let mut target_weights = PortfolioAllocation::new();
+    target_weights.insert("ABC", 0.4);
+    target_weights.insert("BCD", 0.4);
+    let exchange = DefaultExchangeBuilder::new()
+        .with_clock(Rc::clone(&clock))
+        .with_data_source(src.clone())
+        .build();
+    let brkr = SimulatedBrokerBuilder::new()
+        .with_exchange(exchange)
+        .with_data(src.clone())
+        .build();
+    let strat = StaticInvestmentStrategy::new(
+        brkr,
+        Schedule::EveryFriday,
+        target_weights,
+        Rc::clone(&clock),
+    );
+
+    let mut sim = UKSimConfig::parse(config)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, src);
+    while clock.borrow().has_next() {
+        clock.borrow_mut().tick();
+        sim.update();
+    }
+
+    //GIA holds 16,000 of holdings with a gain of 8,000 and 2,000 of cash. Cash has no gain so it
+    //is moved along with 6,000 of holdings, which crystallises the 3,000 exempt amount.
+    assert!((*sim.isa.liquidation_value() - 8_000.0).abs() < 1.0);
+}
@@ -0,0 +1,234 @@
+/*
+Runs the same config and data with a daily and a monthly clock. Trades and events happen on
+different days in each mode so the outputs are only expected to be close.
+*/
+
+use alator::broker::Quote;
+use alator::clock::Clock;
+use alator::exchange::DefaultExchangeBuilder;
+use alator::input::QuotesHashMap;
+use alator::sim::SimulatedBrokerBuilder;
+use alator::types::PortfolioAllocation;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use antevorta::config::uk::UKSimConfig;
+use antevorta::input::{
+    build_hashmapsource_for_clock, daily_data_generator_static, HashMapSourceSim,
+    HashMapSourceSimBuilder,
+};
+use antevorta::output::UKSimulationOutput;
+use antevorta::schedule::{build_clock, Schedule, Step};
+use antevorta::strat::StaticInvestmentStrategy;
+
+//2020-01-01, runs for ten years
+const START_DATE: i64 = 1_577_836_800;
+const LENGTH_IN_DAYS: i64 = 365 * 10;
+
+//Paid on the same days in both modes
+const FLOWS: &str = r#"
+        {
+            "flow_type": "EmploymentPAYE",
+            "value": 5000.0,
+            "schedule": {
+                "schedule_type": "StartOfMonth"
+            }
+        },
+        {
+            "flow_type": "InflationLinkedExpense",
+            "value": 2000.0,
+            "schedule": {
+                "schedule_type": "EndOfMonth"
+            }
+        },
+        {
+            "flow_type": "Rental",
+            "value": 800.0,
+            "property": 0,
+            "schedule": {
+                "schedule_type": "StartOfMonth"
+            }
+        }"#;
+
+//Daily flows are paid on each day that a monthly tick covers and static growth compounds daily
+const DAILY_FLOWS: &str = r#"
+        {
+            "flow_type": "EmploymentStaticGrowth",
+            "value": 3000.0,
+            "static_growth": 0.0001,
+            "schedule": {
+                "schedule_type": "StartOfMonth"
+            }
+        },
+        {
+            "flow_type": "Expense",
+            "value": 20.0,
+            "schedule": {
+                "schedule_type": "EveryDay"
+            }
+        }"#;
+
+fn config(step: &str, flows: &str) -> String {
+    format!(
+        r#"{{
+            "starting_cash": 10000.0,
+            "nic": "A",
+            "contribution_pct": 0.05,
+            "emergency_cash_min": 5000.0,
+            "lifetime_pension_contributions": 0.0,
+            "step": "{step}",
+            "flows": [{flows}
+            ],
+            "stacks": [
+                {{
+                    "stack_type": "Gia",
+                    "value": 20000.0
+                }},
+                {{
+                    "stack_type": "Isa",
+                    "value": 20000.0
+                }},
+                {{
+                    "stack_type": "Sipp",
+                    "value": 20000.0
+                }},
+                {{
+                    "stack_type": "Property",
+                    "value": 200000.0
+                }}
+            ]
+        }}"#
+    )
+}
+
+//Prices grow smoothly so that differences come from the step rather than the path
+fn daily_source(clock: Clock) -> HashMapSourceSim {
+    let daily_return = (1.05_f64).powf(1.0 / 365.0) - 1.0;
+    let mut quotes: QuotesHashMap = HashMap::new();
+    let mut price = 100.0;
+    for date in clock.borrow().peek() {
+        let q_abc = Quote::new(price, price, date.clone(), "ABC");
+        let q_bcd = Quote::new(price, price, date.clone(), "BCD");
+        quotes.insert(date, vec![q_abc, q_bcd]);
+        price *= 1.0 + daily_return;
+    }
+
+    HashMapSourceSimBuilder::start()
+        .with_clock(Rc::clone(&clock))
+        .with_quotes(quotes)
+        .with_inflation(daily_data_generator_static(0.02, Rc::clone(&clock)))
+        .with_rates(daily_data_generator_static(0.01, Rc::clone(&clock)))
+        .with_house_prices(daily_data_generator_static(0.03, Rc::clone(&clock)))
+        .build()
+}
+
+fn run(
+    clock: Clock,
+    src: HashMapSourceSim,
+    step: Step,
+    config: &str,
+) -> (f64, UKSimulationOutput) {
+    let mut weights = PortfolioAllocation::new();
+    weights.insert("ABC", 0.5);
+    weights.insert("BCD", 0.5);
+
+    let exchange = DefaultExchangeBuilder::new()
+        .with_clock(Rc::clone(&clock))
+        .with_data_source(src.clone())
+        .build();
+
+    let brkr = SimulatedBrokerBuilder::new()
+        .with_exchange(exchange)
+        .with_data(src.clone())
+        .build();
+
+    let schedule = Schedule::EveryFriday.for_step(&step);
+    let strat = StaticInvestmentStrategy::new(brkr, schedule, weights, Rc::clone(&clock));
+    let mut sim = UKSimConfig::parse(config)
+        .unwrap()
+        .create(Rc::clone(&clock), strat, src);
+
+    while clock.borrow().has_next() {
+        sim.update();
+        clock.borrow_mut().tick();
+    }
+    (*sim.get_total_value(), UKSimulationOutput::get_output(&sim))
+}
+
+fn relative_diff(a: f64, b: f64) -> f64 {
+    (a - b).abs() / a.abs().max(b.abs())
+}
+
+//Runs the flows with a daily and a monthly clock, returns the total value and output of each
+fn run_both(flows: &str) -> ((f64, UKSimulationOutput), (f64, UKSimulationOutput)) {
+    let daily_clock = build_clock(START_DATE, LENGTH_IN_DAYS, &Step::Daily);
+    let daily_src = daily_source(Rc::clone(&daily_clock));
+    let monthly_clock = build_clock(START_DATE, LENGTH_IN_DAYS, &Step::Monthly);
+    let monthly_src = build_hashmapsource_for_clock(&daily_src, Rc::clone(&monthly_clock));
+    assert!(monthly_clock.borrow().peek().len() == 120);
+
+    let daily = run(daily_clock, daily_src, Step::Daily, &config("Daily", flows));
+    let monthly = run(monthly_clock, monthly_src, Step::Monthly, &config("Monthly", flows));
+    (daily, monthly)
+}
+
+fn sum(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>()
+}
+
+#[test]
+fn test_that_monthly_step_matches_daily_step() {
+    let ((daily_value, daily), (monthly_value, monthly)) = run_both(FLOWS);
+
+    assert!(daily.ruin_date.is_none() && monthly.ruin_date.is_none());
+    //The last monthly tick isn't run so the monthly value misses a month of growth
+    assert!(relative_diff(daily_value, monthly_value) < 0.015);
+
+    assert!(relative_diff(sum(&daily.gross_income), sum(&monthly.gross_income)) < 0.01);
+    assert!(relative_diff(sum(&daily.expense), sum(&monthly.expense)) < 0.01);
+    assert!(relative_diff(sum(&daily.tax_paid), sum(&monthly.tax_paid)) < 0.02);
+    let daily_property = *daily.property_value.last().unwrap();
+    let monthly_property = *monthly.property_value.last().unwrap();
+    assert!(relative_diff(daily_property, monthly_property) < 0.001);
+}
+
+#[test]
+fn test_that_monthly_step_scales_daily_flows() {
+    let ((_, daily), (_, monthly)) = run_both(DAILY_FLOWS);
+
+    //Expense is paid on every day in both modes, the first full tax year has 365 days
+    assert!(relative_diff(sum(&daily.expense), sum(&monthly.expense)) < 0.001);
+    assert!(monthly.expense[1] == 20.0 * 365.0);
+
+    //Pay grows at the same daily rate, so income in the tax year from April 2027 matches
+    let last_year = |output: &UKSimulationOutput| output.gross_income[8];
+    assert!(last_year(&monthly) > 36_000.0 * 1.0001_f64.powi(365 * 7));
+    assert!(relative_diff(last_year(&daily), last_year(&monthly)) < 0.01);
+    assert!(relative_diff(sum(&daily.gross_income), sum(&monthly.gross_income)) < 0.01);
+}
+
+#[test]
+fn test_that_tax_year_starts_after_annual_tax() {
+    //Annual tax and the tax year start both fall on the April tick with a monthly clock, taxes
+    //are paid first so the bed-and-SIPP contribution is counted in the new tax year
+    let with_bed_and_sipp = |step: &str| {
+        config(step, "").replace(r#""step""#, r#""bed_and_sipp": 1000.0, "step""#)
+    };
+    let daily_clock = build_clock(START_DATE, LENGTH_IN_DAYS, &Step::Daily);
+    let daily_src = daily_source(Rc::clone(&daily_clock));
+    let monthly_clock = build_clock(START_DATE, LENGTH_IN_DAYS, &Step::Monthly);
+    let monthly_src = build_hashmapsource_for_clock(&daily_src, Rc::clone(&monthly_clock));
+    let (_, daily) = run(daily_clock, daily_src, Step::Daily, &with_bed_and_sipp("Daily"));
+    let (_, monthly) = run(
+        monthly_clock,
+        monthly_src,
+        Step::Monthly,
+        &with_bed_and_sipp("Monthly"),
+    );
+
+    //Tax year ending April 2020 has no contributions, each later year has one at basic rate
+    for output in [daily, monthly] {
+        assert!(output.sipp_contributions[0] == 0.0);
+        assert!(output.sipp_contributions[1..].iter().all(|v| (v - 1250.0).abs() < 1e-6));
+    }
+}
@@ -0,0 +1,42 @@
+import csv
+import json
+
+#GDP-weighted inflation and short rate across countries with data in each year, weights are
+#rescaled over the countries with data. Inflation is calculated within each country so the first
+#year for each country is dropped.
+weights = {}
+inflation = {}
+rates = {}
+last_cpi = {}
+with open('./jst.txt') as f:
+    reader = csv.DictReader(f, delimiter='\t')
+    for row in reader:
+        country = row['country']
+        cpi = float(row['cpi']) if row['cpi'] else None
+        prev = last_cpi.get(country)
+        last_cpi[country] = cpi
+
+        if cpi is None or prev is None or not row['bill_rate']:
+            continue
+        if not row['rgdpmad'] or not row['pop']:
+            continue
+
+        year = int(row['year'])
+        gdp = float(row['rgdpmad']) * float(row['pop'])
+        weights[year] = weights.get(year, 0.0) + gdp
+        inflation[year] = inflation.get(year, 0.0) + (((cpi / prev) - 1) * gdp)
+        rates[year] = rates.get(year, 0.0) + (float(row['bill_rate']) * gdp)
+
+rets = []
+for year in sorted(rates.keys()):
+    if year == 1923:
+        continue
+    data = {
+        "year": year,
+        "inflation": inflation[year] / weights[year],
+        "rate": rates[year] / weights[year],
+    }
+    rets.append(data)
+
+with open('world_macro.json', 'w') as f:
+    f.write(json.dumps(rets))
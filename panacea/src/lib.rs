@@ -3,6 +3,7 @@ pub mod eod;
 pub mod risk;
 pub mod sim;
 pub mod stat;
+pub mod tax;
 
 use calcs::{max_dd_threshold_position, DDInput, DDResults};
 use risk::{risk_analysis, EodRawRiskInput};
@@ -10,7 +11,9 @@ use std::panic;
 use wasm_bindgen::prelude::*;
 
 use sim::{alator_backtest, AlatorResults, EodRawAlatorInput, EodRawAntevortaInput};
+use sim::{antevorta_goal, AntevortaGoalResults, EodRawAntevortaGoalInput};
 use sim::{antevorta_multiple, AntevortaResults};
+use tax::{uk_tax_calc, UKTaxCalcInput};
 
 extern crate console_error_panic_hook;
 
@@ -30,6 +33,14 @@ pub fn antevorta(js_input: JsValue) -> JsValue {
     serde_wasm_bindgen::to_value(&antevorta_res).unwrap()
 }
 
+#[wasm_bindgen]
+pub fn goal(js_input: JsValue) -> JsValue {
+    panic::set_hook(Box::new(console_error_panic_hook::hook));
+    let raw_input: EodRawAntevortaGoalInput = serde_wasm_bindgen::from_value(js_input).unwrap();
+    let goal_res: AntevortaGoalResults = antevorta_goal(raw_input).unwrap();
+    serde_wasm_bindgen::to_value(&goal_res).unwrap()
+}
+
 #[wasm_bindgen]
 pub fn drawdown(js_input: JsValue) -> JsValue {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
@@ -45,3 +56,11 @@ pub fn risk(js_input: JsValue) -> JsValue {
     let risk_result = risk_analysis(&input);
     serde_wasm_bindgen::to_value(&risk_result).unwrap()
 }
+
+#[wasm_bindgen]
+pub fn uk_tax(js_input: JsValue) -> JsValue {
+    panic::set_hook(Box::new(console_error_panic_hook::hook));
+    let input: UKTaxCalcInput = serde_wasm_bindgen::from_value(js_input).unwrap();
+    let breakdown = uk_tax_calc(&input);
+    serde_wasm_bindgen::to_value(&breakdown).unwrap()
+}
@@ -1,25 +1,30 @@
 use crate::eod::{EodRawCommon, EodRow};
-use crate::stat::build_sample_raw_daily;
-use alator::broker::Quote;
-use alator::clock::ClockBuilder;
+use crate::stat::build_sample_raw_daily_with_dividends;
+use alator::broker::{Dividend, Quote};
 use alator::exchange::DefaultExchangeBuilder;
+use alator::input::DividendsHashMap;
 use alator::sim::SimulatedBrokerBuilder;
 use alator::types::{DateTime, PortfolioAllocation};
 use antevorta::config::uk::UKSimConfig;
-use antevorta::input::build_hashmapsource_with_quotes_with_inflation;
+use antevorta::input::{
+    build_hashmapsource_for_clock, build_hashmapsource_with_quotes_with_inflation_with_params,
+    HashMapSourceSim, HousePriceParams, ShortRateParams, StressScenario, VecSourceSim,
+};
 use antevorta::output::UKSimulationOutput;
-use antevorta::schedule::Schedule;
+use antevorta::schedule::{build_clock, Schedule, Step};
 use antevorta::strat::StaticInvestmentStrategy;
+use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::rc::Rc;
 
-pub fn build_price_input_from_raw_close_prices(
+fn build_input_from_raw_rows(
     close: &Vec<Vec<EodRow>>,
     assets: &Vec<String>,
     date_intersection: &Vec<String>,
+    field: fn(&EodRow) -> f64,
 ) -> AntevortaPriceInput {
     let mut res = AntevortaPriceInput::new();
     for (ticker, prices) in assets.iter().zip(close) {
@@ -28,9 +33,9 @@ pub fn build_price_input_from_raw_close_prices(
                 let ticker_str = ticker.to_string();
                 if res.contains_key(&ticker_str) {
                     let curr = res.get_mut(&ticker_str).unwrap();
-                    curr.push(row.adjusted_close);
+                    curr.push(field(row));
                 } else {
-                    let prices = vec![row.adjusted_close];
+                    let prices = vec![field(row)];
                     res.insert(ticker_str, prices);
                 }
             }
@@ -39,6 +44,54 @@ pub fn build_price_input_from_raw_close_prices(
     res
 }
 
+//Total return prices
+pub fn build_price_input_from_raw_close_prices(
+    close: &Vec<Vec<EodRow>>,
+    assets: &Vec<String>,
+    date_intersection: &Vec<String>,
+) -> AntevortaPriceInput {
+    build_input_from_raw_rows(close, assets, date_intersection, |row| row.adjusted_close)
+}
+
+//Price return prices, the difference with the total return is paid out as dividends
+pub fn build_unadjusted_price_input_from_raw_close_prices(
+    close: &Vec<Vec<EodRow>>,
+    assets: &Vec<String>,
+    date_intersection: &Vec<String>,
+) -> AntevortaPriceInput {
+    build_input_from_raw_rows(close, assets, date_intersection, |row| row.close)
+}
+
+//Dividends on an explicit yield are paid quarterly
+const DIVIDEND_PAYMENT_DAYS: usize = 91;
+
+//Splits a total return price series into a price return series and dividends per share with the
+//annual yield given. Yield accrues daily on the price and is paid out every quarter.
+pub fn dividends_from_yield(total: &[f64], annual_yield: &f64) -> (Vec<f64>, Vec<f64>) {
+    let daily_yield = annual_yield / 365.0;
+    let mut prices = Vec::with_capacity(total.len());
+    let mut dividends = Vec::with_capacity(total.len());
+    let mut accrued = 0.0;
+    for (pos, total_price) in total.iter().enumerate() {
+        if pos == 0 {
+            prices.push(*total_price);
+            dividends.push(0.0);
+            continue;
+        }
+        let last_price = prices[pos - 1];
+        let total_ret = (total_price / total[pos - 1]) - 1.0;
+        accrued += daily_yield * last_price;
+        prices.push(last_price * (1.0 + total_ret - daily_yield));
+        if pos % DIVIDEND_PAYMENT_DAYS == 0 {
+            dividends.push(accrued);
+            accrued = 0.0;
+        } else {
+            dividends.push(0.0);
+        }
+    }
+    (prices, dividends)
+}
+
 pub fn build_dates_from_raw_close_prices(close: &Vec<Vec<EodRow>>) -> (Vec<String>, Vec<i64>) {
     let dates = EodRawCommon::dates_intersect(close);
     let mut epoch_dates = EodRawCommon::convert_dates_to_epoch(&dates);
@@ -57,6 +110,28 @@ impl fmt::Display for AntevortaInsufficientDataError {
 
 impl Error for AntevortaInsufficientDataError {}
 
+#[derive(Clone, Copy, Debug)]
+pub struct AntevortaDateOfDeathError;
+
+impl fmt::Display for AntevortaDateOfDeathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Date Of Death Before Start Date Error")
+    }
+}
+
+impl Error for AntevortaDateOfDeathError {}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AntevortaNoRunsError;
+
+impl fmt::Display for AntevortaNoRunsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No Runs Error")
+    }
+}
+
+impl Error for AntevortaNoRunsError {}
+
 pub type AntevortaPriceInput = HashMap<String, Vec<f64>>;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -71,43 +146,143 @@ pub struct EodRawAntevortaInput {
     pub inflation_mu: f64,
     pub inflation_var: f64,
     pub start_date: i64,
+    //Rates are zero if this isn't given
+    pub short_rate: Option<ShortRateParams>,
+    //House prices are flat if this isn't given
+    pub house_prices: Option<HousePriceParams>,
+    //Annual yield by asset. Assets not given here pay the difference between close and
+    //adjusted_close as dividends.
+    pub dividend_yield: Option<HashMap<String, f64>>,
+    //Historical episodes laid over every run, each scenario is run alongside the Monte Carlo runs
+    pub scenarios: Option<Vec<StressScenario>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AntevortaScenarioResults {
+    pub name: String,
+    //Paired with the Monte Carlo results, each run has the same resampled path and date of death
+    //as the run in the same position with the episode laid over it
+    pub results: Vec<UKSimulationOutput>,
+    pub probability_of_ruin: f64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AntevortaResults {
     pub results: Vec<UKSimulationOutput>,
+    //Runs end at death if a date of death is given or sampled, so this is the probability of
+    //running out of money before death. Otherwise it is before the end of sim_length.
+    pub probability_of_ruin: f64,
     pub sample_start: i64,
     pub sample_end: i64,
+    pub scenarios: Vec<AntevortaScenarioResults>,
 }
 
-pub fn antevorta_multiple(input: EodRawAntevortaInput) -> Result<AntevortaResults, Box<dyn Error>> {
-    //Intersection of overlapping dates
-    let (string_dates, epoch_dates) = build_dates_from_raw_close_prices(&input.close);
-    let close = build_price_input_from_raw_close_prices(&input.close, &input.assets, &string_dates);
+pub(super) fn probability_of_ruin(
+    results: &[UKSimulationOutput],
+) -> Result<f64, AntevortaNoRunsError> {
+    if results.is_empty() {
+        return Err(AntevortaNoRunsError);
+    }
+    let ruined = results.iter().filter(|r| r.ruin_date.is_some()).count();
+    Ok(ruined as f64 / results.len() as f64)
+}
 
-    let mut results = Vec::new();
-    for _i in 0..input.runs {
-        let sim_length_in_days = (input.sim_length * 365) as i64;
+//Prices that every run is resampled from
+pub(super) struct AntevortaPrices {
+    epoch_dates: Vec<i64>,
+    close: AntevortaPriceInput,
+    unadjusted: AntevortaPriceInput,
+    dividend_yield: HashMap<String, f64>,
+}
+
+impl AntevortaPrices {
+    pub(super) fn new(input: &EodRawAntevortaInput) -> Self {
+        //Intersection of overlapping dates
+        let (string_dates, epoch_dates) = build_dates_from_raw_close_prices(&input.close);
+        let close =
+            build_price_input_from_raw_close_prices(&input.close, &input.assets, &string_dates);
+        let mut unadjusted = build_unadjusted_price_input_from_raw_close_prices(
+            &input.close,
+            &input.assets,
+            &string_dates,
+        );
+        //Assets with an explicit yield are split after resampling so no dividend is derived for
+        //them
+        let dividend_yield = input.dividend_yield.clone().unwrap_or_default();
+        for asset in dividend_yield.keys() {
+            if let Some(total) = close.get(asset) {
+                unadjusted.insert(asset.clone(), total.clone());
+            }
+        }
+        Self {
+            epoch_dates,
+            close,
+            unadjusted,
+            dividend_yield,
+        }
+    }
+
+    pub(super) fn sample_path(
+        &self,
+        input: &EodRawAntevortaInput,
+        config: &UKSimConfig,
+        rng: &mut impl Rng,
+    ) -> Result<AntevortaPath, Box<dyn Error>> {
+        //Each run lasts until the date of death, if there is one, rounded up to a whole year as the
+        //price data is resampled in years
+        let date_of_death = config.sample_date_of_death(&input.start_date, rng);
+        let sim_length = match date_of_death {
+            Some(date) if date < input.start_date => {
+                return Err(Box::new(AntevortaDateOfDeathError));
+            }
+            Some(date) => {
+                let seconds_in_year = 365 * 86_400;
+                (date - input.start_date) / seconds_in_year + 1
+            }
+            None => input.sim_length,
+        };
+        let sim_length_in_days = (sim_length * 365) as i64;
 
         //Start date of the simulation is provided by the user, date shouldn't be overlapping with
-        //sample dates in data
-        let clock = ClockBuilder::with_length_in_days(input.start_date, sim_length_in_days - 1)
-            .with_frequency(&alator::types::Frequency::Daily)
-            .build();
+        //sample dates in data. Data is always built daily and then moved onto the clock for a run.
+        let clock = build_clock(input.start_date, sim_length_in_days - 1, &Step::Daily);
 
         let mut raw_data: HashMap<DateTime, Vec<Quote>> = HashMap::new();
-        if let Some(resampled_close) = build_sample_raw_daily(sim_length_in_days, close.clone()) {
+        let mut raw_dividends: DividendsHashMap = HashMap::new();
+        if let Some((mut resampled_close, mut resampled_dividends)) =
+            build_sample_raw_daily_with_dividends(
+                sim_length_in_days,
+                self.unadjusted.clone(),
+                self.close.clone(),
+            )
+        {
+            for (asset, annual_yield) in &self.dividend_yield {
+                if let Some(total) = resampled_close.get(asset) {
+                    let (prices, dividends) = dividends_from_yield(total, annual_yield);
+                    resampled_close.insert(asset.clone(), prices);
+                    resampled_dividends.insert(asset.clone(), dividends);
+                }
+            }
             //The simulator builds its own dates to use an input
-            //This will iterate over the prices within the resampled_close, therefore the vectors have to
-            //be equal to sim_length_days
+            //This will iterate over the prices within the resampled_close, therefore the vectors
+            //have to be equal to sim_length_days
             let mut pos = 0;
             for date in clock.borrow().peek() {
                 let mut quotes: Vec<Quote> = Vec::new();
+                let mut dividends: Vec<Dividend> = Vec::new();
                 for asset in &input.assets {
                     let asset_closes = resampled_close.get(asset).unwrap();
                     let pos_close = asset_closes[pos as usize];
                     let q = Quote::new(pos_close, pos_close, date.clone(), asset);
                     quotes.push(q);
+
+                    let pos_dividend = resampled_dividends.get(asset).unwrap()[pos as usize];
+                    if pos_dividend > 0.0 {
+                        dividends.push(Dividend::new(pos_dividend, asset, date.clone()));
+                    }
+                }
+                if !dividends.is_empty() {
+                    raw_dividends.insert(date.clone(), dividends);
                 }
                 raw_data.insert(date.into(), quotes);
                 pos += 1;
@@ -116,16 +291,54 @@ pub fn antevorta_multiple(input: EodRawAntevortaInput) -> Result<AntevortaResult
             return Err(Box::new(AntevortaInsufficientDataError));
         }
 
-        let src = build_hashmapsource_with_quotes_with_inflation(
+        let source = build_hashmapsource_with_quotes_with_inflation_with_params(
             Rc::clone(&clock),
             raw_data,
+            raw_dividends,
             input.inflation_mu,
             input.inflation_var,
+            input.short_rate.as_ref(),
+            input.house_prices.as_ref(),
         );
+        Ok(AntevortaPath {
+            date_of_death,
+            sim_length_in_days,
+            source,
+        })
+    }
+}
+
+//Data resampled for one run. The source is daily and its clock is never moved, so the same path can
+//be run any number of times with a different config or scenario.
+pub(super) struct AntevortaPath {
+    date_of_death: Option<i64>,
+    sim_length_in_days: i64,
+    source: HashMapSourceSim,
+}
+
+impl AntevortaPath {
+    //Each run moves the data onto a new clock with the step of the config
+    pub(super) fn run(
+        &self,
+        input: &EodRawAntevortaInput,
+        config: &UKSimConfig,
+        scenario: Option<&StressScenario>,
+    ) -> UKSimulationOutput {
+        let step = config.step();
+        let clock = build_clock(input.start_date, self.sim_length_in_days - 1, &step);
+        let src = match scenario {
+            Some(scenario) => build_hashmapsource_for_clock(
+                &scenario.apply(&self.source),
+                Rc::clone(&clock),
+            ),
+            None => build_hashmapsource_for_clock(&self.source, Rc::clone(&clock)),
+        };
+        //Queries are made on every tick, so the data is indexed by position in the clock
+        let src = VecSourceSim::from(&src);
 
         let mut weights = PortfolioAllocation::new();
-        for symbol in input.weights.keys() {
-            weights.insert(symbol.clone(), *input.weights.get(&symbol.clone()).unwrap());
+        for (symbol, weight) in &input.weights {
+            weights.insert(symbol.clone(), *weight);
         }
 
         let exchange = DefaultExchangeBuilder::new()
@@ -138,23 +351,53 @@ pub fn antevorta_multiple(input: EodRawAntevortaInput) -> Result<AntevortaResult
             .with_data(src.clone())
             .build();
 
-        let strat =
-            StaticInvestmentStrategy::new(brkr, Schedule::EveryFriday, weights, Rc::clone(&clock));
-        let config = UKSimConfig::parse(&input.config.clone()).unwrap();
-
+        let schedule = Schedule::EveryFriday.for_step(&step);
+        let strat = StaticInvestmentStrategy::new(brkr, schedule, weights, Rc::clone(&clock));
         let mut sim = config.create(Rc::clone(&clock), strat, src);
+        sim.date_of_death = self.date_of_death.map(DateTime::from);
 
         while clock.borrow().has_next() {
             sim.update();
             clock.borrow_mut().tick();
         }
-        results.push(UKSimulationOutput::get_output(&sim));
+        UKSimulationOutput::get_output(&sim)
     }
+}
+
+pub fn antevorta_multiple(input: EodRawAntevortaInput) -> Result<AntevortaResults, Box<dyn Error>> {
+    if input.runs < 1 {
+        return Err(Box::new(AntevortaNoRunsError));
+    }
+    let prices = AntevortaPrices::new(&input);
+    let config = UKSimConfig::parse(&input.config.clone()).unwrap();
+    let mut rng = thread_rng();
+    let scenarios = input.scenarios.clone().unwrap_or_default();
+
+    let mut results = Vec::new();
+    let mut scenario_results: Vec<Vec<UKSimulationOutput>> = vec![Vec::new(); scenarios.len()];
+    for _i in 0..input.runs {
+        let path = prices.sample_path(&input, &config, &mut rng)?;
+        results.push(path.run(&input, &config, None));
+        for (pos, scenario) in scenarios.iter().enumerate() {
+            scenario_results[pos].push(path.run(&input, &config, Some(scenario)));
+        }
+    }
+    let mut scenarios_out = Vec::new();
+    for (scenario, results) in scenarios.iter().zip(scenario_results) {
+        scenarios_out.push(AntevortaScenarioResults {
+            name: scenario.name.clone(),
+            probability_of_ruin: probability_of_ruin(&results)?,
+            results,
+        });
+    }
+    let probability_of_ruin = probability_of_ruin(&results)?;
     Ok(
         AntevortaResults { 
             results,
-            sample_start: epoch_dates.first().unwrap().clone(),
-            sample_end: epoch_dates.last().unwrap().clone(),
+            probability_of_ruin,
+            sample_start: prices.epoch_dates.first().unwrap().clone(),
+            sample_end: prices.epoch_dates.last().unwrap().clone(),
+            scenarios: scenarios_out,
         }
     )
 }
@@ -169,10 +412,16 @@ mod tests {
         eod::EodRow,
         sim::antevorta::{
             build_dates_from_raw_close_prices, build_price_input_from_raw_close_prices,
+            dividends_from_yield,
         },
     };
 
-    use super::{antevorta_multiple, EodRawAntevortaInput};
+    use antevorta::input::StressScenario;
+
+    use super::{
+        antevorta_multiple, probability_of_ruin, AntevortaDateOfDeathError, AntevortaNoRunsError,
+        EodRawAntevortaInput,
+    };
 
     #[derive(Deserialize, Serialize)]
     struct TestEodInput {
@@ -231,6 +480,10 @@ mod tests {
             inflation_mu: 0.02,
             inflation_var: 0.001,
             start_date: 1680283254,
+            short_rate: None,
+            house_prices: None,
+            dividend_yield: None,
+            scenarios: None,
         }
     }
 
@@ -296,6 +549,10 @@ mod tests {
             inflation_mu: 0.02,
             inflation_var: 0.01,
             start_date: 1680283254,
+            short_rate: None,
+            house_prices: None,
+            dividend_yield: None,
+            scenarios: None,
         };
 
         //This function is called at the start of simulation run to find date intersection
@@ -321,4 +578,56 @@ mod tests {
         //This is larger dataset, this tests that we load without errors
         let _res = antevorta_multiple(antevorta.into()).unwrap();
     }
+
+    #[test]
+    pub fn test_that_date_of_death_before_start_is_an_error() {
+        let mut antevorta = setup();
+        let mut config: serde_json::Value = serde_json::from_str(&antevorta.config).unwrap();
+        config["estate"] = serde_json::json!({ "date_of_death": antevorta.start_date - 86_400 });
+        antevorta.config = config.to_string();
+
+        let err = antevorta_multiple(antevorta).unwrap_err();
+        assert!(err.is::<AntevortaDateOfDeathError>());
+    }
+
+    #[test]
+    pub fn test_that_no_runs_is_an_error() {
+        let mut antevorta = setup();
+        antevorta.runs = 0;
+
+        let err = antevorta_multiple(antevorta).unwrap_err();
+        assert!(err.is::<AntevortaNoRunsError>());
+        assert!(probability_of_ruin(&[]).is_err());
+    }
+
+    #[test]
+    pub fn test_antevorta_runs_scenarios_with_each_run() {
+        let mut antevorta = setup();
+        let mut returns = HashMap::new();
+        returns.insert("100".to_string(), vec![-0.2, -0.1, 0.05, -0.15]);
+        antevorta.scenarios = Some(vec![StressScenario {
+            name: "Crash".to_string(),
+            offset_days: 100,
+            periods_per_year: 4,
+            returns,
+            inflation: Some(vec![0.03; 4]),
+        }]);
+
+        let res = antevorta_multiple(antevorta).unwrap();
+        assert_eq!(res.scenarios.len(), 1);
+        assert_eq!(res.scenarios[0].name, "Crash");
+        assert_eq!(res.scenarios[0].results.len(), res.results.len());
+    }
+
+    #[test]
+    pub fn test_that_yield_splits_total_return() {
+        //Total return of 1% a day, yield equal to total return so price is flat
+        let total: Vec<f64> = (0..365).map(|i| 100.0 * 1.01_f64.powi(i)).collect();
+        let (prices, dividends) = dividends_from_yield(&total, &3.65);
+
+        assert!(prices.iter().all(|p| (p - 100.0).abs() < 1e-9));
+        //Paid quarterly, each payment is 91 days of 1% on a flat price
+        assert_eq!(dividends.iter().filter(|d| **d > 0.0).count(), 4);
+        assert!((dividends[91] - 91.0).abs() < 1e-9);
+    }
 }
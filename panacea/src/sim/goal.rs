@@ -0,0 +1,502 @@
+use antevorta::config::uk::UKSimConfig;
+use rand::thread_rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+
+use super::antevorta::{probability_of_ruin, AntevortaPath, AntevortaPrices, EodRawAntevortaInput};
+
+//Two-sided 95% interval
+const CONFIDENCE_Z: f64 = 1.96;
+
+//Parameter of the config that is solved for, flows are given by their position in the config
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum GoalParameter {
+    FlowValue(usize),
+    //Epoch, last date that the flow is paid so for employment this is the retirement date
+    FlowEndDate(usize),
+    ContributionPct,
+    StartingCash,
+}
+
+impl GoalParameter {
+    fn config(&self, config: &str, value: f64) -> Result<UKSimConfig, Box<dyn Error>> {
+        let mut json: Value = serde_json::from_str(config)?;
+        match self {
+            GoalParameter::FlowValue(pos) => Self::flow(&mut json, *pos)?["value"] = value.into(),
+            GoalParameter::FlowEndDate(pos) => {
+                Self::flow(&mut json, *pos)?["end_date"] = (value.round() as i64).into()
+            }
+            GoalParameter::ContributionPct => json["contribution_pct"] = value.into(),
+            GoalParameter::StartingCash => json["starting_cash"] = value.into(),
+        }
+        Ok(serde_json::from_value(json)?)
+    }
+
+    fn flow(json: &mut Value, pos: usize) -> Result<&mut Value, AntevortaGoalParameterError> {
+        json.get_mut("flows")
+            .and_then(|flows| flows.get_mut(pos))
+            .ok_or(AntevortaGoalParameterError)
+    }
+}
+
+//Parameter refers to a flow that isn't in the config
+#[derive(Clone, Copy, Debug)]
+pub struct AntevortaGoalParameterError;
+
+impl fmt::Display for AntevortaGoalParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Goal Parameter Error")
+    }
+}
+
+impl Error for AntevortaGoalParameterError {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EodRawAntevortaGoalInput {
+    //Every value is run on the same paths, one for each run, and scenarios are ignored
+    pub sim: EodRawAntevortaInput,
+    pub parameter: GoalParameter,
+    //Range searched, success has to be above the target at one end and below it at the other
+    pub lower: f64,
+    pub upper: f64,
+    //Probability of not running out of money
+    pub target_success: f64,
+    //Search stops when the bracket is narrower than this, defaults to a thousandth of the range
+    pub tolerance: Option<f64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AntevortaGoalResults {
+    //Value closest to the failing end of the range that meets the target
+    pub value: f64,
+    pub success: f64,
+    //Values that meet the edges of the confidence interval of the estimated success at the target,
+    //the solved value can't be told apart from values in this range with the number of runs given
+    pub confidence_lower: f64,
+    pub confidence_upper: f64,
+    //Number of values that were run
+    pub iterations: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AntevortaGoalNotBracketedError;
+
+impl fmt::Display for AntevortaGoalNotBracketedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Goal Not Bracketed Error")
+    }
+}
+
+impl Error for AntevortaGoalNotBracketedError {}
+
+//Every value is run on the same paths, so differences in success come from the parameter rather
+//than from sampling and success moves in one direction as the parameter moves
+struct GoalSolver<'a> {
+    input: &'a EodRawAntevortaGoalInput,
+    paths: Vec<AntevortaPath>,
+    tested: Vec<(f64, f64)>,
+}
+
+impl<'a> GoalSolver<'a> {
+    //Samples one path for each run
+    fn new(input: &'a EodRawAntevortaGoalInput) -> Result<Self, Box<dyn Error>> {
+        let prices = AntevortaPrices::new(&input.sim);
+        let config = UKSimConfig::parse(&input.sim.config)?;
+        let mut rng = thread_rng();
+        let mut paths = Vec::new();
+        for _i in 0..input.sim.runs {
+            paths.push(prices.sample_path(&input.sim, &config, &mut rng)?);
+        }
+        Ok(Self {
+            input,
+            paths,
+            tested: Vec::new(),
+        })
+    }
+
+    fn success(&mut self, value: f64) -> Result<f64, Box<dyn Error>> {
+        if let Some((_, success)) = self.tested.iter().find(|(tested, _)| *tested == value) {
+            return Ok(*success);
+        }
+        let config = self.input.parameter.config(&self.input.sim.config, value)?;
+        let results: Vec<_> = self
+            .paths
+            .iter()
+            .map(|path| path.run(&self.input.sim, &config, None))
+            .collect();
+        let success = 1.0 - probability_of_ruin(&results)?;
+        self.tested.push((value, success));
+        Ok(success)
+    }
+
+    //Bisects from the end of the range with higher success towards the other end. Returns the
+    //failing end if the whole range meets the target and None if none of it does.
+    fn bisect(&mut self, good: f64, bad: f64, target: f64) -> Result<Option<f64>, Box<dyn Error>> {
+        if self.success(bad)? >= target {
+            return Ok(Some(bad));
+        }
+        if self.success(good)? < target {
+            return Ok(None);
+        }
+        let tolerance = self
+            .input
+            .tolerance
+            .unwrap_or((self.input.upper - self.input.lower).abs() / 1000.0);
+        let (mut good, mut bad) = (good, bad);
+        while (good - bad).abs() > tolerance {
+            let mid = (good + bad) / 2.0;
+            if self.success(mid)? >= target {
+                good = mid;
+            } else {
+                bad = mid;
+            }
+        }
+        Ok(Some(good))
+    }
+}
+
+pub fn antevorta_goal(
+    input: EodRawAntevortaGoalInput,
+) -> Result<AntevortaGoalResults, Box<dyn Error>> {
+    let mut solver = GoalSolver::new(&input)?;
+    let runs = solver.paths.len() as f64;
+
+    let target = input.target_success;
+    let lower_success = solver.success(input.lower)?;
+    let upper_success = solver.success(input.upper)?;
+    let (good, bad) = match (lower_success >= target, upper_success >= target) {
+        (true, false) => (input.lower, input.upper),
+        (false, true) => (input.upper, input.lower),
+        _ => return Err(Box::new(AntevortaGoalNotBracketedError)),
+    };
+    let value = solver.bisect(good, bad, target)?.unwrap();
+    let success = solver.success(value)?;
+
+    //Binomial standard error of success at the target. Meeting a lower target is always possible
+    //as the target is bracketed, a higher target that can't be met is bounded by the range.
+    let error = (target * (1.0 - target) / runs).sqrt() * CONFIDENCE_Z;
+    let less_certain = solver.bisect(good, bad, target - error)?.unwrap();
+    let more_certain = solver.bisect(good, bad, target + error)?.unwrap_or(good);
+
+    Ok(AntevortaGoalResults {
+        value,
+        success,
+        confidence_lower: less_certain.min(more_certain),
+        confidence_upper: less_certain.max(more_certain),
+        iterations: solver.tested.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use time::macros::{date, format_description};
+    use time::Duration;
+
+    use super::{
+        antevorta_goal, AntevortaGoalNotBracketedError, AntevortaGoalParameterError,
+        EodRawAntevortaGoalInput, GoalParameter, GoalSolver,
+    };
+    use crate::eod::EodRow;
+    use crate::sim::EodRawAntevortaInput;
+
+    #[derive(Deserialize, Serialize)]
+    struct TestEodInput {
+        data: Vec<EodRow>,
+    }
+
+    //2023-03-31, runs for two years
+    const START_DATE: i64 = 1680283254;
+    const TARGET: f64 = 0.8;
+
+    fn config(flows: &str, starting_cash: f64, contribution_pct: f64) -> String {
+        format!(
+            r#"{{
+                "flows": [{flows}],
+                "stacks": [
+                    {{
+                        "stack_type":"Gia",
+                        "value":0
+                    }},
+                    {{
+                        "stack_type":"Isa",
+                        "value":0
+                    }},
+                    {{
+                        "stack_type":"Sipp",
+                        "value":0
+                    }}
+                ],
+                "nic":"A",
+                "contribution_pct":{contribution_pct},
+                "emergency_cash_min":1000,
+                "starting_cash":{starting_cash},
+                "lifetime_pension_contributions":0
+            }}"#
+        )
+    }
+
+    fn flow(flow_type: &str, value: f64, end_date: Option<i64>) -> String {
+        let end_date = end_date.map_or(String::new(), |date| format!(r#","end_date":{date}"#));
+        format!(
+            r#"{{
+                "schedule": {{
+                    "schedule_type":"EndOfMonth"
+                }},
+                "value":{value},
+                "flow_type":"{flow_type}"{end_date}
+            }}"#
+        )
+    }
+
+    //Prices and inflation are flat so every path is the same and success is either zero or one
+    fn flat_input(
+        config: String,
+        parameter: GoalParameter,
+        lower: f64,
+        upper: f64,
+        tolerance: f64,
+    ) -> EodRawAntevortaGoalInput {
+        let format = format_description!("[year]-[month]-[day]");
+        let data = (0..800)
+            .map(|day| EodRow {
+                date: (date!(2000 - 01 - 01) + Duration::days(day)).format(&format).unwrap(),
+                open: 100.0,
+                high: 100.0,
+                low: 100.0,
+                close: 100.0,
+                adjusted_close: 100.0,
+                volume: 100.0,
+            })
+            .collect();
+        let mut weights = HashMap::new();
+        weights.insert("100".to_string(), 0.5);
+
+        EodRawAntevortaGoalInput {
+            sim: EodRawAntevortaInput {
+                close: vec![data],
+                assets: vec!["100".to_string()],
+                config,
+                runs: 2,
+                sim_length: 2,
+                weights,
+                inflation_mu: 0.0,
+                inflation_var: 0.0,
+                start_date: START_DATE,
+                short_rate: None,
+                house_prices: None,
+                dividend_yield: None,
+                scenarios: None,
+            },
+            parameter,
+            lower,
+            upper,
+            target_success: TARGET,
+            tolerance: Some(tolerance),
+        }
+    }
+
+    //Solved value meets the target and moving one tolerance step towards the failing end of the
+    //range doesn't
+    fn solve(input: EodRawAntevortaGoalInput) -> f64 {
+        let res = antevorta_goal(input.clone()).unwrap();
+        assert!(res.success >= TARGET);
+
+        let mut solver = GoalSolver::new(&input).unwrap();
+        let tolerance = input.tolerance.unwrap();
+        let beyond = if solver.success(input.lower).unwrap() < TARGET {
+            res.value - tolerance
+        } else {
+            res.value + tolerance
+        };
+        assert!(solver.success(res.value).unwrap() >= TARGET);
+        assert!(solver.success(beyond).unwrap() < TARGET);
+        res.value
+    }
+
+    #[test]
+    pub fn test_that_goal_solves_for_expense() {
+        let text = std::fs::read_to_string("./data/mcd.json").unwrap();
+        let json = serde_json::from_str::<TestEodInput>(&text).unwrap();
+        let config = r#"
+            {
+                "flows": [
+                    {
+                        "person":0,
+                        "schedule": {
+                            "schedule_type":"EndOfMonth"
+                        },
+                        "value":4000,
+                        "flow_type":"Employment"
+                    },
+                    {
+                        "schedule": {
+                            "schedule_type":"EndOfMonth"
+                        },
+                        "value":1000,
+                        "flow_type":"Expense"
+                    }
+                ],
+                "stacks": [
+                    {
+                        "stack_type":"Gia",
+                        "value":0
+                    },
+                    {
+                        "stack_type":"Isa",
+                        "value":0
+                    },
+                    {
+                        "stack_type":"Sipp",
+                        "value":0
+                    }
+                ],
+                "nic":"A",
+                "contribution_pct":0.05,
+                "emergency_cash_min":1000,
+                "starting_cash":5000,
+                "lifetime_pension_contributions":0
+            }
+        "#;
+        let mut weights = HashMap::new();
+        weights.insert("100".to_string(), 0.5);
+
+        let input = EodRawAntevortaGoalInput {
+            sim: EodRawAntevortaInput {
+                close: vec![json.data],
+                assets: vec!["100".to_string()],
+                config: config.to_string(),
+                runs: 5,
+                sim_length: 2,
+                weights,
+                inflation_mu: 0.02,
+                inflation_var: 0.001,
+                start_date: 1680283254,
+                short_rate: None,
+                house_prices: None,
+                dividend_yield: None,
+                scenarios: None,
+            },
+            parameter: GoalParameter::FlowValue(1),
+            lower: 0.0,
+            upper: 20000.0,
+            target_success: 0.8,
+            tolerance: Some(10.0),
+        };
+
+        let res = antevorta_goal(input).unwrap();
+        assert!(res.success >= 0.8);
+        assert!(res.value > 0.0 && res.value < 20000.0);
+        assert!(res.confidence_lower <= res.value && res.value <= res.confidence_upper);
+        //Expenses above pay after tax run through starting cash well within two years
+        assert!(res.value < 4000.0);
+    }
+
+    #[test]
+    pub fn test_that_missing_flow_is_an_error() {
+        let config = r#"
+            {
+                "flows": [
+                    {
+                        "schedule": {
+                            "schedule_type":"EndOfMonth"
+                        },
+                        "value":1000,
+                        "flow_type":"Expense"
+                    }
+                ],
+                "nic":"A",
+                "contribution_pct":0.05,
+                "emergency_cash_min":1000,
+                "starting_cash":5000,
+                "lifetime_pension_contributions":0
+            }
+        "#;
+        assert!(GoalParameter::FlowValue(0).config(config, 500.0).is_ok());
+        let err = GoalParameter::FlowValue(1).config(config, 500.0).unwrap_err();
+        assert!(err.is::<AntevortaGoalParameterError>());
+
+        let no_flows = r#"
+            {
+                "nic":"A",
+                "contribution_pct":0.05,
+                "emergency_cash_min":1000,
+                "starting_cash":5000,
+                "lifetime_pension_contributions":0
+            }
+        "#;
+        let err = GoalParameter::FlowEndDate(0).config(no_flows, 0.0).unwrap_err();
+        assert!(err.is::<AntevortaGoalParameterError>());
+    }
+
+    #[test]
+    pub fn test_that_goal_solves_for_maximum_expense() {
+        let input = |starting_cash: f64| {
+            let flows = [flow("Employment", 4000.0, None), flow("Expense", 0.0, None)].join(",");
+            let config = config(&flows, starting_cash, 0.05);
+            flat_input(config, GoalParameter::FlowValue(1), 0.0, 20000.0, 10.0)
+        };
+        let value = solve(input(5000.0));
+        assert!(value > 0.0 && value < 20000.0);
+        //More savings support more spending
+        assert!(solve(input(50000.0)) > value);
+    }
+
+    #[test]
+    pub fn test_that_goal_solves_for_maximum_contribution_pct() {
+        let input = |expense: f64| {
+            let flows = [flow("Employment", 4000.0, None), flow("Expense", expense, None)];
+            let config = config(&flows.join(","), 5000.0, 0.0);
+            flat_input(config, GoalParameter::ContributionPct, 0.0, 1.0, 0.001)
+        };
+        let value = solve(input(2900.0));
+        assert!(value > 0.0 && value < 1.0);
+        //Lower spending leaves more of the salary to contribute
+        assert!(solve(input(2700.0)) > value);
+    }
+
+    #[test]
+    pub fn test_that_goal_solves_for_minimum_starting_cash() {
+        let input = |expense: f64| {
+            let config = config(&flow("Expense", expense, None), 0.0, 0.0);
+            flat_input(config, GoalParameter::StartingCash, 0.0, 100000.0, 10.0)
+        };
+        let value = solve(input(1000.0));
+        //Two years of spending with nothing earned or lost on the investments
+        assert!(value > 20000.0 && value < 26000.0);
+        //Higher spending needs more savings
+        assert!(solve(input(1500.0)) > value);
+    }
+
+    #[test]
+    pub fn test_that_goal_solves_for_earliest_retirement_date() {
+        let end = START_DATE + (2 * 365 * 86_400);
+        let input = |expense: f64| {
+            let flows = [
+                flow("Employment", 4000.0, Some(end)),
+                flow("Expense", expense, None),
+            ]
+            .join(",");
+            let config = config(&flows, 5000.0, 0.05);
+            let (lower, upper) = (START_DATE as f64, end as f64);
+            flat_input(config, GoalParameter::FlowEndDate(0), lower, upper, 86_400.0)
+        };
+        let value = solve(input(2000.0));
+        assert!(value > START_DATE as f64 && value < end as f64);
+        //Higher spending means working for longer
+        assert!(solve(input(2500.0)) > value);
+    }
+
+    #[test]
+    pub fn test_that_goal_not_bracketed_is_an_error() {
+        //Every expense in the range can be paid from the salary
+        let flows = [flow("Employment", 4000.0, None), flow("Expense", 0.0, None)].join(",");
+        let config = config(&flows, 5000.0, 0.05);
+        let input = flat_input(config, GoalParameter::FlowValue(1), 0.0, 100.0, 1.0);
+        let err = antevorta_goal(input).unwrap_err();
+        assert!(err.is::<AntevortaGoalNotBracketedError>());
+    }
+}
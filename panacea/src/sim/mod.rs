@@ -1,8 +1,13 @@
 mod alator;
 mod antevorta;
+mod goal;
 
 pub use self::alator::{alator_backtest, AlatorInput, AlatorResults, EodRawAlatorInput};
 pub use self::antevorta::{
-    antevorta_multiple, AntevortaInsufficientDataError, AntevortaPriceInput, AntevortaResults,
-    EodRawAntevortaInput,
+    antevorta_multiple, AntevortaDateOfDeathError, AntevortaInsufficientDataError,
+    AntevortaNoRunsError, AntevortaPriceInput, AntevortaResults, EodRawAntevortaInput,
+};
+pub use self::goal::{
+    antevorta_goal, AntevortaGoalNotBracketedError, AntevortaGoalParameterError,
+    AntevortaGoalResults, EodRawAntevortaGoalInput, GoalParameter,
 };
@@ -13,6 +13,26 @@ pub fn build_sample_raw_daily(
     build_sample_raw_inner(sim_length, raw_data, 365)
 }
 
+//Sample sim_length_yrs number of values from uniform distribution from 0 to length of data
+//- minimum_sample_length - 1
+//
+//Start from one so we have the extra space at the start to create a return for the full
+//price series
+fn sample_start_positions(
+    data_length: i64,
+    sample_count: i64,
+    minimum_sample_length: i64,
+) -> Vec<i64> {
+    let sim_length_dist = Uniform::new(1, data_length - minimum_sample_length + 1);
+    let mut rng = thread_rng();
+    let mut sample_start_positions: Vec<i64> = Vec::new();
+    for _date in 0..sample_count {
+        let sample_pos = sim_length_dist.sample(&mut rng);
+        sample_start_positions.push(sample_pos as i64);
+    }
+    sample_start_positions
+}
+
 fn build_sample_raw_inner(
     sim_length: i64,
     raw_data: HashMap<String, Vec<f64>>,
@@ -27,18 +47,8 @@ fn build_sample_raw_inner(
         if data_length <= minimum_sample_length {
             return None;
         }
-        //Sample sim_length_yrs number of values from uniform distribution from 0 to length of data
-        //- minimum_sample_length - 1
-        //
-        //Start from one so we have the extra space at the start to create a return for the full
-        //price series
-        let sim_length_dist = Uniform::new(1, data_length - minimum_sample_length + 1);
-        let mut rng = thread_rng();
-        let mut sample_start_positions: Vec<i64> = Vec::new();
-        for _date in 0..sample_count {
-            let sample_pos = sim_length_dist.sample(&mut rng);
-            sample_start_positions.push(sample_pos as i64);
-        }
+        let sample_start_positions =
+            sample_start_positions(data_length, sample_count, minimum_sample_length);
         //Take price sample from data, then convert into a return
         //This is just a flat vec because we are removing ordering from the price series
         let mut resampled_rets: Vec<f64> = Vec::new();
@@ -63,6 +73,87 @@ fn build_sample_raw_inner(
     Some(resampled_data)
 }
 
+//Differences between the total and price return larger than this that aren't splits are assumed
+//to be other corporate actions rather than dividends
+const MAX_DAILY_DIVIDEND_YIELD: f64 = 0.1;
+//Splits and reverse splits up to 10:1
+const MAX_SPLIT_TERM: u32 = 10;
+const SPLIT_TOLERANCE: f64 = 0.001;
+
+//The total return series is adjusted for splits but the price series isn't, so on the day of a
+//split the ratio of the two returns is a simple fraction
+fn is_split(total_ret: f64, price_ret: f64) -> bool {
+    let ratio = (1.0 + total_ret) / (1.0 + price_ret);
+    for n in 1..=MAX_SPLIT_TERM {
+        for m in 1..=MAX_SPLIT_TERM {
+            if n == m {
+                continue;
+            }
+            let fraction = n as f64 / m as f64;
+            if ((ratio / fraction) - 1.0).abs() < SPLIT_TOLERANCE {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+//Returns resampled prices and the dividend paid per share on each day. Price and total return
+//series are sampled from the same positions so that dividends stay with the price moves they were
+//paid alongside. The dividend is the difference between the total and price return on the last
+//price.
+pub fn build_sample_raw_daily_with_dividends(
+    sim_length: i64,
+    price: HashMap<String, Vec<f64>>,
+    total_return: HashMap<String, Vec<f64>>,
+) -> Option<(HashMap<String, Vec<f64>>, HashMap<String, Vec<f64>>)> {
+    build_sample_raw_with_dividends_inner(sim_length, price, total_return, 365)
+}
+
+fn build_sample_raw_with_dividends_inner(
+    sim_length: i64,
+    price: HashMap<String, Vec<f64>>,
+    total_return: HashMap<String, Vec<f64>>,
+    minimum_sample_length: i64,
+) -> Option<(HashMap<String, Vec<f64>>, HashMap<String, Vec<f64>>)> {
+    let mut resampled_data: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut resampled_dividends: HashMap<String, Vec<f64>> = HashMap::new();
+    let sample_count = sim_length / minimum_sample_length;
+
+    for (symbol, totals) in total_return.iter() {
+        let prices = price.get(symbol)?;
+        let data_length = totals.len() as i64;
+        if data_length <= minimum_sample_length || prices.len() != totals.len() {
+            return None;
+        }
+        let sample_start_positions =
+            sample_start_positions(data_length, sample_count, minimum_sample_length);
+
+        let mut resampled_prices: Vec<f64> = Vec::new();
+        let mut dividends: Vec<f64> = Vec::new();
+        let mut curr_price = 100.0;
+        for start_pos in &sample_start_positions {
+            let end_pos = *start_pos + minimum_sample_length;
+            for pos in *start_pos..end_pos {
+                let pos = pos as usize;
+                let total_ret = (totals[pos] / totals[pos - 1]) - 1.0;
+                let mut price_ret = (prices[pos] / prices[pos - 1]) - 1.0;
+                let mut income = total_ret - price_ret;
+                if is_split(total_ret, price_ret) || income.abs() > MAX_DAILY_DIVIDEND_YIELD {
+                    price_ret = total_ret;
+                    income = 0.0;
+                }
+                dividends.push(income.max(0.0) * curr_price);
+                curr_price *= 1.0 + price_ret;
+                resampled_prices.push(curr_price);
+            }
+        }
+        resampled_data.insert(symbol.clone(), resampled_prices);
+        resampled_dividends.insert(symbol.clone(), dividends);
+    }
+    Some((resampled_data, resampled_dividends))
+}
+
 #[cfg(test)]
 mod tests {
     use alator::clock::ClockBuilder;
@@ -71,7 +162,9 @@ mod tests {
     use rand_distr::Distribution;
     use std::collections::HashMap;
 
-    use super::{build_sample_raw_daily, build_sample_raw_inner};
+    use super::{
+        build_sample_raw_daily, build_sample_raw_inner, build_sample_raw_with_dividends_inner,
+    };
 
     #[test]
     fn test_sample_generator_with_insufficient_data() {
@@ -155,4 +248,64 @@ mod tests {
         let sum1: f64 = cum_return(res1.unwrap().get(&"ABC".to_string()).unwrap());
         assert!(sum != sum1);
     }
+
+    #[test]
+    fn test_that_dividends_are_difference_between_total_and_price_return() {
+        //Price is flat, total return is 1% every fifth day and there is a 2:1 split on day 12
+        let mut price: Vec<f64> = Vec::new();
+        let mut total: Vec<f64> = Vec::new();
+        let mut total_price = 100.0;
+        for i in 0..20 {
+            if i > 0 && i % 5 == 0 {
+                total_price *= 1.01;
+            }
+            price.push(if i >= 12 { 50.0 } else { 100.0 });
+            total.push(total_price);
+        }
+
+        let mut price_data: HashMap<String, Vec<f64>> = HashMap::new();
+        price_data.insert("ABC".to_string(), price);
+        let mut total_data: HashMap<String, Vec<f64>> = HashMap::new();
+        total_data.insert("ABC".to_string(), total);
+
+        let (prices, dividends) =
+            build_sample_raw_with_dividends_inner(19, price_data, total_data, 19).unwrap();
+        let prices = prices.get("ABC").unwrap();
+        let dividends = dividends.get("ABC").unwrap();
+        assert!(prices.len() == 19 && dividends.len() == 19);
+
+        //Only one sample is possible so the series is in order, the split is ignored
+        assert!(dividends.iter().filter(|d| **d > 0.0).count() == 3);
+        assert!((dividends[4] - 1.0).abs() < 1e-9);
+        assert!(prices.iter().all(|p| (p - 100.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_that_five_for_four_split_is_not_a_dividend() {
+        //Price is flat, total return is 1% every fifth day and there is a 5:4 split on day 12
+        let mut price: Vec<f64> = Vec::new();
+        let mut total: Vec<f64> = Vec::new();
+        let mut total_price = 100.0;
+        for i in 0..20 {
+            if i > 0 && i % 5 == 0 {
+                total_price *= 1.01;
+            }
+            price.push(if i >= 12 { 80.0 } else { 100.0 });
+            total.push(total_price);
+        }
+
+        let mut price_data: HashMap<String, Vec<f64>> = HashMap::new();
+        price_data.insert("ABC".to_string(), price);
+        let mut total_data: HashMap<String, Vec<f64>> = HashMap::new();
+        total_data.insert("ABC".to_string(), total);
+
+        //The split is within the old threshold so was paid as a 25% dividend
+        let (prices, dividends) =
+            build_sample_raw_with_dividends_inner(19, price_data, total_data, 19).unwrap();
+        let prices = prices.get("ABC").unwrap();
+        let dividends = dividends.get("ABC").unwrap();
+        assert!(dividends.iter().filter(|d| **d > 0.0).count() == 3);
+        assert!(dividends[11] == 0.0);
+        assert!(prices.iter().all(|p| (p - 100.0).abs() < 1e-9));
+    }
 }
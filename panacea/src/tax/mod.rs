@@ -0,0 +1,54 @@
+use antevorta::tax::uk::{Residency, TaxPeriod, UKTaxBreakdown, UKTaxInput};
+use serde::{Deserialize, Serialize};
+
+//Income for a full tax year. Employment income passed as non_paye_employment has income tax and NI
+//calculated, paye_employment only has income tax calculated and is offset by paye_tax_paid.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UKTaxCalcInput {
+    pub input: UKTaxInput,
+    pub tax_year: i32,
+    pub residency: Residency,
+}
+
+pub fn uk_tax_calc(input: &UKTaxCalcInput) -> UKTaxBreakdown {
+    TaxPeriod::calc_for_year(&input.input, input.tax_year, input.residency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{uk_tax_calc, UKTaxCalcInput};
+
+    fn calc(income: &str) -> f64 {
+        let json = format!(
+            r#"{{
+                "input": {{
+                    "non_paye_employment": 60000.0{income}
+                }},
+                "tax_year": 2025,
+                "residency": "RestOfUK"
+            }}"#
+        );
+        let input: UKTaxCalcInput = serde_json::from_str(&json).unwrap();
+        let breakdown = uk_tax_calc(&input);
+        breakdown.income_tax_basic + breakdown.income_tax_higher + breakdown.income_tax_additional
+    }
+
+    #[test]
+    fn test_that_savings_income_is_taxed() {
+        //Higher rate taxpayer with a 500 personal savings allowance
+        let tax = calc(r#", "savings": 10000.0"#) - calc("");
+        assert!((tax - 9_500.0 * 0.4).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_that_rental_income_is_taxed() {
+        let tax = calc(r#", "rental": 10000.0"#) - calc("");
+        assert!((tax - 9_000.0 * 0.4).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_that_self_employment_income_is_taxed() {
+        let tax = calc(r#", "self_employment": 10000.0"#) - calc("");
+        assert!((tax - 9_000.0 * 0.4).abs() < 0.01);
+    }
+}